@@ -1,3 +1,5 @@
+use crate::{Comp, Dest, Jump};
+
 pub struct SimpleAssembler;
 impl SimpleAssembler {
     pub fn a_instruction(value: usize) -> Result<[char; 16], String> {
@@ -14,63 +16,21 @@ impl SimpleAssembler {
         Ok(instruction)
     }
     pub fn dest(field: &str) -> Result<[char; 3], String> {
-        match field {
-            "null" => Ok(['0'; 3]),
-            "M" => Ok(['0', '0', '1']),
-            "D" => Ok(['0', '1', '0']),
-            "MD" => Ok(['0', '1', '1']),
-            "A" => Ok(['1', '0', '0']),
-            "AM" => Ok(['1', '0', '1']),
-            "AD" => Ok(['1', '1', '0']),
-            "AMD" => Ok(['1'; 3]),
-            _ => Err(String::from("Invalid dest field")),
-        }
+        Dest::parse(field).map(|dest| dest.bits())
     }
     pub fn jump(field: &str) -> Result<[char; 3], String> {
-        match field {
-            "null" => Ok(['0'; 3]),
-            "JGT" => Ok(['0', '0', '1']),
-            "JEQ" => Ok(['0', '1', '0']),
-            "JGE" => Ok(['0', '1', '1']),
-            "JLT" => Ok(['1', '0', '0']),
-            "JNE" => Ok(['1', '0', '1']),
-            "JLE" => Ok(['1', '1', '0']),
-            "JMP" => Ok(['1'; 3]),
-            _ => Err(String::from("Invalid jump field")),
-        }
+        Jump::parse(field).map(|jump| jump.bits())
     }
     pub fn comp(field: &str) -> Result<[char; 7], String> {
-        match field {
-            "0" => Ok(['0', '1', '0', '1', '0', '1', '0']),
-            "1" => Ok(['0', '1', '1', '1', '1', '1', '1']),
-            "-1" => Ok(['0', '1', '1', '1', '0', '1', '0']),
-            "D" => Ok(['0', '0', '0', '1', '1', '0', '0']),
-            "A" => Ok(['0', '1', '1', '0', '0', '0', '0']),
-            "M" => Ok(['1', '1', '1', '0', '0', '0', '0']),
-            "!D" => Ok(['0', '0', '0', '1', '1', '0', '1']),
-            "!A" => Ok(['0', '1', '1', '0', '0', '0', '1']),
-            "!M" => Ok(['1', '1', '1', '0', '0', '0', '1']),
-            "-D" => Ok(['0', '0', '0', '1', '1', '1', '1']),
-            "-A" => Ok(['0', '1', '1', '0', '0', '1', '1']),
-            "-M" => Ok(['1', '1', '1', '0', '0', '1', '1']),
-            "D+1" => Ok(['0', '0', '1', '1', '1', '1', '1']),
-            "A+1" => Ok(['0', '1', '1', '0', '1', '1', '1']),
-            "M+1" => Ok(['1', '1', '1', '0', '1', '1', '1']),
-            "D-1" => Ok(['0', '0', '0', '1', '1', '1', '0']),
-            "A-1" => Ok(['0', '1', '1', '0', '0', '1', '0']),
-            "M-1" => Ok(['1', '1', '1', '0', '0', '1', '0']),
-            "D+A" => Ok(['0', '0', '0', '0', '0', '1', '0']),
-            "D+M" => Ok(['1', '0', '0', '0', '0', '1', '0']),
-            "D-A" => Ok(['0', '0', '1', '0', '0', '1', '1']),
-            "D-M" => Ok(['1', '0', '1', '0', '0', '1', '1']),
-            "A-D" => Ok(['0', '0', '0', '0', '1', '1', '1']),
-            "M-D" => Ok(['1', '0', '0', '0', '1', '1', '1']),
-            "D&A" => Ok(['0', '0', '0', '0', '0', '0', '0']),
-            "D&M" => Ok(['1', '0', '0', '0', '0', '0', '0']),
-            "D|A" => Ok(['0', '0', '1', '0', '1', '0', '1']),
-            "D|M" => Ok(['1', '0', '1', '0', '1', '0', '1']),
-            _ => Err(String::from("Invalid comp field")),
-        }
+        Comp::parse(field).map(|comp| comp.bits())
+    }
+    /// Binary of a C-Instruction
+    pub fn c_instruction(dest: Dest, comp: Comp, jump: Jump) -> [char; 16] {
+        let mut instruction = ['1'; 16];
+        instruction[13..16].copy_from_slice(&jump.bits());
+        instruction[10..13].copy_from_slice(&dest.bits());
+        instruction[3..10].copy_from_slice(&comp.bits());
+        instruction
     }
 }
 
@@ -98,6 +58,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_c_instruction() {
+        assert_eq!(
+            SimpleAssembler::c_instruction(Dest::D, Comp::M, Jump::Null)
+                .iter()
+                .collect::<String>(),
+            "1111110000010000"
+        );
+        assert_eq!(SimpleAssembler::comp("D+A"), Ok(Comp::DPlusA.bits()));
+        assert!(SimpleAssembler::comp("A+D").is_err());
+    }
+
     #[test]
     fn test_a_instruction_overflow() {
         let overflow = Err(String::from("Overflow A-Instruction value"));
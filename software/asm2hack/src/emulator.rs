@@ -0,0 +1,162 @@
+/// Hack CPU emulator, runs the binary produced by the assembler
+/// Meant for tests, so there is no screen or keyboard, they are only plain RAM
+pub struct Emulator {
+    pub ram: Vec<u16>,
+    rom: Vec<u16>,
+    pub a: u16,
+    pub d: u16,
+    pub pc: usize,
+}
+
+impl Emulator {
+    /// Every address of the 15 bit address space, past KBD included
+    pub const RAM_SIZE: usize = 32768;
+
+    pub fn new(rom: &[[char; 16]]) -> Self {
+        let rom = rom
+            .iter()
+            .map(|instruction| {
+                u16::from_str_radix(&instruction.iter().collect::<String>(), 2).unwrap()
+            })
+            .collect::<Vec<u16>>();
+        Self {
+            ram: vec![0; Self::RAM_SIZE],
+            rom,
+            a: 0,
+            d: 0,
+            pc: 0,
+        }
+    }
+
+    /// RAM[address] as a signed Hack word
+    pub fn ram_i16(&self, address: usize) -> i16 {
+        self.ram[address] as i16
+    }
+
+    /// Runs until the pc falls off the end of the ROM, the program halts in a jump to itself
+    /// or max_steps instructions were run
+    /// Returns the number of instructions run
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        for steps in 0..max_steps {
+            let pc = self.pc;
+            if !self.step() {
+                return steps;
+            }
+            // @LOOP, 0;JMP back to the @LOOP
+            if pc > 0 && self.pc == pc - 1 && self.rom[pc - 1] as usize == pc - 1 {
+                return steps + 1;
+            }
+        }
+        max_steps
+    }
+
+    /// Runs a single instruction, false if the pc is outside of the ROM
+    pub fn step(&mut self) -> bool {
+        let instruction = match self.rom.get(self.pc) {
+            Some(instruction) => *instruction,
+            None => return false,
+        };
+        if instruction & 0x8000 == 0 {
+            // A-Instruction
+            self.a = instruction;
+            self.pc += 1;
+            return true;
+        }
+        let y = if instruction & 0x1000 == 0 {
+            self.a
+        } else {
+            self.read(self.a)
+        };
+        let out = Self::alu(self.d, y, (instruction >> 6) & 0x3f);
+        // Address of M is the A before this instruction
+        let address = self.a;
+        if instruction & 0x20 != 0 {
+            self.a = out;
+        }
+        if instruction & 0x10 != 0 {
+            self.d = out;
+        }
+        if instruction & 0x08 != 0 {
+            self.ram[Self::ram_address(address)] = out;
+        }
+        let out = out as i16;
+        let jump = match instruction & 0x7 {
+            0 => false,
+            1 => out > 0,
+            2 => out == 0,
+            3 => out >= 0,
+            4 => out < 0,
+            5 => out != 0,
+            6 => out <= 0,
+            _ => true,
+        };
+        if jump {
+            self.pc = self.a as usize;
+        } else {
+            self.pc += 1;
+        }
+        true
+    }
+
+    fn read(&self, address: u16) -> u16 {
+        self.ram[Self::ram_address(address)]
+    }
+    /// Only the low 15 bits of A address the RAM, like the Hack CPU
+    fn ram_address(a: u16) -> usize {
+        (a & 0x7fff) as usize
+    }
+
+    /// The Hack ALU, control is zx nx zy ny f no
+    fn alu(x: u16, y: u16, control: u16) -> u16 {
+        let bit = |i: u16| control & (1 << (5 - i)) != 0;
+        let x = if bit(0) { 0 } else { x };
+        let x = if bit(1) { !x } else { x };
+        let y = if bit(2) { 0 } else { y };
+        let y = if bit(3) { !y } else { y };
+        let out = if bit(4) { x.wrapping_add(y) } else { x & y };
+        if bit(5) {
+            !out
+        } else {
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simple::Simple;
+
+    fn run(source: &str) -> Emulator {
+        let mut emulator = Emulator::new(&Simple::compile(source.to_string()).unwrap());
+        emulator.run(1000);
+        emulator
+    }
+
+    #[test]
+    fn arithmetic() {
+        let emulator = run("@7\nD=A\n@3\nD=D-A\n@0\nM=D\nD=-D\n@1\nM=D\nD=!A\n@2\nM=D");
+        assert_eq!(emulator.ram_i16(0), 4);
+        assert_eq!(emulator.ram_i16(1), -4);
+        assert_eq!(emulator.ram_i16(2), !1);
+    }
+    #[test]
+    fn wrapping() {
+        let emulator = run("@32767\nD=A\nD=D+1\n@0\nM=D");
+        assert_eq!(emulator.ram_i16(0), i16::MIN);
+    }
+    #[test]
+    fn jumps_and_halt() {
+        let source = "@5\nD=A\n(LOOP)\n@R1\nM=M+1\nD=D-1\n@LOOP\nD;JGT\n(END)\n@END\n0;JMP";
+        let mut emulator = Emulator::new(&Simple::compile(source.to_string()).unwrap());
+        let steps = emulator.run(1000);
+        assert!(steps < 1000);
+        assert_eq!(emulator.ram[1], 5);
+    }
+    #[test]
+    fn whole_address_space() {
+        let emulator = run("@32767\nM=-1\nD=M\nA=-1\nM=D+1\n@0\nM=D");
+        assert_eq!(emulator.ram[32767], 0);
+        assert_eq!(emulator.ram_i16(0), -1);
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+
+/// Target of an A-Instruction, @symbol or @value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Address {
+    Symbol(String),
+    Value(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dest {
+    Null,
+    M,
+    D,
+    Md,
+    A,
+    Am,
+    Ad,
+    Amd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comp {
+    Zero,
+    One,
+    MinusOne,
+    D,
+    A,
+    M,
+    NotD,
+    NotA,
+    NotM,
+    NegD,
+    NegA,
+    NegM,
+    DPlusOne,
+    APlusOne,
+    MPlusOne,
+    DMinusOne,
+    AMinusOne,
+    MMinusOne,
+    DPlusA,
+    DPlusM,
+    DMinusA,
+    DMinusM,
+    AMinusD,
+    MMinusD,
+    DAndA,
+    DAndM,
+    DOrA,
+    DOrM,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jump {
+    Null,
+    Jgt,
+    Jeq,
+    Jge,
+    Jlt,
+    Jne,
+    Jle,
+    Jmp,
+}
+
+// (field, asm, binary) tables, the single place the Hack encoding is written down
+const DESTS: [(Dest, &str, &str); 8] = [
+    (Dest::Null, "null", "000"),
+    (Dest::M, "M", "001"),
+    (Dest::D, "D", "010"),
+    (Dest::Md, "MD", "011"),
+    (Dest::A, "A", "100"),
+    (Dest::Am, "AM", "101"),
+    (Dest::Ad, "AD", "110"),
+    (Dest::Amd, "AMD", "111"),
+];
+const COMPS: [(Comp, &str, &str); 28] = [
+    (Comp::Zero, "0", "0101010"),
+    (Comp::One, "1", "0111111"),
+    (Comp::MinusOne, "-1", "0111010"),
+    (Comp::D, "D", "0001100"),
+    (Comp::A, "A", "0110000"),
+    (Comp::M, "M", "1110000"),
+    (Comp::NotD, "!D", "0001101"),
+    (Comp::NotA, "!A", "0110001"),
+    (Comp::NotM, "!M", "1110001"),
+    (Comp::NegD, "-D", "0001111"),
+    (Comp::NegA, "-A", "0110011"),
+    (Comp::NegM, "-M", "1110011"),
+    (Comp::DPlusOne, "D+1", "0011111"),
+    (Comp::APlusOne, "A+1", "0110111"),
+    (Comp::MPlusOne, "M+1", "1110111"),
+    (Comp::DMinusOne, "D-1", "0001110"),
+    (Comp::AMinusOne, "A-1", "0110010"),
+    (Comp::MMinusOne, "M-1", "1110010"),
+    (Comp::DPlusA, "D+A", "0000010"),
+    (Comp::DPlusM, "D+M", "1000010"),
+    (Comp::DMinusA, "D-A", "0010011"),
+    (Comp::DMinusM, "D-M", "1010011"),
+    (Comp::AMinusD, "A-D", "0000111"),
+    (Comp::MMinusD, "M-D", "1000111"),
+    (Comp::DAndA, "D&A", "0000000"),
+    (Comp::DAndM, "D&M", "1000000"),
+    (Comp::DOrA, "D|A", "0010101"),
+    (Comp::DOrM, "D|M", "1010101"),
+];
+const JUMPS: [(Jump, &str, &str); 8] = [
+    (Jump::Null, "null", "000"),
+    (Jump::Jgt, "JGT", "001"),
+    (Jump::Jeq, "JEQ", "010"),
+    (Jump::Jge, "JGE", "011"),
+    (Jump::Jlt, "JLT", "100"),
+    (Jump::Jne, "JNE", "101"),
+    (Jump::Jle, "JLE", "110"),
+    (Jump::Jmp, "JMP", "111"),
+];
+
+fn from_asm<T: Copy>(table: &[(T, &str, &str)], field: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, asm, _)| *asm == field)
+        .map(|(value, _, _)| *value)
+}
+fn lookup<T: PartialEq>(
+    table: &[(T, &'static str, &'static str)],
+    value: &T,
+) -> (&'static str, &'static str) {
+    table
+        .iter()
+        .find(|(v, _, _)| v == value)
+        .map(|(_, asm, bits)| (*asm, *bits))
+        .unwrap()
+}
+fn to_bits<const N: usize>(bits: &str) -> [char; N] {
+    bits.chars().collect::<Vec<char>>().try_into().unwrap()
+}
+
+impl Dest {
+    pub fn parse(field: &str) -> Result<Self, String> {
+        from_asm(&DESTS, field).ok_or_else(|| String::from("Invalid dest field"))
+    }
+    pub fn to_asm(&self) -> &'static str {
+        lookup(&DESTS, self).0
+    }
+    pub fn bits(&self) -> [char; 3] {
+        to_bits(lookup(&DESTS, self).1)
+    }
+}
+impl Comp {
+    pub fn parse(field: &str) -> Result<Self, String> {
+        from_asm(&COMPS, field).ok_or_else(|| String::from("Invalid comp field"))
+    }
+    pub fn to_asm(&self) -> &'static str {
+        lookup(&COMPS, self).0
+    }
+    pub fn bits(&self) -> [char; 7] {
+        to_bits(lookup(&COMPS, self).1)
+    }
+}
+impl Jump {
+    pub fn parse(field: &str) -> Result<Self, String> {
+        from_asm(&JUMPS, field).ok_or_else(|| String::from("Invalid jump field"))
+    }
+    pub fn to_asm(&self) -> &'static str {
+        lookup(&JUMPS, self).0
+    }
+    pub fn bits(&self) -> [char; 3] {
+        to_bits(lookup(&JUMPS, self).1)
+    }
+}
+
+/// A single line of Hack assembly
+/// Label and Comment do not take up a ROM address
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    A(Address),
+    C { dest: Dest, comp: Comp, jump: Jump },
+    Label(String),
+    Comment(String),
+}
+
+impl Instruction {
+    pub fn symbol(symbol: &str) -> Self {
+        Self::A(Address::Symbol(symbol.to_string()))
+    }
+    pub fn value(value: usize) -> Self {
+        Self::A(Address::Value(value))
+    }
+    /// dest=comp
+    pub fn assign(dest: Dest, comp: Comp) -> Self {
+        Self::C {
+            dest,
+            comp,
+            jump: Jump::Null,
+        }
+    }
+    /// comp;jump
+    pub fn jump(comp: Comp, jump: Jump) -> Self {
+        Self::C {
+            dest: Dest::Null,
+            comp,
+            jump,
+        }
+    }
+    pub fn label(label: &str) -> Self {
+        Self::Label(label.to_string())
+    }
+    pub fn comment(comment: &str) -> Self {
+        Self::Comment(comment.to_string())
+    }
+
+    /// Parses a single line of asm, an inline comment after an instruction is dropped
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let source = source.trim();
+        if let Some(comment) = source.strip_prefix("//") {
+            return Ok(Self::Comment(comment.to_string()));
+        }
+        let source = match source.split_once("//") {
+            Some((source, _)) => source.trim(),
+            None => source,
+        };
+        if let Some(label) = source.strip_prefix('(') {
+            return match label.strip_suffix(')') {
+                Some(label) if is_valid_symbol(label) => Ok(Self::label(label)),
+                _ => Err(String::from("Not a valid instruction label")),
+            };
+        }
+        if let Some(address) = source.strip_prefix('@') {
+            if address.is_empty() {
+                return Err(String::from("Empty A-Instruction"));
+            }
+            if is_valid_symbol(address) {
+                return Ok(Self::symbol(address));
+            }
+            return match address.parse::<usize>() {
+                Ok(value) => Ok(Self::value(value)),
+                Err(_) => Err(String::from("Invalid A-Instruction Decimal Value")),
+            };
+        }
+        let (dest, source) = match source.split_once('=') {
+            Some((dest, source)) => (dest, source),
+            None => ("null", source),
+        };
+        let (comp, jump) = match source.split_once(';') {
+            Some((comp, jump)) => (comp, jump),
+            None => (source, "null"),
+        };
+        Ok(Self::C {
+            dest: Dest::parse(dest.trim())?,
+            comp: Comp::parse(comp.trim())?,
+            jump: Jump::parse(jump.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A(Address::Symbol(symbol)) => write!(f, "@{}", symbol),
+            Self::A(Address::Value(value)) => write!(f, "@{}", value),
+            Self::C { dest, comp, jump } => {
+                if *dest != Dest::Null {
+                    write!(f, "{}=", dest.to_asm())?;
+                }
+                write!(f, "{}", comp.to_asm())?;
+                if *jump != Jump::Null {
+                    write!(f, ";{}", jump.to_asm())?;
+                }
+                Ok(())
+            }
+            Self::Label(label) => write!(f, "({})", label),
+            Self::Comment(comment) => write!(f, "//{}", comment),
+        }
+    }
+}
+
+/// Renders instructions as an asm file, one per line
+pub fn render(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Symbols can be used both as labels and variables
+pub fn is_valid_symbol(symbol: &str) -> bool {
+    let symbol = symbol.chars().collect::<Vec<char>>();
+    if symbol.is_empty() {
+        return false;
+    }
+    if !(symbol[0].is_ascii_alphabetic()
+        || symbol[0] == '_'
+        || symbol[0] == '.'
+        || symbol[0] == '$'
+        || symbol[0] == ':')
+    {
+        return false;
+    }
+    for c in &symbol[1..symbol.len()] {
+        if !(c.is_ascii_alphanumeric() || c == &'_' || c == &'.' || c == &'$' || c == &':') {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_symbols() {
+        let symbols = ["_", ".", "$", ":", "a2", ".2", "DRAW_REACT", "wow"];
+        for symbol in symbols {
+            assert!(is_valid_symbol(symbol));
+        }
+    }
+    #[test]
+    fn invalid_symbols() {
+        let symbols = ["2a", "", "a%", "oops@"];
+        for symbol in symbols {
+            assert!(!is_valid_symbol(symbol));
+        }
+    }
+    #[test]
+    fn parse() {
+        assert_eq!(Instruction::parse("@SP"), Ok(Instruction::symbol("SP")));
+        assert_eq!(Instruction::parse("@17"), Ok(Instruction::value(17)));
+        assert_eq!(
+            Instruction::parse("AM=M-1 // SP--"),
+            Ok(Instruction::assign(Dest::Am, Comp::MMinusOne))
+        );
+        assert_eq!(
+            Instruction::parse("0;JMP"),
+            Ok(Instruction::jump(Comp::Zero, Jump::Jmp))
+        );
+        assert_eq!(
+            Instruction::parse("D=D-A;JGT"),
+            Ok(Instruction::C {
+                dest: Dest::D,
+                comp: Comp::DMinusA,
+                jump: Jump::Jgt
+            })
+        );
+        assert_eq!(
+            Instruction::parse("(Main.main$LOOP)"),
+            Ok(Instruction::label("Main.main$LOOP"))
+        );
+        assert_eq!(
+            Instruction::parse("//push constant 1"),
+            Ok(Instruction::comment("push constant 1"))
+        );
+    }
+    #[test]
+    fn parse_errors() {
+        let sources = ["@", "@-10", "@3.3", "()", "(1abv)", "D=X", "D;JUMP", "AMD"];
+        for source in sources {
+            assert!(Instruction::parse(source).is_err(), "{}", source);
+        }
+    }
+    #[test]
+    fn render_round_trip() {
+        let source = "//call\n@SP\nAM=M+1\nD;JNE\n(LOOP)\n@5\n0;JMP";
+        let instructions = source
+            .split('\n')
+            .map(|line| Instruction::parse(line).unwrap())
+            .collect::<Vec<Instruction>>();
+        assert_eq!(render(&instructions), source);
+    }
+    #[test]
+    fn bits() {
+        assert_eq!(Dest::Amd.bits(), ['1'; 3]);
+        assert_eq!(Comp::DOrM.bits(), ['1', '0', '1', '0', '1', '0', '1']);
+        assert_eq!(Jump::Null.bits(), ['0'; 3]);
+    }
+}
@@ -2,8 +2,15 @@
 /// EOL = End of Line
 /// EOF = End of File
 pub mod assembler;
+pub mod emulator;
+pub mod instruction;
+pub mod linker;
+pub mod object;
 pub mod simple;
 pub mod symbol_table;
 
 pub use assembler::SimpleAssembler;
+pub use instruction::{Address, Comp, Dest, Instruction, Jump};
+pub use linker::{LinkError, LinkOutput, Linker};
+pub use object::ObjectFile;
 pub use symbol_table::SimpleSymbolTable;
@@ -0,0 +1,234 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{ObjectFile, SimpleAssembler};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    pub object: String,
+    pub msg: String,
+}
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.object, self.msg)
+    }
+}
+
+#[derive(Debug)]
+pub struct LinkOutput {
+    pub rom: Vec<[char; 16]>,
+    pub source_lines: Vec<(usize, usize)>, // (object index, source line) of every ROM word
+    pub n_statics: usize,                  // Variables included
+}
+
+/// Where a symbol was defined, so duplicates can name both objects
+struct Definition {
+    value: usize,
+    object: usize,
+}
+
+/// Places objects in ROM one after the other, in the order given
+/// Statics get RAM slots from static_base upwards, also in object order
+/// Then variables no object defines as a label or static, one slot per name like the Hack assembler
+///
+/// Reports
+/// - labels or statics defined by more than one object, or defined twice
+/// - references to symbols that no object defines, nor lists as a variable
+/// - programs that do not fit in ROM
+pub struct Linker;
+
+impl Linker {
+    /// Size of the ROM, the A-Instruction can not address any further
+    const ROM_SIZE: usize = 32768;
+
+    pub fn link(objects: &[ObjectFile], static_base: usize) -> Result<LinkOutput, Vec<LinkError>> {
+        let mut errors = Vec::default();
+        // (symbol, value, object index) of every label and static
+        let mut definitions = Vec::default();
+        let mut code_base = 0;
+        let mut n_statics = 0;
+        for (i, object) in objects.iter().enumerate() {
+            for (label, offset) in &object.labels {
+                definitions.push((label.as_str(), code_base + offset, i));
+            }
+            for name in &object.statics {
+                definitions.push((name.as_str(), static_base + n_statics, i));
+                n_statics += 1;
+            }
+            code_base += object.code.len();
+        }
+        let mut symbols: HashMap<&str, Definition> = HashMap::new();
+        for (symbol, value, object) in definitions {
+            if let Some(first) = symbols.get(symbol) {
+                errors.push(LinkError {
+                    object: objects[object].name.clone(),
+                    msg: format!(
+                        "{} is already defined by {}",
+                        symbol, objects[first.object].name
+                    ),
+                });
+            } else {
+                symbols.insert(symbol, Definition { value, object });
+            }
+        }
+        for (i, object) in objects.iter().enumerate() {
+            for name in &object.variables {
+                if !symbols.contains_key(name.as_str()) {
+                    let value = static_base + n_statics;
+                    symbols.insert(name.as_str(), Definition { value, object: i });
+                    n_statics += 1;
+                }
+            }
+        }
+        if code_base > Self::ROM_SIZE {
+            errors.push(LinkError {
+                object: objects.last().unwrap().name.clone(),
+                msg: format!(
+                    "Program is {} words, ROM only has {}",
+                    code_base,
+                    Self::ROM_SIZE
+                ),
+            });
+        }
+
+        let mut rom = Vec::with_capacity(code_base);
+        let mut source_lines = Vec::with_capacity(code_base);
+        for (i, object) in objects.iter().enumerate() {
+            let start = rom.len();
+            rom.extend_from_slice(&object.code);
+            source_lines.extend(object.source_lines.iter().map(|line| (i, *line)));
+            for (at, symbol) in &object.references {
+                let value = match symbols.get(symbol.as_str()) {
+                    Some(definition) => definition.value,
+                    None => {
+                        errors.push(LinkError {
+                            object: object.name.clone(),
+                            msg: format!("Undefined symbol {}", symbol),
+                        });
+                        continue;
+                    }
+                };
+                match SimpleAssembler::a_instruction(value) {
+                    Ok(word) => rom[start + at] = word,
+                    Err(msg) => errors.push(LinkError {
+                        object: object.name.clone(),
+                        msg: format!("{}, {} is {}", msg, symbol, value),
+                    }),
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(LinkOutput {
+                rom,
+                source_lines,
+                n_statics,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{emulator::Emulator, Instruction};
+
+    fn parse(source: &str) -> Vec<Instruction> {
+        source
+            .split('\n')
+            .map(|line| Instruction::parse(line).unwrap())
+            .collect()
+    }
+    fn object(name: &str, source: &str, statics: &[&str]) -> ObjectFile {
+        let instructions = parse(source);
+        let statics = statics
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<String>>();
+        ObjectFile::assemble(name, &instructions, &statics).unwrap()
+    }
+
+    #[test]
+    fn link() {
+        let main = "@5\nD=A\n@Lib.twice\n0;JMP\n(Main.return)\n@Main.0\nM=D\n(END)\n@END\n0;JMP";
+        let lib = "(Lib.twice)\n@5\nD=D+A\n@Lib.0\nM=D\n@Main.return\n0;JMP";
+        let objects = [
+            object("Main", main, &["Main.0"]),
+            object("Lib", lib, &["Lib.0"]),
+        ];
+        let output = Linker::link(&objects, 16).unwrap();
+        assert_eq!(output.n_statics, 2);
+        assert_eq!(output.source_lines[8], (1, 1));
+
+        // Lib.twice is placed right after Main
+        assert_eq!(output.rom[2], SimpleAssembler::a_instruction(8).unwrap());
+
+        let mut emulator = Emulator::new(&output.rom);
+        emulator.run(100);
+        assert_eq!(emulator.ram[16], 10);
+        assert_eq!(emulator.ram[17], 10);
+    }
+    #[test]
+    fn variables() {
+        let main = "@counter\nM=M+1\n@Lib.count\n0;JMP";
+        let lib = "(Lib.count)\n@counter\nM=M+1\n@Lib.0\nM=M+1\n@total\nM=M+1\n(END)\n@END\n0;JMP";
+        let variables = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<String>>()
+        };
+        let objects = [
+            ObjectFile::assemble_with_variables(
+                "Main",
+                &parse(main),
+                &[],
+                &variables(&["counter", "Lib.count"]),
+            )
+            .unwrap(),
+            ObjectFile::assemble_with_variables(
+                "Lib",
+                &parse(lib),
+                &variables(&["Lib.0"]),
+                &variables(&["counter", "total"]),
+            )
+            .unwrap(),
+        ];
+        let output = Linker::link(&objects, 16).unwrap();
+        // Lib.count is a label of Lib, so not a variable of Main
+        assert_eq!(output.n_statics, 3);
+
+        let mut emulator = Emulator::new(&output.rom);
+        emulator.run(100);
+        // Statics first, then the variables in order of their objects, once per name
+        assert_eq!(emulator.ram[16], 1);
+        assert_eq!(emulator.ram[17], 2);
+        assert_eq!(emulator.ram[18], 1);
+    }
+    #[test]
+    fn undefined_and_duplicate_symbols() {
+        let objects = [
+            object("Main", "(Main.main)\n@Main.0\n@Sys.init", &["Main.0"]),
+            object("Other", "(Main.main)\n@Main.0", &["Main.0"]),
+        ];
+        let errors = Linker::link(&objects, 16).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                LinkError {
+                    object: String::from("Other"),
+                    msg: String::from("Main.main is already defined by Main"),
+                },
+                LinkError {
+                    object: String::from("Other"),
+                    msg: String::from("Main.0 is already defined by Main"),
+                },
+                LinkError {
+                    object: String::from("Main"),
+                    msg: String::from("Undefined symbol Sys.init"),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use crate::{Address, Instruction, SimpleAssembler, SimpleSymbolTable};
+
+/// A single file assembled on its own, without knowing where it will end up in ROM
+///
+/// Labels are relative to the start of code, every A-Instruction to a symbol that is not
+/// predefined (SP, R13, SCREEN...) is left as a reference for the Linker to fill in
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ObjectFile {
+    pub name: String,
+    pub code: Vec<[char; 16]>,
+    pub labels: Vec<(String, usize)>, // Exported labels and their code index
+    pub references: Vec<(usize, String)>, // Code index of an A-Instruction and its symbol
+    pub statics: Vec<String>,         // Variables the Linker allocates a RAM slot for
+    pub variables: Vec<String>,       // Shared by name, like @counter in inline asm
+    pub source_lines: Vec<usize>,     // Instruction index each code word came from
+}
+
+impl ObjectFile {
+    /// Extension of object files written to disk
+    pub const EXTENSION: &'static str = "hobj";
+
+    /// statics are the variables owned by this file, any other symbol has to be a label
+    pub fn assemble(
+        name: &str,
+        instructions: &[Instruction],
+        statics: &[String],
+    ) -> Result<Self, String> {
+        Self::assemble_with_variables(name, instructions, statics, &[])
+    }
+    /// Same as assemble, variables can be used by several objects like those of the Hack assembler
+    /// The Linker only gives them a RAM slot if no object defines them as a label or static
+    pub fn assemble_with_variables(
+        name: &str,
+        instructions: &[Instruction],
+        statics: &[String],
+        variables: &[String],
+    ) -> Result<Self, String> {
+        let predefined = SimpleSymbolTable::new().table;
+        let mut object = Self {
+            name: name.to_string(),
+            statics: statics.to_vec(),
+            variables: variables.to_vec(),
+            ..Self::default()
+        };
+        let mut labels: HashMap<&str, usize> = HashMap::new();
+        for (i, instruction) in instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Label(label) => {
+                    if predefined.contains_key(label) || labels.contains_key(label.as_str()) {
+                        return Err(format!("Instruction label, {}, already exists", label));
+                    }
+                    labels.insert(label, object.code.len());
+                    object.labels.push((label.clone(), object.code.len()));
+                    continue;
+                }
+                Instruction::Comment(_) => continue,
+                Instruction::A(Address::Symbol(symbol)) => match predefined.get(symbol) {
+                    Some(value) => object.code.push(SimpleAssembler::a_instruction(*value)?),
+                    None => {
+                        object.references.push((object.code.len(), symbol.clone()));
+                        object.code.push(['0'; 16]);
+                    }
+                },
+                Instruction::A(Address::Value(value)) => {
+                    object.code.push(SimpleAssembler::a_instruction(*value)?)
+                }
+                Instruction::C { dest, comp, jump } => object
+                    .code
+                    .push(SimpleAssembler::c_instruction(*dest, *comp, *jump)),
+            }
+            object.source_lines.push(i);
+        }
+        Ok(object)
+    }
+
+    /// Text form, a header line followed by one line per static, variable, label, reference and code word
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("object {}", self.name)];
+        lines.extend(self.statics.iter().map(|name| format!("static {}", name)));
+        lines.extend(
+            self.variables
+                .iter()
+                .map(|name| format!("variable {}", name)),
+        );
+        lines.extend(
+            self.labels
+                .iter()
+                .map(|(label, i)| format!("label {} {}", label, i)),
+        );
+        lines.extend(
+            self.references
+                .iter()
+                .map(|(i, symbol)| format!("reference {} {}", i, symbol)),
+        );
+        lines.extend(
+            self.code
+                .iter()
+                .zip(&self.source_lines)
+                .map(|(word, line)| format!("code {} {}", word.iter().collect::<String>(), line)),
+        );
+        lines.join("\n")
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut lines = text.split('\n').enumerate();
+        let mut object = match lines.next().map(|(_, line)| line.split_once(' ')) {
+            Some(Some(("object", name))) => Self {
+                name: name.to_string(),
+                ..Self::default()
+            },
+            _ => {
+                return Err(String::from(
+                    "Not an object file, missing the object header",
+                ))
+            }
+        };
+        for (i, line) in lines {
+            let tokens = line.split(' ').collect::<Vec<&str>>();
+            let index = |token: &str| {
+                token
+                    .parse::<usize>()
+                    .map_err(|_| format!("Error on line {}: Invalid index {}", i + 1, token))
+            };
+            match tokens.as_slice() {
+                ["static", name] => object.statics.push(name.to_string()),
+                ["variable", name] => object.variables.push(name.to_string()),
+                ["label", label, at] => object.labels.push((label.to_string(), index(at)?)),
+                ["reference", at, symbol] => {
+                    object.references.push((index(at)?, symbol.to_string()))
+                }
+                ["code", word, line]
+                    if word.len() == 16 && word.chars().all(|c| c == '0' || c == '1') =>
+                {
+                    object
+                        .code
+                        .push(word.chars().collect::<Vec<char>>().try_into().unwrap());
+                    object.source_lines.push(index(line)?);
+                }
+                [""] => {}
+                _ => {
+                    return Err(format!(
+                        "Error on line {}: Unknown object line {}",
+                        i + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        let code_len = object.code.len();
+        let out_of_code = object.labels.iter().any(|(_, i)| *i > code_len)
+            || object.references.iter().any(|(i, _)| *i >= code_len);
+        if out_of_code {
+            return Err(String::from("Label or reference outside of the code"));
+        }
+        Ok(object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Vec<Instruction> {
+        source
+            .split('\n')
+            .map(|line| Instruction::parse(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn assemble() {
+        let source = "//Main\n(Main.main)\n@SP\nM=M+1\n@Main.0\n(LOOP)\n@LOOP\n0;JMP\n(END)";
+        let object =
+            ObjectFile::assemble("Main", &parse(source), &[String::from("Main.0")]).unwrap();
+        assert_eq!(object.code.len(), 5);
+        assert_eq!(object.code[0], SimpleAssembler::a_instruction(0).unwrap());
+        assert_eq!(
+            object.labels,
+            vec![
+                (String::from("Main.main"), 0),
+                (String::from("LOOP"), 3),
+                (String::from("END"), 5)
+            ]
+        );
+        assert_eq!(
+            object.references,
+            vec![(2, String::from("Main.0")), (3, String::from("LOOP"))]
+        );
+        assert_eq!(object.source_lines, vec![2, 3, 4, 6, 7]);
+    }
+    #[test]
+    fn assemble_errors() {
+        assert!(ObjectFile::assemble("Main", &parse("(LOOP)\n(LOOP)"), &[]).is_err());
+        assert!(ObjectFile::assemble("Main", &parse("(SP)"), &[]).is_err());
+        assert!(ObjectFile::assemble("Main", &parse("@40000"), &[]).is_err());
+    }
+    #[test]
+    fn text_round_trip() {
+        let source = "(Main.main)\n@Main.0\nM=M+1\n@counter\n@Main.main\n0;JMP";
+        let object = ObjectFile::assemble_with_variables(
+            "Main",
+            &parse(source),
+            &[String::from("Main.0")],
+            &[String::from("counter")],
+        )
+        .unwrap();
+        assert_eq!(ObjectFile::from_text(&object.to_text()), Ok(object));
+    }
+    #[test]
+    fn text_errors() {
+        let texts = [
+            "",
+            "label Main.main 0",
+            "object Main\ncode 01 0",
+            "object Main\nlabel LOOP x",
+            "object Main\nreference 0 LOOP",
+        ];
+        for text in texts {
+            assert!(ObjectFile::from_text(text).is_err(), "{}", text);
+        }
+    }
+}
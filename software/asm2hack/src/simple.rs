@@ -1,4 +1,6 @@
-use crate::{SimpleAssembler, SimpleSymbolTable};
+use crate::{
+    instruction, Address, Comp, Dest, Instruction, Jump, SimpleAssembler, SimpleSymbolTable,
+};
 
 #[derive(Clone, Debug)]
 struct LineSource {
@@ -14,6 +16,7 @@ impl LineSource {
 pub struct Simple {
     rom: Vec<[char; 16]>,
     line_sources: Vec<LineSource>,
+    instructions: Vec<(Instruction, usize)>, // Instruction and the line it came from
     symbol_table: SimpleSymbolTable,
     had_error: bool,
 }
@@ -26,174 +29,133 @@ impl Simple {
             .map(|(i, s)| LineSource::new(s.to_string(), i))
             .collect::<Vec<LineSource>>();
         Self {
-            rom: Vec::default(),
             line_sources: source,
-            symbol_table: SimpleSymbolTable::new(),
-            had_error: false,
+            ..Self::default()
         }
     }
     pub fn compile(source: String) -> Option<Vec<[char; 16]>> {
+        Self::compile_with_source_lines(source, 16).map(|(rom, _)| rom)
+    }
+    /// Same as compile, but also returns the source line (starting from 0) of every ROM instruction
+    /// Variables are allocated from variable_base instead of 16
+    pub fn compile_with_source_lines(
+        source: String,
+        variable_base: usize,
+    ) -> Option<(Vec<[char; 16]>, Vec<usize>)> {
         let mut simple = Self::new(source);
+        simple.symbol_table = SimpleSymbolTable::with_variable_base(variable_base);
         simple.remove_whtiespace();
-        simple.first_pass();
-        simple.hack();
+        simple.parse();
+        simple.assemble()
+    }
+    /// Assembles already parsed instructions, skipping the text round trip
+    /// The returned source lines are indexes into instructions
+    pub fn assemble_with_source_lines(
+        instructions: &[Instruction],
+        variable_base: usize,
+    ) -> Option<(Vec<[char; 16]>, Vec<usize>)> {
+        let simple = Self {
+            instructions: instructions
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, instruction)| (instruction, i))
+                .collect(),
+            symbol_table: SimpleSymbolTable::with_variable_base(variable_base),
+            ..Self::default()
+        };
+        simple.assemble()
+    }
+
+    fn assemble(mut self) -> Option<(Vec<[char; 16]>, Vec<usize>)> {
+        self.first_pass();
+        self.hack();
         #[cfg(feature = "debug")]
         {
-            println!("{:?}", simple.symbol_table.table);
+            println!("{:?}", self.symbol_table.table);
         }
-        if simple.had_error {
+        if self.had_error {
             None
         } else {
-            Some(simple.rom)
+            // After the first pass, every instruction is a ROM instruction
+            let source_lines = self
+                .instructions
+                .iter()
+                .map(|(_, line)| *line)
+                .collect::<Vec<usize>>();
+            Some((self.rom, source_lines))
+        }
+    }
+
+    fn parse(&mut self) {
+        for line_source in self.line_sources.clone() {
+            match Instruction::parse(&line_source.source) {
+                Ok(instruction) => self.instructions.push((instruction, line_source.line)),
+                Err(msg) => self.error(line_source.line, msg),
+            }
         }
     }
 
     fn first_pass(&mut self) {
-        let mut new_line_sources: Vec<LineSource> = Vec::new();
+        let mut new_instructions: Vec<(Instruction, usize)> = Vec::new();
         let mut rom_line = 0;
-        for line_source in self.line_sources.clone() {
-            let source = &line_source.source;
-            if source.starts_with('(') && source.ends_with(')') {
+        for (instruction, line) in self.instructions.clone() {
+            match instruction {
                 // If we encounter an instruction label, remove it and save the symbol of ROM line
-                self.add_instruction_label(&line_source, rom_line);
-            } else {
-                // Normal instruction, increment rom_line number and save line_source
-                rom_line += 1;
-                new_line_sources.push(line_source);
+                Instruction::Label(label) => self.add_instruction_label(&label, line, rom_line),
+                Instruction::Comment(_) => {}
+                // Normal instruction, increment rom_line number and save the instruction
+                _ => {
+                    rom_line += 1;
+                    new_instructions.push((instruction, line));
+                }
             }
         }
-        self.line_sources = new_line_sources;
+        self.instructions = new_instructions;
     }
 
     fn hack(&mut self) {
-        for line_source in self.line_sources.clone() {
-            if line_source.source.starts_with('@') {
-                // A-Instruction
-                self.a_instruction(&line_source);
-            } else {
-                // C-Instruction
-                self.c_instruction(&line_source);
+        for (instruction, line) in self.instructions.clone() {
+            match instruction {
+                Instruction::A(address) => self.a_instruction(&address, line),
+                Instruction::C { dest, comp, jump } => self.c_instruction(dest, comp, jump),
+                // Removed by the first pass
+                Instruction::Label(_) | Instruction::Comment(_) => {}
             }
         }
     }
 
-    fn a_instruction(&mut self, line_source: &LineSource) {
-        let source = match line_source.source.strip_prefix('@') {
-            Some(s) => s,
-            None => {
-                return self.error(line_source.line, String::from("Invalid A-Instruction"));
-            }
-        };
-        if source.is_empty() {
-            // Empty @ instruction
-            return self.error(line_source.line, String::from("Empty A-Instruction"));
-        }
-        let label = source.chars().collect::<Vec<char>>();
-        let value = if Self::is_valid_label(&label) {
-            self.symbol_table
-                .get_or_insert_memory_label(label.iter().collect::<String>())
-        } else {
-            match label.iter().collect::<String>().parse::<usize>() {
-                Ok(v) => v,
-                Err(_) => {
-                    return self.error(
-                        line_source.line,
-                        String::from("Invalid A-Instruction Decimal Value"),
-                    );
+    fn a_instruction(&mut self, address: &Address, line: usize) {
+        let value = match address {
+            Address::Symbol(symbol) => {
+                if !instruction::is_valid_symbol(symbol) {
+                    return self.error(line, format!("Invalid A-Instruction symbol, {}", symbol));
                 }
+                self.symbol_table.get_or_insert_memory_label(symbol.clone())
             }
+            Address::Value(value) => *value,
         };
         match SimpleAssembler::a_instruction(value) {
-            Err(msg) => self.error(line_source.line, msg),
+            Err(msg) => self.error(line, msg),
             Ok(rom_instruction) => self.rom.push(rom_instruction),
         }
     }
 
-    fn c_instruction(&mut self, line_source: &LineSource) {
-        let mut source = line_source.source.clone();
-        let null = String::from("null");
-
-        let mut dest = null.clone();
-        let comp: String;
-        let mut jump = null.clone();
-
-        if source.contains('=') {
-            let s = source.split_once('=').unwrap();
-            dest = s.0.to_string();
-            source = s.1.to_string();
-        }
-        if source.contains(';') {
-            let s = source.split_once(';').unwrap();
-            comp = s.0.to_string();
-            jump = s.1.to_string();
-        } else {
-            comp = source;
-        }
-
-        let dest_instruction = match SimpleAssembler::dest(dest.trim()) {
-            Ok(v) => v,
-            Err(msg) => return self.error(line_source.line, msg),
-        };
-        let comp_instruction = match SimpleAssembler::comp(comp.trim()) {
-            Ok(v) => v,
-            Err(msg) => return self.error(line_source.line, msg),
-        };
-        let jump_instruction = match SimpleAssembler::jump(jump.trim()) {
-            Ok(v) => v,
-            Err(msg) => return self.error(line_source.line, msg),
-        };
-        let mut instruction = ['1'; 16];
-        instruction[13..16].copy_from_slice(&jump_instruction);
-        instruction[10..13].copy_from_slice(&dest_instruction);
-        instruction[3..10].copy_from_slice(&comp_instruction);
-        self.rom.push(instruction);
-    }
-
-    fn add_instruction_label(&mut self, line_source: &LineSource, value: usize) {
-        let invalid_instruction_msg = String::from("Not a valid instruction label");
-        let mut source = match line_source.source.strip_prefix('(') {
-            Some(s) => s,
-            None => {
-                return self.error(line_source.line, invalid_instruction_msg);
-            }
-        };
-        source = match source.strip_suffix(')') {
-            Some(s) => s,
-            None => {
-                return self.error(line_source.line, invalid_instruction_msg);
-            }
-        };
-        let label = source.chars().collect::<Vec<char>>();
-        if Self::is_valid_label(&label) {
-            if let Err(msg) = self
-                .symbol_table
-                .insert_instruction_label(label.iter().collect::<String>(), value)
-            {
-                self.error(line_source.line, msg);
-            };
-        } else {
-            self.error(line_source.line, invalid_instruction_msg);
-        }
+    fn c_instruction(&mut self, dest: Dest, comp: Comp, jump: Jump) {
+        self.rom
+            .push(SimpleAssembler::c_instruction(dest, comp, jump));
     }
 
-    fn is_valid_label(label: &[char]) -> bool {
-        if label.is_empty() {
-            return false;
+    fn add_instruction_label(&mut self, label: &str, line: usize, value: usize) {
+        if !instruction::is_valid_symbol(label) {
+            return self.error(line, String::from("Not a valid instruction label"));
         }
-        if !(label[0].is_ascii_alphabetic()
-            || label[0] == '_'
-            || label[0] == '.'
-            || label[0] == '$'
-            || label[0] == ':')
+        if let Err(msg) = self
+            .symbol_table
+            .insert_instruction_label(label.to_string(), value)
         {
-            return false;
-        }
-        for c in &label[1..label.len()] {
-            if !(c.is_ascii_alphanumeric() || c == &'_' || c == &'.' || c == &'$' || c == &':') {
-                return false;
-            }
-        }
-        true
+            self.error(line, msg);
+        };
     }
 
     fn error(&mut self, line: usize, msg: String) {
@@ -227,11 +189,13 @@ impl Simple {
         self.line_sources = new_line_sources;
     }
 }
+#[allow(clippy::derivable_impls)]
 impl Default for Simple {
     fn default() -> Self {
         Self {
             rom: Vec::default(),
             line_sources: Vec::default(),
+            instructions: Vec::default(),
             symbol_table: SimpleSymbolTable::new(),
             had_error: false,
         }
@@ -243,7 +207,8 @@ mod tests {
     use super::*;
 
     mod simple {
-        use super::{LineSource, Simple};
+        use super::{Address, Instruction, Simple};
+        use crate::{instruction, Comp, Dest, Jump};
 
         #[test]
         fn empty_remove_whitespace() {
@@ -265,33 +230,6 @@ mod tests {
             assert_eq!(simple.line_sources[0].source.len(), 6);
         }
 
-        #[test]
-        fn valid_labels() {
-            let labels = vec!["_", ".", "$", ":", "a2", ".2", "DRAW_REACT", "wow"];
-            let labels = labels
-                .into_iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>();
-            for label in labels {
-                assert!(Simple::is_valid_label(
-                    &label.chars().collect::<Vec<char>>()
-                ));
-            }
-        }
-        #[test]
-        fn invalid_labels() {
-            let labels = vec!["2a", "", "a%", "oops@"];
-            let labels = labels
-                .into_iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>();
-            for label in labels {
-                assert!(!Simple::is_valid_label(
-                    &label.chars().collect::<Vec<char>>()
-                ));
-            }
-        }
-
         #[test]
         fn valid_add_instruction_label() {
             let labels = [
@@ -305,20 +243,14 @@ mod tests {
                 "(_wow)",
                 "(DRAW_REACT)",
             ];
-            let labels = labels
-                .iter()
-                .map(|s| LineSource::new(s.to_string(), 1))
-                .collect::<Vec<LineSource>>();
             let mut simple = Simple::default();
             for label in labels {
-                simple.add_instruction_label(&label, 1);
+                let actual_label = match Instruction::parse(label) {
+                    Ok(Instruction::Label(actual_label)) => actual_label,
+                    other => panic!("{} should be a label, not {:?}", label, other),
+                };
+                simple.add_instruction_label(&actual_label, 1, 1);
                 assert!(!simple.had_error);
-                let actual_label = label
-                    .source
-                    .strip_prefix('(')
-                    .unwrap()
-                    .strip_suffix(')')
-                    .unwrap();
                 assert_eq!(
                     simple
                         .symbol_table
@@ -330,31 +262,53 @@ mod tests {
         #[test]
         fn invalid_add_instruction_label() {
             let labels = ["a(valid)", "(VALID).", "(1abv)", "abc", "@no", "()"];
-            let labels = labels
-                .iter()
-                .map(|s| LineSource::new(s.to_string(), 1))
-                .collect::<Vec<LineSource>>();
             for label in labels {
-                let mut simple = Simple::default();
-                assert!(!simple.had_error);
-                simple.add_instruction_label(&label, 1);
-                assert!(simple.had_error);
+                assert!(!matches!(
+                    Instruction::parse(label),
+                    Ok(Instruction::Label(_))
+                ));
             }
+            let mut simple = Simple::default();
+            simple.add_instruction_label("1abv", 1, 1);
+            assert!(simple.had_error);
         }
 
         #[test]
         fn invalid_a_instruction() {
             let sources = ["no", "@", "@-10", "@3333333333", "@3.3"];
-            let sources = sources
-                .iter()
-                .map(|s| LineSource::new(s.to_string(), 1))
-                .collect::<Vec<LineSource>>();
             for source in sources {
-                let mut simple = Simple::default();
-                assert!(!simple.had_error);
-                simple.a_instruction(&source);
-                assert!(simple.had_error);
+                assert!(Simple::compile(source.to_string()).is_none());
             }
+            let mut simple = Simple::default();
+            simple.a_instruction(&Address::Symbol(String::from("oops@")), 1);
+            assert!(simple.had_error);
+        }
+        #[test]
+        fn compile_with_source_lines() {
+            let source = String::from("// comment\n@2\n(LOOP)\n\nD=A // wow\n@LOOP\n0;JMP");
+            let (rom, source_lines) = Simple::compile_with_source_lines(source, 16).unwrap();
+            assert_eq!(rom.len(), 4);
+            assert_eq!(source_lines, vec![1, 4, 5, 6]);
+
+            let source = String::from("@a\n@b");
+            let (rom, _) = Simple::compile_with_source_lines(source, 20).unwrap();
+            assert_eq!(rom[1].iter().collect::<String>(), "0000000000010101");
+        }
+        #[test]
+        fn assemble_with_source_lines() {
+            let instructions = [
+                Instruction::comment("start"),
+                Instruction::symbol("SP"),
+                Instruction::label("LOOP"),
+                Instruction::assign(Dest::D, Comp::A),
+                Instruction::symbol("LOOP"),
+                Instruction::jump(Comp::Zero, Jump::Jmp),
+            ];
+            let (rom, source_lines) =
+                Simple::assemble_with_source_lines(&instructions, 16).unwrap();
+            assert_eq!(source_lines, vec![1, 3, 4, 5]);
+            let text = Simple::compile(instruction::render(&instructions)).unwrap();
+            assert_eq!(rom, text);
         }
         #[test]
         fn valid_a_instruction() {
@@ -366,23 +320,12 @@ mod tests {
                 "0000000000000001",
                 "0110000000000000",
                 "0100000000000000",
-            ]
-            .iter()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-            let sources = sources
-                .iter()
-                .map(|s| LineSource::new(s.to_string(), 1))
-                .collect::<Vec<LineSource>>();
+            ];
             for (source, correct_rom_instruction) in
                 sources.iter().zip(correct_rom_instructions.iter())
             {
-                let mut simple = Simple::default();
-                simple.a_instruction(source);
-                assert_eq!(
-                    simple.rom[0].iter().collect::<String>(),
-                    correct_rom_instruction.to_owned()
-                );
+                let rom = Simple::compile(source.to_string()).unwrap();
+                assert_eq!(rom[0].iter().collect::<String>(), *correct_rom_instruction);
             }
         }
     }
@@ -6,6 +6,10 @@ pub struct SimpleSymbolTable {
 }
 impl SimpleSymbolTable {
     pub fn new() -> Self {
+        Self::with_variable_base(16)
+    }
+    /// Variables (memory labels) are allocated from variable_base upwards
+    pub fn with_variable_base(variable_base: usize) -> Self {
         let table = HashMap::from([
             (String::from("SP"), 0),
             (String::from("LCL"), 1),
@@ -33,7 +37,7 @@ impl SimpleSymbolTable {
         ]);
         Self {
             table,
-            current_memory: 16,
+            current_memory: variable_base,
         }
     }
     pub fn insert_instruction_label(&mut self, label: String, value: usize) -> Result<(), String> {
@@ -89,6 +93,22 @@ mod tests {
             .is_err())
     }
     #[test]
+    fn variable_base() {
+        let mut symbol_table = SimpleSymbolTable::with_variable_base(100);
+        assert_eq!(
+            symbol_table.get_or_insert_memory_label(String::from("a")),
+            100
+        );
+        assert_eq!(
+            symbol_table.get_or_insert_memory_label(String::from("b")),
+            101
+        );
+        assert_eq!(
+            symbol_table.get_or_insert_memory_label(String::from("a")),
+            100
+        );
+    }
+    #[test]
     fn get_reserved_instruction() {
         let mut symbol_table = SimpleSymbolTable::new();
         let label = String::from("R10");
@@ -0,0 +1,310 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use jack2vm::{ParserOptions, TypeCheck};
+
+type ProgResult = Result<(), String>;
+
+const USAGE: &str = "hackc [--output folder] [--os] [--os-folder folder] [--stop-after vm|asm|hack] [--native-math] [--type-check lenient|strict] [vm2hack options] [path]";
+
+/// OS of this repo, used by --os
+const OS_FOLDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../jack/os");
+
+/// Last stage to run, the stages after it are skipped
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Stage {
+    Vm,   // jack2vm, writes the .vm files
+    Asm,  // vm2asm, writes the .asm and objects into the build folder
+    Hack, // Links into the .hack and source map
+}
+
+struct Options {
+    project: PathBuf,
+    output: Option<PathBuf>, // <project>/out by default
+    os: Option<PathBuf>,     // OS classes that the project does not define itself
+    stop_after: Stage,
+    parser: ParserOptions,
+    vm2hack_args: Vec<String>, // Passed on to vm2hack::Options::from_args
+}
+impl Options {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut project = None;
+        let mut output = None;
+        let mut os = None;
+        let mut stop_after = Stage::Hack;
+        let mut parser = ParserOptions::default();
+        let mut vm2hack_args = Vec::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => match args.next() {
+                    Some(folder) => output = Some(PathBuf::from(folder)),
+                    None => return Err(String::from("Expected a folder after --output")),
+                },
+                "--os" => os = Some(PathBuf::from(OS_FOLDER)),
+                "--os-folder" => match args.next() {
+                    Some(folder) => os = Some(PathBuf::from(folder)),
+                    None => return Err(String::from("Expected a folder after --os-folder")),
+                },
+                "--stop-after" => {
+                    stop_after = match args.next().map(|stage| stage.as_str()) {
+                        Some("vm") => Stage::Vm,
+                        Some("asm") => Stage::Asm,
+                        Some("hack") => Stage::Hack,
+                        _ => {
+                            return Err(String::from("Expected vm, asm or hack after --stop-after"))
+                        }
+                    }
+                }
+                "--native-math" => parser.native_math = true,
+                "--type-check" => {
+                    parser.type_check = match args.next().map(|mode| mode.as_str()) {
+                        Some("lenient") => Some(TypeCheck::Lenient),
+                        Some("strict") => Some(TypeCheck::Strict),
+                        _ => {
+                            return Err(String::from(
+                                "Expected lenient or strict after --type-check",
+                            ))
+                        }
+                    }
+                }
+                // Every other option is for vm2hack, with its value if it takes one
+                _ if arg.starts_with("--") => {
+                    vm2hack_args.push(arg.clone());
+                    if takes_value(arg) {
+                        match args.next() {
+                            Some(value) => vm2hack_args.push(value.clone()),
+                            None => return Err(format!("Expected a value after {}", arg)),
+                        }
+                    }
+                }
+                _ if project.is_none() => project = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
+        }
+        match project {
+            Some(project) => Ok(Self {
+                project,
+                output,
+                os,
+                stop_after,
+                parser,
+                vm2hack_args,
+            }),
+            None => Err(String::from("Expected a project folder")),
+        }
+    }
+
+    fn output_folder(&self) -> PathBuf {
+        self.output.clone().unwrap_or(self.project.join("out"))
+    }
+}
+
+/// vm2hack options followed by a value, the others are flags
+fn takes_value(option: &str) -> bool {
+    !matches!(
+        option,
+        "--stack-report" | "--clean" | "--runtime-checks" | "--math-commands" | "--tail-calls"
+    )
+}
+
+/// Files in folder with extension, sorted by name
+fn files_with_extension(folder: &Path, extension: &str) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::default();
+    for entry in fs::read_dir(folder).map_err(|e| format!("{}: {}", folder.display(), e))? {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        if entry_path.is_file() && entry_path.extension().is_some_and(|e| e == extension) {
+            paths.push(entry_path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn class_name(path: &Path) -> String {
+    path.file_stem().unwrap().to_str().unwrap().to_string()
+}
+
+/// Compiles the project, and the OS classes it does not define, into .vm files in the output folder
+/// The classes are checked together, nothing is written if any of them has an error
+/// Returns the number of .vm files written
+fn compile_to_vm(options: &Options, output_folder: &Path) -> Result<usize, String> {
+    let mut jack_files = files_with_extension(&options.project, "jack")?;
+    let mut vm_files = Vec::default();
+    if let Some(os) = &options.os {
+        let classes = jack_files
+            .iter()
+            .map(|path| class_name(path))
+            .collect::<Vec<String>>();
+        let is_new = |path: &PathBuf| !classes.contains(&class_name(path));
+        let os_jack_files = files_with_extension(os, "jack")?;
+        let os_classes = os_jack_files
+            .iter()
+            .map(|path| class_name(path))
+            .collect::<Vec<String>>();
+        vm_files.extend(
+            files_with_extension(os, "vm")?
+                .into_iter()
+                // A .vm next to its .jack is an old output of jack2vm
+                .filter(|path| is_new(path) && !os_classes.contains(&class_name(path))),
+        );
+        jack_files.extend(os_jack_files.into_iter().filter(is_new));
+    }
+    if jack_files.is_empty() {
+        return Err(format!("No .jack files in {}", options.project.display()));
+    }
+
+    let mut files = Vec::default();
+    for path in &jack_files {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file_name = path.file_name().unwrap().to_str().unwrap().to_string();
+        files.push((file_name, source));
+    }
+    let outputs = jack2vm::compile_project(&files, &options.parser)?;
+
+    // Old .vm files would be linked in as well
+    fs::create_dir_all(output_folder).map_err(|e| e.to_string())?;
+    for path in files_with_extension(output_folder, "vm")? {
+        fs::remove_file(path).map_err(|e| e.to_string())?;
+    }
+    for (path, output) in jack_files.iter().zip(outputs) {
+        let vm_path = output_folder.join(format!("{}.vm", class_name(path)));
+        fs::write(vm_path, output.map(|output| output.vm).unwrap_or_default())
+            .map_err(|e| e.to_string())?;
+    }
+    for path in &vm_files {
+        let vm_path = output_folder.join(path.file_name().unwrap());
+        fs::copy(path, vm_path).map_err(|e| e.to_string())?;
+    }
+    Ok(jack_files.len() + vm_files.len())
+}
+
+fn compile_project(options: &Options) -> ProgResult {
+    if !options.project.is_dir() {
+        return Err(String::from("Not a directory"));
+    }
+    let output_folder = options.output_folder();
+    let n_files = compile_to_vm(options, &output_folder)?;
+    println!("Wrote {} .vm files to {}", n_files, output_folder.display());
+    if options.stop_after == Stage::Vm {
+        return Ok(());
+    }
+
+    let mut args = options.vm2hack_args.clone();
+    args.push(output_folder.to_str().unwrap().to_string());
+    let mut vm2hack_options = vm2hack::Options::from_args(&args)?;
+    // The .vm files have mul and div commands instead of calls to Math
+    if options.parser.native_math {
+        vm2hack_options.compiler.math_commands = true;
+    }
+    // Named after the project, not the output folder
+    vm2hack_options.name = Some(
+        options
+            .project
+            .canonicalize()
+            .map_err(|e| e.to_string())?
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("program")
+            .to_string(),
+    );
+    let program = vm2hack::compile_to_objects(&vm2hack_options)?;
+    for line in &program.stack_report {
+        println!("{}", line);
+    }
+    println!("{}", program.rebuilt_summary());
+    if options.stop_after == Stage::Asm {
+        return Ok(());
+    }
+    vm2hack::compile_to_hack(&vm2hack_options, program)
+}
+
+/// Compiles a Jack project folder down to a .hack program
+fn main() -> ProgResult {
+    let args: Vec<String> = env::args().collect();
+    match Options::from_args(&args[1..]) {
+        Ok(options) => compile_project(&options),
+        Err(e) => Err(format!(
+            "{}\nUsage: {}\nvm2hack options: {}",
+            e,
+            USAGE,
+            vm2hack::USAGE
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn from_args() {
+        let options = Options::from_args(&args(
+            "--stop-after asm --stack-base 300 --runtime-checks Pong --os --type-check strict",
+        ))
+        .unwrap();
+        assert_eq!(options.project, PathBuf::from("Pong"));
+        assert_eq!(options.stop_after, Stage::Asm);
+        assert_eq!(options.os, Some(PathBuf::from(OS_FOLDER)));
+        assert_eq!(options.parser.type_check, Some(TypeCheck::Strict));
+        assert_eq!(
+            options.vm2hack_args,
+            args("--stack-base 300 --runtime-checks")
+        );
+        assert_eq!(options.output_folder(), PathBuf::from("Pong/out"));
+
+        let options = Options::from_args(&args("--stack-base 300 MyProject")).unwrap();
+        assert_eq!(options.project, PathBuf::from("MyProject"));
+        assert_eq!(options.vm2hack_args, args("--stack-base 300"));
+
+        assert!(Options::from_args(&args("--stop-after link Pong")).is_err());
+        assert!(Options::from_args(&args("Pong Other")).is_err());
+        assert!(Options::from_args(&args("--stack-base 300")).is_err());
+    }
+    /// Builds the OS Main test program with flags and the OS of this repo, runs it and returns its results
+    fn os_test_program(name: &str, flags: &str) -> Vec<i16> {
+        let root = env::temp_dir().join(format!("hackc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let project = root.join("Prog");
+        fs::create_dir_all(&project).unwrap();
+        // The OS Main, with Math.multiply and Math.divide
+        fs::copy(
+            Path::new(OS_FOLDER).join("Main.jack"),
+            project.join("Main.jack"),
+        )
+        .unwrap();
+
+        let options =
+            Options::from_args(&args(&format!("{} --os {}", flags, project.display()))).unwrap();
+        compile_project(&options).unwrap();
+        let hack = fs::read_to_string(project.join("out/Prog.hack")).unwrap();
+        assert!(project.join("out/Prog.map.json").is_file());
+        assert!(project.join("out/build/Main.asm").is_file());
+        assert!(project.join("out/Sys.vm").is_file());
+        fs::remove_dir_all(&root).unwrap();
+
+        let rom = hack
+            .lines()
+            .map(|line| line.chars().collect::<Vec<char>>().try_into().unwrap())
+            .collect::<Vec<[char; 16]>>();
+        let mut emulator = asm2hack::emulator::Emulator::new(&rom);
+        emulator.run(10_000_000);
+        (8000..8008)
+            .map(|address| emulator.ram_i16(address))
+            .collect()
+    }
+    #[test]
+    fn os_test() {
+        let results = os_test_program("os", "--type-check lenient");
+        assert_eq!(results, vec![6, -180, -18000, -18000, 0, 3, -3000, 0]);
+        // mul and div commands instead of Math calls
+        let results = os_test_program("native-math", "--native-math");
+        assert_eq!(results, vec![6, -180, -18000, -18000, 0, 3, -3000, 0]);
+    }
+}
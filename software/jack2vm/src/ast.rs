@@ -0,0 +1,280 @@
+//! Typed AST of a single Jack class, produced by the Parser
+//!
+//! Every node keeps the Span it was parsed from, so passes over the AST can point back into the source
+//! XML emission and VM code generation are such passes, see xml.rs and code_gen.rs
+
+/// Chars of the source covered by a node, like a Token
+/// line is the line the node starts on
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub length: usize,
+    pub line: usize,
+}
+impl Span {
+    /// From the start of self to the end of other
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            start: self.start,
+            length: other.end().max(self.end()) - self.start,
+            line: self.line,
+        }
+    }
+    pub fn end(&self) -> usize {
+        self.start + self.length
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Char,
+    Boolean,
+    Class(String),
+}
+impl Type {
+    /// Name as written in the source
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Int => "int",
+            Self::Char => "char",
+            Self::Boolean => "boolean",
+            Self::Class(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: Identifier,
+    pub var_decs: Vec<ClassVarDec>,
+    pub subroutines: Vec<SubroutineDec>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClassVarKind {
+    Static,
+    Field,
+}
+impl ClassVarKind {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Static => "static",
+            Self::Field => "field",
+        }
+    }
+}
+
+/// ('static' | 'field') type varName (',' varName)* ';'
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassVarDec {
+    pub kind: ClassVarKind,
+    pub _type: Type,
+    pub type_span: Span,
+    pub names: Vec<Identifier>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubroutineKind {
+    Constructor,
+    Function,
+    Method,
+}
+impl SubroutineKind {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Constructor => "constructor",
+            Self::Function => "function",
+            Self::Method => "method",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineDec {
+    pub kind: SubroutineKind,
+    pub return_type: Option<Type>, // None is void
+    pub return_type_span: Span,
+    pub name: Identifier,
+    pub parameters: Vec<Parameter>,
+    pub var_decs: Vec<VarDec>,
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub _type: Type,
+    pub type_span: Span,
+    pub name: Identifier,
+}
+
+/// 'var' type varName (',' varName)* ';'
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDec {
+    pub _type: Type,
+    pub type_span: Span,
+    pub names: Vec<Identifier>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let {
+        name: Identifier,
+        index: Option<Expression>, // let name[index] = value;
+        value: Expression,
+        span: Span,
+    },
+    If {
+        condition: Expression,
+        statements: Vec<Statement>,
+        else_statements: Option<Vec<Statement>>,
+        span: Span,
+    },
+    While {
+        condition: Expression,
+        statements: Vec<Statement>,
+        span: Span,
+    },
+    Do {
+        call: SubroutineCall,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+}
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Let { span, .. }
+            | Self::If { span, .. }
+            | Self::While { span, .. }
+            | Self::Do { span, .. }
+            | Self::Return { span, .. } => *span,
+        }
+    }
+}
+
+/// term (op term)*
+/// Jack has no operator precedence, the ops are applied from left to right
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub term: Term,
+    pub ops: Vec<(BinaryOp, Term)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    LessThan,
+    GreaterThan,
+    Equal,
+}
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::And => "&",
+            Self::Or => "|",
+            Self::LessThan => "<",
+            Self::GreaterThan => ">",
+            Self::Equal => "=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Neg => "-",
+            Self::Not => "~",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeywordConstant {
+    True,
+    False,
+    Null,
+    This,
+}
+impl KeywordConstant {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::True => "true",
+            Self::False => "false",
+            Self::Null => "null",
+            Self::This => "this",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Integer(u16, Span), // Within 0 and 32767, checked by the Tokenizer
+    String(String, Span),
+    Keyword(KeywordConstant, Span),
+    Variable(Identifier),
+    Index {
+        name: Identifier,
+        index: Box<Expression>,
+        span: Span,
+    },
+    Call(SubroutineCall),
+    Parenthesized(Box<Expression>, Span),
+    Unary {
+        op: UnaryOp,
+        term: Box<Term>,
+        span: Span,
+    },
+}
+impl Term {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Integer(_, span)
+            | Self::String(_, span)
+            | Self::Keyword(_, span)
+            | Self::Parenthesized(_, span)
+            | Self::Index { span, .. }
+            | Self::Unary { span, .. } => *span,
+            Self::Variable(identifier) => identifier.span,
+            Self::Call(call) => call.span,
+        }
+    }
+}
+
+/// name(arguments) or receiver.name(arguments)
+/// The receiver is either a variable or a class name
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineCall {
+    pub receiver: Option<Identifier>,
+    pub name: Identifier,
+    pub arguments: Vec<Expression>,
+    pub span: Span,
+}
@@ -0,0 +1,443 @@
+//! Whole program checks over the ASTs of every class in a project
+//!
+//! Each file compiles on its own, so a call into another class can only be checked once every
+//! class is parsed. Classes of the project shadow the OS classes of the same name
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    ast::{
+        Class, Expression, Identifier, Span, Statement, SubroutineCall, SubroutineDec,
+        SubroutineKind, Term, Type,
+    },
+    os_api::OS_API,
+    CheckError, JackError, Parser,
+};
+
+/// Error of check_project or check_types, in the file of the class it was found in
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectError {
+    pub file_name: String,
+    pub error: JackError,
+}
+impl ProjectError {
+    pub fn span(&self) -> Span {
+        self.error.span()
+    }
+}
+impl fmt::Display for ProjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, line {}: {}",
+            self.file_name,
+            self.span().line,
+            self.error
+        )
+    }
+}
+
+/// What a caller needs to know about a subroutine
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub kind: SubroutineKind,
+    pub return_type: Option<Type>, // None is void
+    pub parameters: Vec<Type>,
+}
+impl Signature {
+    fn new(subroutine: &SubroutineDec) -> Self {
+        Self {
+            kind: subroutine.kind,
+            return_type: subroutine.return_type.clone(),
+            parameters: subroutine
+                .parameters
+                .iter()
+                .map(|parameter| parameter._type.clone())
+                .collect(),
+        }
+    }
+}
+
+/// Subroutine signatures of every class, by class name and subroutine name
+pub struct ProjectSymbols {
+    classes: HashMap<String, HashMap<String, Signature>>,
+}
+impl ProjectSymbols {
+    /// Symbols of the OS only
+    pub fn os() -> Self {
+        let mut classes = HashMap::new();
+        for source in OS_API {
+            let class = Parser::parse_ast(source).unwrap().unwrap();
+            let subroutines = class
+                .subroutines
+                .iter()
+                .map(|subroutine| (subroutine.name.name.clone(), Signature::new(subroutine)))
+                .collect();
+            classes.insert(class.name.name, subroutines);
+        }
+        Self { classes }
+    }
+    pub fn has_class(&self, class_name: &str) -> bool {
+        self.classes.contains_key(class_name)
+    }
+    /// Signatures of the subroutines of a class, by name
+    pub fn subroutines(&self, class_name: &str) -> Option<&HashMap<String, Signature>> {
+        self.classes.get(class_name)
+    }
+    pub fn signature(&self, class_name: &str, subroutine_name: &str) -> Option<&Signature> {
+        self.classes.get(class_name)?.get(subroutine_name)
+    }
+}
+
+/// Checks the classes of a project, given as (file name, class)
+///
+/// Reports
+/// - classes or subroutines defined twice
+/// - types that are not a class of the project or the OS
+/// - calls to classes or subroutines that do not exist
+/// - methods called on a class, or from a function without this
+/// - calls with the wrong number of arguments
+pub fn check_project(classes: &[(String, Class)]) -> Result<ProjectSymbols, Vec<ProjectError>> {
+    let mut errors = Vec::default();
+    let mut symbols = ProjectSymbols::os();
+    let mut defined_by: HashMap<&str, &str> = HashMap::new();
+    for (file_name, class) in classes {
+        if let Some(first) = defined_by.insert(&class.name.name, file_name) {
+            errors.push(ProjectError {
+                file_name: file_name.clone(),
+                error: CheckError::ClassDefinedTwice {
+                    class: class.name.name.clone(),
+                    first_file: first.to_string(),
+                }
+                .at(class.name.span),
+            });
+            continue;
+        }
+        let mut subroutines = HashMap::new();
+        for subroutine in &class.subroutines {
+            let name = &subroutine.name;
+            if subroutines
+                .insert(name.name.clone(), Signature::new(subroutine))
+                .is_some()
+            {
+                errors.push(ProjectError {
+                    file_name: file_name.clone(),
+                    error: CheckError::SubroutineDefinedTwice(format!(
+                        "{}.{}",
+                        class.name.name, name.name
+                    ))
+                    .at(name.span),
+                });
+            }
+        }
+        symbols.classes.insert(class.name.name.clone(), subroutines);
+    }
+
+    for (file_name, class) in classes {
+        let mut checker = Checker {
+            symbols: &symbols,
+            file_name,
+            class,
+            subroutine: None,
+            variables: HashMap::new(),
+            errors: Vec::default(),
+        };
+        checker.class();
+        errors.extend(checker.errors);
+    }
+    if errors.is_empty() {
+        Ok(symbols)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Pass over a single class
+struct Checker<'a> {
+    symbols: &'a ProjectSymbols,
+    file_name: &'a str,
+    class: &'a Class,
+    subroutine: Option<&'a SubroutineDec>,
+    variables: HashMap<&'a str, &'a Type>, // Every variable in scope
+    errors: Vec<ProjectError>,
+}
+impl<'a> Checker<'a> {
+    fn error(&mut self, span: Span, error: CheckError) {
+        self.errors.push(ProjectError {
+            file_name: self.file_name.to_string(),
+            error: error.at(span),
+        });
+    }
+    fn _type(&mut self, _type: &Type, span: Span) {
+        if let Type::Class(name) = _type {
+            if !self.symbols.has_class(name) {
+                self.error(span, CheckError::UnknownType(name.clone()));
+            }
+        }
+    }
+
+    fn class(&mut self) {
+        let class = self.class;
+        let mut class_variables = HashMap::new();
+        for var_dec in &class.var_decs {
+            self._type(&var_dec._type, var_dec.type_span);
+            for name in &var_dec.names {
+                class_variables.insert(name.name.as_str(), &var_dec._type);
+            }
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine = Some(subroutine);
+            self.variables = class_variables.clone();
+            self.subroutine_dec(subroutine);
+        }
+    }
+    fn subroutine_dec(&mut self, subroutine: &'a SubroutineDec) {
+        if let Some(_type) = &subroutine.return_type {
+            self._type(_type, subroutine.return_type_span);
+        }
+        for parameter in &subroutine.parameters {
+            self._type(&parameter._type, parameter.type_span);
+            self.variables
+                .insert(&parameter.name.name, &parameter._type);
+        }
+        for var_dec in &subroutine.var_decs {
+            self._type(&var_dec._type, var_dec.type_span);
+            for name in &var_dec.names {
+                self.variables.insert(&name.name, &var_dec._type);
+            }
+        }
+        self.statements(&subroutine.statements);
+    }
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let { index, value, .. } => {
+                    if let Some(index) = index {
+                        self.expression(index);
+                    }
+                    self.expression(value);
+                }
+                Statement::If {
+                    condition,
+                    statements,
+                    else_statements,
+                    ..
+                } => {
+                    self.expression(condition);
+                    self.statements(statements);
+                    if let Some(else_statements) = else_statements {
+                        self.statements(else_statements);
+                    }
+                }
+                Statement::While {
+                    condition,
+                    statements,
+                    ..
+                } => {
+                    self.expression(condition);
+                    self.statements(statements);
+                }
+                Statement::Do { call, .. } => self.subroutine_call(call),
+                Statement::Return { value, .. } => {
+                    if let Some(value) = value {
+                        self.expression(value);
+                    }
+                }
+            }
+        }
+    }
+    fn expression(&mut self, expression: &Expression) {
+        self.term(&expression.term);
+        for (_, term) in &expression.ops {
+            self.term(term);
+        }
+    }
+    fn term(&mut self, term: &Term) {
+        match term {
+            Term::Index { index, .. } => self.expression(index),
+            Term::Call(call) => self.subroutine_call(call),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary { term, .. } => self.term(term),
+            _ => {}
+        }
+    }
+    fn subroutine_call(&mut self, call: &SubroutineCall) {
+        for argument in &call.arguments {
+            self.expression(argument);
+        }
+        // The class called into, and if there is an object for this
+        let (class_name, on_object) = match &call.receiver {
+            None => (self.class.name.name.as_str(), true),
+            Some(Identifier { name, span }) => match self.variables.get(name.as_str()) {
+                Some(Type::Class(class_name)) => (class_name.as_str(), true),
+                // Calls on int, char and boolean are reported by the CodeGen
+                Some(_) => return,
+                None => {
+                    if !self.symbols.has_class(name) {
+                        self.error(*span, CheckError::UnknownClassOrVariable(name.clone()));
+                        return;
+                    }
+                    (name.as_str(), false)
+                }
+            },
+        };
+        // Unknown types are already reported at their declaration
+        if !self.symbols.has_class(class_name) {
+            return;
+        }
+        let full_name = format!("{}.{}", class_name, call.name.name);
+        let signature = match self.symbols.signature(class_name, &call.name.name) {
+            Some(signature) => signature,
+            None => {
+                return self.error(call.name.span, CheckError::UndefinedSubroutine(full_name));
+            }
+        };
+        if signature.kind == SubroutineKind::Method {
+            if !on_object {
+                self.error(
+                    call.name.span,
+                    CheckError::MethodWithoutObject(full_name.clone()),
+                );
+            } else if call.receiver.is_none()
+                && self.subroutine.map(|subroutine| subroutine.kind)
+                    == Some(SubroutineKind::Function)
+            {
+                self.error(
+                    call.name.span,
+                    CheckError::MethodWithoutThis(full_name.clone()),
+                );
+            }
+        }
+        if signature.parameters.len() != call.arguments.len() {
+            self.error(
+                call.span,
+                CheckError::ArgumentCount {
+                    subroutine: full_name,
+                    n_parameters: signature.parameters.len(),
+                    n_arguments: call.arguments.len(),
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(sources: &[&str]) -> Vec<String> {
+        let classes = sources
+            .iter()
+            .map(|source| {
+                let class = Parser::parse_ast(source).unwrap().unwrap();
+                (format!("{}.jack", class.name.name), class)
+            })
+            .collect::<Vec<(String, Class)>>();
+        match check_project(&classes) {
+            Ok(_) => Vec::default(),
+            Err(errors) => errors.iter().map(|error| error.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn os_api() {
+        let symbols = ProjectSymbols::os();
+        let signature = symbols.signature("String", "appendChar").unwrap();
+        assert_eq!(signature.kind, SubroutineKind::Method);
+        assert_eq!(
+            signature.return_type,
+            Some(Type::Class(String::from("String")))
+        );
+        assert_eq!(signature.parameters, vec![Type::Char]);
+        assert!(symbols.has_class("Sys"));
+    }
+    #[test]
+    fn valid_project() {
+        let main = "class Main {
+            function void main() {
+                var Point p;
+                let p = Point.new(1, 2);
+                do p.move(3);
+                do Output.printInt(p.x());
+                return;
+            }
+        }";
+        let point = "class Point {
+            field int x, y;
+            constructor Point new(int ax, int ay) { let x = ax; let y = ay; return this; }
+            method int x() { return x; }
+            method void move(int dx) { let x = x + dx; do draw(); return; }
+            method void draw() { return; }
+        }";
+        assert_eq!(check(&[main, point]), Vec::<String>::new());
+    }
+    #[test]
+    fn call_errors() {
+        let main = "class Main {
+            function void main() {
+                var Point p;
+                do Point.nothing();
+                do Poin.new(1, 2);
+                do Point.new(1);
+                do Point.draw();
+                do p.draw(1);
+                do draw();
+                return;
+            }
+            method void draw() { return; }
+        }";
+        let point = "class Point {
+            constructor Point new(int ax, int ay) { return this; }
+            method void draw() { return; }
+        }";
+        assert_eq!(
+            check(&[main, point]),
+            vec![
+                "Main.jack, line 4: Point.nothing is not defined",
+                "Main.jack, line 5: Unknown class or variable Poin",
+                "Main.jack, line 6: Point.new takes 2 arguments, got 1",
+                "Main.jack, line 7: Point.draw is a method, it needs an object to be called on",
+                "Main.jack, line 8: Point.draw takes 0 arguments, got 1",
+                "Main.jack, line 9: Main.draw is a method, a function has no this to call it on",
+            ]
+        );
+    }
+    #[test]
+    fn typed_errors() {
+        let main = "class Main { function void main() { do Main.main(1); return; } }";
+        let class = Parser::parse_ast(main).unwrap().unwrap();
+        let errors = match check_project(&[(String::from("Main.jack"), class)]) {
+            Ok(_) => panic!("Expected an error"),
+            Err(errors) => errors,
+        };
+        assert_eq!(
+            errors[0].error,
+            CheckError::ArgumentCount {
+                subroutine: String::from("Main.main"),
+                n_parameters: 0,
+                n_arguments: 1,
+            }
+            .at(errors[0].span())
+        );
+        assert_eq!(errors[0].span().line, 1);
+    }
+    #[test]
+    fn declaration_errors() {
+        let main = "class Main {
+            static Strin s;
+            function Poin f(Arra a) { var Int i; return; }
+            function void f() { return; }
+        }";
+        let other = "class Main { }";
+        assert_eq!(
+            check(&[main, other]),
+            vec![
+                "Main.jack, line 4: Main.f is defined twice",
+                "Main.jack, line 1: Class Main is already defined by Main.jack",
+                "Main.jack, line 2: Unknown type Strin",
+                "Main.jack, line 3: Unknown type Poin",
+                "Main.jack, line 3: Unknown type Arra",
+                "Main.jack, line 3: Unknown type Int",
+            ]
+        );
+    }
+}
@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
-use crate::{Parser, ParserReturn, ReservedKeywords, Token, TokenType, VM_OPS};
+use crate::{
+    ast::{
+        BinaryOp, Class, ClassVarKind, Expression, KeywordConstant, Statement, SubroutineCall,
+        SubroutineDec, SubroutineKind, Term, Type, UnaryOp,
+    },
+    CodeGenError, JackError, ParserOptions, VM_OPS,
+};
 
 #[derive(Debug)]
 pub enum VariableKind {
@@ -22,29 +28,23 @@ impl VariableKind {
     }
 }
 
-#[derive(Debug)]
-pub enum VariableType {
-    Int,
-    Char,
-    Boolean,
-    Identifier(String),
-}
-
 // type
 // kind
 // no.
 #[derive(Debug)]
 pub struct VariableMetaData {
-    _type: VariableType,
-    kind: VariableKind,
-    number: usize,
+    pub _type: Type,
+    pub kind: VariableKind,
+    pub number: usize,
 }
 
-type CodeGenResult = Result<(), String>;
+type CodeGenResult = Result<(), CodeGenError>;
+type PassResult = Result<(), JackError>;
 
-/// Used by the Parser
+/// Pass over the AST of a class, generating its VM code
 /// subroutine symbol table is reset by the subroutine dec before used
 pub struct CodeGen {
+    class_name: String,
     pub class_symbol_table: HashMap<String, VariableMetaData>,
     pub subroutine_symbol_table: HashMap<String, VariableMetaData>,
     vm_code: Vec<String>,
@@ -53,76 +53,266 @@ pub struct CodeGen {
     argument_counter: usize,
     local_counter: usize,
     flow_counter: usize,
+    pub native_math: bool, // mul and div instead of Math.multiply and Math.divide
 }
 impl CodeGen {
-    fn new() -> Self {
-        Self::default()
+    pub fn new(options: &ParserOptions) -> Self {
+        Self {
+            native_math: options.native_math,
+            ..Self::default()
+        }
     }
     pub fn gen_vm_code(&self) -> String {
         self.vm_code.join("\n")
     }
+
+    // The pass over the AST
+    pub fn class(&mut self, class: &Class) -> PassResult {
+        self.class_name = class.name.name.clone();
+        for var_dec in &class.var_decs {
+            for name in &var_dec.names {
+                self.insert_class_variable(name.name.clone(), &var_dec.kind, &var_dec._type)
+                    .map_err(|e| e.at(name.span))?;
+            }
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine_dec(subroutine)?;
+        }
+        Ok(())
+    }
+    fn subroutine_dec(&mut self, subroutine: &SubroutineDec) -> PassResult {
+        self.push_comment(format!("start subroutine_dec, {}", subroutine.span.line));
+        // decalring a new subroutine
+        self.reset_subroutine_table(self.class_name.clone());
+        for parameter in &subroutine.parameters {
+            self.insert_subroutine_variable(
+                parameter.name.name.clone(),
+                VariableKind::Argument,
+                &parameter._type,
+            )
+            .map_err(|e| e.at(parameter.name.span))?;
+        }
+        for var_dec in &subroutine.var_decs {
+            for name in &var_dec.names {
+                self.insert_subroutine_variable(
+                    name.name.clone(),
+                    VariableKind::Local,
+                    &var_dec._type,
+                )
+                .map_err(|e| e.at(name.span))?;
+            }
+        }
+
+        // declare the function
+        self.push_function(&self.class_name.clone(), &subroutine.name.name);
+        match subroutine.kind {
+            SubroutineKind::Constructor => {
+                self.constructor_alloc();
+            }
+            SubroutineKind::Method => {
+                self.push_variable(&String::from("this"))
+                    .map_err(|e| e.at(subroutine.span))?;
+                self.pop_pointer(0);
+            }
+            SubroutineKind::Function => {}
+        }
+        self.statements(&subroutine.statements)?;
+        #[cfg(feature = "debug")]
+        {
+            println!("{}.{} symbol table", self.class_name, subroutine.name.name);
+            println!("{:?}", self.subroutine_symbol_table);
+            println!();
+        }
+        self.push_comment(format!("end subroutine_dec, {}", subroutine.span.line));
+        Ok(())
+    }
+    fn statements(&mut self, statements: &[Statement]) -> PassResult {
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+    fn statement(&mut self, statement: &Statement) -> PassResult {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => {
+                if let Some(index) = index {
+                    self.push_variable(&name.name)
+                        .map_err(|e| e.at(name.span))?;
+                    self.expression(index)?;
+                    self.push_op(VM_OPS::ADD);
+                }
+                self.expression(value)?;
+                // pop the expression into the variable
+                if index.is_some() {
+                    self.pop_temp(0);
+                    self.pop_pointer(1);
+                    self.push_temp();
+                    self.pop_that();
+                } else {
+                    self.pop_variable(&name.name).map_err(|e| e.at(name.span))?;
+                }
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+                span,
+            } => {
+                self.push_comment(format!("start if, {}", span.line));
+                // expression is at the top of the stack
+                self.expression(condition)?;
+                self.push_op(VM_OPS::NOT);
+                let l1 = self.get_flow_counter(&self.class_name.clone());
+                let l2 = self.get_flow_counter(&self.class_name.clone());
+                self.push_if_goto(&l1);
+                self.statements(statements)?;
+                self.push_goto(&l2);
+                self.push_label(&l1); // Condition did not pass, so we jump to else
+                if let Some(else_statements) = else_statements {
+                    self.statements(else_statements)?;
+                }
+                self.push_label(&l2);
+                self.push_comment(format!("end if, {}", span.line));
+            }
+            Statement::While {
+                condition,
+                statements,
+                span,
+            } => {
+                self.push_comment(format!("start while, {}", span.line));
+                let l1 = self.get_flow_counter(&self.class_name.clone());
+                let l2 = self.get_flow_counter(&self.class_name.clone());
+                self.push_label(&l1);
+                self.expression(condition)?;
+                self.push_op(VM_OPS::NOT);
+                self.push_if_goto(&l2);
+                self.statements(statements)?;
+                self.push_goto(&l1);
+                self.push_label(&l2);
+                self.push_comment(format!("end while, {}", span.line));
+            }
+            Statement::Do { call, .. } => {
+                self.subroutine_call(call)?;
+                // needs to remove uncessary value from top of stack from function return
+                self.pop_temp(0);
+            }
+            Statement::Return { value, .. } => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.push_integer_constant(0),
+                }
+                self.push_return();
+            }
+        }
+        Ok(())
+    }
+    fn expression(&mut self, expression: &Expression) -> PassResult {
+        self.term(&expression.term)?;
+        for (op, term) in &expression.ops {
+            self.term(term)?;
+            // push the op after the term
+            match op {
+                BinaryOp::Add => self.push_op(VM_OPS::ADD),
+                BinaryOp::Sub => self.push_op(VM_OPS::SUB),
+                BinaryOp::And => self.push_op(VM_OPS::AND),
+                BinaryOp::Or => self.push_op(VM_OPS::OR),
+                BinaryOp::GreaterThan => self.push_op(VM_OPS::GT),
+                BinaryOp::LessThan => self.push_op(VM_OPS::LT),
+                BinaryOp::Equal => self.push_op(VM_OPS::EQ),
+                BinaryOp::Mul => self.call_math_multiply(),
+                BinaryOp::Div => self.call_math_divide(),
+            }
+        }
+        Ok(())
+    }
+    fn term(&mut self, term: &Term) -> PassResult {
+        match term {
+            Term::Integer(x, _) => self.push_integer_constant(*x as i16),
+            Term::String(string, span) => {
+                self.push_string_constant(string).map_err(|e| e.at(*span))?
+            }
+            Term::Keyword(KeywordConstant::This, _) => self.push_pointer(0),
+            Term::Keyword(keyword, _) => self.push_keyword_constant(keyword),
+            Term::Variable(name) => self
+                .push_variable(&name.name)
+                .map_err(|e| e.at(name.span))?,
+            Term::Index { name, index, .. } => {
+                self.push_variable(&name.name)
+                    .map_err(|e| e.at(name.span))?;
+                self.expression(index)?;
+                self.push_op(VM_OPS::ADD);
+                self.pop_pointer(1); // set array base address + idx
+                self.push_that();
+            }
+            Term::Call(call) => self.subroutine_call(call)?,
+            Term::Parenthesized(expression, _) => self.expression(expression)?,
+            Term::Unary { op, term, .. } => {
+                self.term(term)?;
+                match op {
+                    UnaryOp::Neg => self.push_op(VM_OPS::NEG),
+                    UnaryOp::Not => self.push_op(VM_OPS::NOT),
+                }
+            }
+        }
+        Ok(())
+    }
+    fn subroutine_call(&mut self, call: &SubroutineCall) -> PassResult {
+        self.push_comment(format!("start subroutine_call, {}", call.span.line));
+        let n_args = call.arguments.len() as i16 + 1;
+        match &call.receiver {
+            None => {
+                // since this is a call within the class, we need to set this
+                self.push_pointer(0);
+                self.expression_list(&call.arguments)?;
+                self.push_call(&self.class_name.clone(), &call.name.name, n_args);
+            }
+            Some(receiver) => {
+                // error means we are trying to call a Class function, push dummy 0 for dummy this arg
+                if self.push_variable(&receiver.name).is_err() {
+                    self.push_comment(String::from("dummy 0"));
+                    self.push_integer_constant(0);
+                };
+                self.expression_list(&call.arguments)?;
+                self.complex_subroutine_call(&receiver.name, &call.name.name, n_args)
+                    .map_err(|e| e.at(receiver.span))?;
+            }
+        }
+        self.push_comment(format!("end subroutine_call, {}", call.span.line));
+        Ok(())
+    }
+    fn expression_list(&mut self, expressions: &[Expression]) -> PassResult {
+        for expression in expressions {
+            self.expression(expression)?;
+        }
+        Ok(())
+    }
     pub fn insert_class_variable(
         &mut self,
         name: String,
-        kind: &Token,
-        _type: &Token,
-        source: &[char],
-    ) -> ParserReturn {
-        let variable_kind = match kind._type {
-            TokenType::Keyword(ReservedKeywords::Static) => VariableKind::Static,
-            TokenType::Keyword(ReservedKeywords::Field) => VariableKind::Field,
-            _ => {
-                return Err(Parser::error_expected_token_type(
-                    kind,
-                    &[
-                        TokenType::Keyword(ReservedKeywords::Static),
-                        TokenType::Keyword(ReservedKeywords::Field),
-                    ],
-                    source,
-                ))
-            }
-        };
-        let variable_type = match &_type._type {
-            TokenType::Keyword(ReservedKeywords::Int) => VariableType::Int,
-            TokenType::Keyword(ReservedKeywords::Char) => VariableType::Char,
-            TokenType::Keyword(ReservedKeywords::Boolean) => VariableType::Boolean,
-            TokenType::Identifier => VariableType::Identifier(_type.get_source(source)),
-            _ => {
-                return Err(Parser::error_expected_token_type(
-                    _type,
-                    &[
-                        TokenType::Keyword(ReservedKeywords::Int),
-                        TokenType::Keyword(ReservedKeywords::Char),
-                        TokenType::Keyword(ReservedKeywords::Boolean),
-                        TokenType::Identifier,
-                    ],
-                    source,
-                ))
-            }
-        };
-        let number = match variable_kind {
-            VariableKind::Static => {
+        kind: &ClassVarKind,
+        _type: &Type,
+    ) -> CodeGenResult {
+        let (variable_kind, number) = match kind {
+            ClassVarKind::Static => {
                 self.static_counter += 1;
-                self.static_counter - 1
+                (VariableKind::Static, self.static_counter - 1)
             }
-            VariableKind::Field => {
+            ClassVarKind::Field => {
                 self.field_counter += 1;
-                self.field_counter - 1
+                (VariableKind::Field, self.field_counter - 1)
             }
-            _ => panic!("Should not fail insert class variable"),
         };
         match self.class_symbol_table.insert(
             name.clone(),
             VariableMetaData {
-                _type: variable_type,
+                _type: _type.clone(),
                 kind: variable_kind,
                 number,
             },
         ) {
-            Some(_) => Err(format!(
-                "Class variable {} is defined again on line {}",
-                name, kind.line
-            )),
+            Some(_) => Err(CodeGenError::VariableDeclaredTwice(name)),
             None => Ok(()),
         }
     }
@@ -131,7 +321,7 @@ impl CodeGen {
         self.subroutine_symbol_table.insert(
             String::from("this"),
             VariableMetaData {
-                _type: VariableType::Identifier(class_name),
+                _type: Type::Class(class_name),
                 number: 0,
                 kind: VariableKind::Argument,
             },
@@ -143,36 +333,11 @@ impl CodeGen {
         &mut self,
         name: String,
         variable_kind: VariableKind,
-        _type: &Token,
-        source: &[char],
-    ) -> ParserReturn {
-        if let Some(existing_variable_meta_data) = self.class_symbol_table.get(&name) {
-            return Err(format!("Variable with the same name declared again at line {}, was declared previously as a {:?} variable", _type.line, existing_variable_meta_data.kind));
-        }
-        match variable_kind {
-            VariableKind::Local | VariableKind::Argument => {}
-            _ => {
-                panic!("Wow, the person that coded this should not have done this. insert subroutine variable failed on kind")
-            }
+        _type: &Type,
+    ) -> CodeGenResult {
+        if self.class_symbol_table.contains_key(&name) {
+            return Err(CodeGenError::ShadowsClassVariable(name));
         }
-        let variable_type = match _type._type {
-            TokenType::Keyword(ReservedKeywords::Int) => VariableType::Int,
-            TokenType::Keyword(ReservedKeywords::Char) => VariableType::Char,
-            TokenType::Keyword(ReservedKeywords::Boolean) => VariableType::Boolean,
-            TokenType::Identifier => VariableType::Identifier(_type.get_source(source)),
-            _ => {
-                return Err(Parser::error_expected_token_type(
-                    _type,
-                    &[
-                        TokenType::Keyword(ReservedKeywords::Int),
-                        TokenType::Keyword(ReservedKeywords::Char),
-                        TokenType::Keyword(ReservedKeywords::Boolean),
-                        TokenType::Identifier,
-                    ],
-                    source,
-                ))
-            }
-        };
         let number = match variable_kind {
             VariableKind::Argument => {
                 self.argument_counter += 1;
@@ -182,22 +347,19 @@ impl CodeGen {
                 self.local_counter += 1;
                 self.local_counter - 1
             }
-            _ => panic!("Should not fail insert class variable"),
+            _ => {
+                panic!("Wow, the person that coded this should not have done this. insert subroutine variable failed on kind")
+            }
         };
         match self.subroutine_symbol_table.insert(
             name.clone(),
             VariableMetaData {
-                _type: variable_type,
+                _type: _type.clone(),
                 kind: variable_kind,
                 number,
             },
         ) {
-            // Discrepency with insert_class_variable since we don't pass in a kind token
-            // We use _type instead of kind.line
-            Some(_) => Err(format!(
-                "Class variable {} is defined again on line {}",
-                name, _type.line
-            )),
+            Some(_) => Err(CodeGenError::VariableDeclaredTwice(name)),
             None => Ok(()),
         }
     }
@@ -205,6 +367,30 @@ impl CodeGen {
     pub fn push_integer_constant(&mut self, x: i16) {
         self.vm_code.push(format!("push constant {}", x));
     }
+    /// true is -1, false and null are 0
+    pub fn push_keyword_constant(&mut self, keyword: &KeywordConstant) {
+        self.push_integer_constant(0);
+        if let KeywordConstant::True = keyword {
+            self.push_op(VM_OPS::NOT);
+        }
+    }
+    /// String.new(length), then String.appendChar(c) for every character
+    pub fn push_string_constant(&mut self, string: &str) -> CodeGenResult {
+        let chars = string.chars().collect::<Vec<char>>();
+        // Dummy 0 for the String class
+        self.push_integer_constant(0);
+        self.push_integer_constant(chars.len() as i16);
+        self.push_call("String", "new", 2);
+        for c in chars {
+            // Jack character set, printable ASCII
+            if !(' '..='~').contains(&c) {
+                return Err(CodeGenError::CharacterNotInCharset(c));
+            }
+            self.push_integer_constant(c as i16);
+            self.push_call("String", "appendChar", 2);
+        }
+        Ok(())
+    }
     pub fn push_pointer(&mut self, no: i16) {
         self.vm_code.push(format!("push pointer {}", no))
     }
@@ -215,13 +401,16 @@ impl CodeGen {
         self.vm_code.push(op.to_vm_string());
     }
     // handling variables
-    fn get_variable_metadata(&self, variable_name: &String) -> Result<&VariableMetaData, String> {
+    pub fn get_variable_metadata(
+        &self,
+        variable_name: &String,
+    ) -> Result<&VariableMetaData, CodeGenError> {
         if let Some(variable) = self.subroutine_symbol_table.get(variable_name) {
             Ok(variable)
         } else if let Some(variable) = self.class_symbol_table.get(variable_name) {
             Ok(variable)
         } else {
-            return Err(format!("Variable {} not declared before", variable_name));
+            Err(CodeGenError::UndeclaredVariable(variable_name.clone()))
         }
     }
     pub fn push_variable(&mut self, variable_name: &String) -> CodeGenResult {
@@ -292,8 +481,10 @@ impl CodeGen {
             .iter()
             .filter(|(_, v)| matches!(v.kind, VariableKind::Field))
             .count();
+        // Dummy 0 for the Memory class
+        self.push_integer_constant(0);
         self.push_integer_constant(n_fields as i16);
-        self.vm_code.push(String::from("call Memory.alloc 1"));
+        self.push_call("Memory", "alloc", 2);
         self.pop_pointer(0);
     }
     pub fn push_call(&mut self, class_name: &str, function_name: &str, n_args: i16) {
@@ -311,15 +502,15 @@ impl CodeGen {
             return Ok(());
         };
         match &variable_meta_data._type {
-            VariableType::Identifier(class_name) => {
+            Type::Class(class_name) => {
                 self.vm_code
                     .push(format!("call {}.{} {}", class_name, l2, n_args));
             }
-            _ => {
-                return Err(format!(
-                    "subroutine call on, {}, with type {:?} is not possible",
-                    l1, variable_meta_data._type
-                ))
+            _type => {
+                return Err(CodeGenError::CallOnPrimitive {
+                    variable: l1.to_string(),
+                    _type: _type.clone(),
+                })
             }
         }
         Ok(())
@@ -327,6 +518,7 @@ impl CodeGen {
     pub fn push_return(&mut self) {
         self.vm_code.push(String::from("return"));
     }
+    #[cfg_attr(not(feature = "debug"), allow(unused_variables))]
     pub fn push_comment(&mut self, comment: String) {
         #[cfg(feature = "debug")]
         {
@@ -335,19 +527,26 @@ impl CodeGen {
     }
     // For easier Math syntax
     /// For Math.multiply and Math.divide
+    /// Puts the dummy argument below x and y, keeping x before y
     fn setup_math_call(&mut self) {
         self.pop_temp(0);
         self.pop_temp(1);
         self.push_integer_constant(0);
-        self.push_temp_idx(0);
         self.push_temp_idx(1);
+        self.push_temp_idx(0);
     }
     /// Called when encountering * in the parser, after both terms are already added to the vm_code
     pub fn call_math_multiply(&mut self) {
+        if self.native_math {
+            return self.push_op(VM_OPS::MUL);
+        }
         self.setup_math_call();
         self.push_call("Math", "multiply", 3);
     }
     pub fn call_math_divide(&mut self) {
+        if self.native_math {
+            return self.push_op(VM_OPS::DIV);
+        }
         self.setup_math_call();
         self.push_call("Math", "divide", 3);
     }
@@ -356,6 +555,7 @@ impl CodeGen {
 impl Default for CodeGen {
     fn default() -> Self {
         Self {
+            class_name: String::new(),
             class_symbol_table: HashMap::default(),
             subroutine_symbol_table: HashMap::default(),
             vm_code: Vec::default(),
@@ -364,6 +564,7 @@ impl Default for CodeGen {
             argument_counter: 0,
             local_counter: 0,
             flow_counter: 0,
+            native_math: false,
         }
     }
 }
@@ -0,0 +1,104 @@
+//! Comparison of an output against a reference file, like the TextComparer of the course
+use std::fmt;
+
+/// First difference of two texts, lines counted from 1
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub line: usize,
+    pub text: String, // Line of the output, empty past its end
+    pub reference_line: usize,
+    pub reference_text: String,
+}
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {}, reference line {}: {}",
+            self.line,
+            describe(&self.text),
+            self.reference_line,
+            describe(&self.reference_text)
+        )
+    }
+}
+fn describe(text: &str) -> String {
+    if text.is_empty() {
+        String::from("end of file")
+    } else {
+        format!("`{}`", text)
+    }
+}
+
+/// None when the texts are the same but for whitespace
+pub fn compare_ignoring_whitespace(output: &str, reference: &str) -> Option<Mismatch> {
+    let mut output_chars = significant_chars(output);
+    let mut reference_chars = significant_chars(reference);
+    loop {
+        match (output_chars.next(), reference_chars.next()) {
+            (None, None) => return None,
+            (Some((c, _)), Some((reference_c, _))) if c == reference_c => {}
+            (found, expected) => {
+                let (line, text) = line_at(output, found);
+                let (reference_line, reference_text) = line_at(reference, expected);
+                return Some(Mismatch {
+                    line,
+                    text,
+                    reference_line,
+                    reference_text,
+                });
+            }
+        }
+    }
+}
+
+/// Chars that are not whitespace, with the index of their line
+fn significant_chars(text: &str) -> impl Iterator<Item = (char, usize)> + '_ {
+    text.lines().enumerate().flat_map(|(i, line)| {
+        line.chars()
+            .filter(|c| !c.is_whitespace())
+            .map(move |c| (c, i))
+    })
+}
+/// Line number and trimmed text of a char, the line after the last one past the end
+fn line_at(text: &str, c: Option<(char, usize)>) -> (usize, String) {
+    match c {
+        Some((_, i)) => (
+            i + 1,
+            text.lines().nth(i).unwrap_or_default().trim().to_string(),
+        ),
+        None => (text.lines().count() + 1, String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare() {
+        let reference = "<tokens>\r\n<keyword> class </keyword>\r\n<identifier> Main </identifier>\r\n</tokens>\r\n";
+        assert_eq!(
+            compare_ignoring_whitespace(
+                "<tokens>\n  <keyword>class</keyword>\n<identifier> Main </identifier>\n</tokens>",
+                reference
+            ),
+            None
+        );
+        let mismatch = compare_ignoring_whitespace(
+            "<tokens>\n<keyword> class </keyword>\n\n<identifier> Maim </identifier>\n</tokens>\n",
+            reference,
+        )
+        .unwrap();
+        assert_eq!(
+            mismatch.to_string(),
+            "line 4: `<identifier> Maim </identifier>`, reference line 3: `<identifier> Main </identifier>`"
+        );
+        let mismatch =
+            compare_ignoring_whitespace("<tokens>\n<keyword> class </keyword>\n", reference)
+                .unwrap();
+        assert_eq!(
+            mismatch.to_string(),
+            "line 3: end of file, reference line 3: `<identifier> Main </identifier>`"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::{error, fmt};
+
+use crate::{ast::Span, JackError};
+
+/// Error at a place in a source file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file_name: String, // Empty until known, the Tokenizer and Parser only see the source
+    pub column: usize,     // Counted in chars from 1, like the line
+    pub error: JackError,
+}
+impl Diagnostic {
+    pub fn new(error: JackError, source: &[char]) -> Self {
+        let start = error.span().start.min(source.len());
+        let line_start = source[..start]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |i| i + 1);
+        Self {
+            file_name: String::new(),
+            column: start - line_start + 1,
+            error,
+        }
+    }
+    pub fn span(&self) -> Span {
+        self.error.span()
+    }
+
+    /// The error, followed by its line of source with carets under the span
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+        let line = source.lines().nth(span.line - 1).unwrap_or_default();
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        // Tabs are kept, so the carets line up with the source
+        let padding = line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let n_carets = span
+            .length
+            .min(line.chars().count().saturating_sub(self.column - 1))
+            .max(1);
+        format!(
+            "{}\n{} |\n{} | {}\n{} | {}{}",
+            self,
+            gutter,
+            line_number,
+            line,
+            gutter,
+            padding,
+            "^".repeat(n_carets)
+        )
+    }
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.file_name.is_empty() {
+            write!(f, "{}, ", self.file_name)?;
+        }
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.span().line,
+            self.column,
+            self.error
+        )
+    }
+}
+impl error::Error for Diagnostic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parser;
+
+    #[test]
+    fn render() {
+        let source = "class Main {\n\tfield int x\n}";
+        let mut diagnostic = Parser::parse(source).unwrap_err().remove(0);
+        assert_eq!(diagnostic.column, 1);
+        assert_eq!(
+            diagnostic.to_string(),
+            "line 3, column 1: Expected ';', got }"
+        );
+
+        diagnostic.file_name = String::from("Main.jack");
+        diagnostic.column = 2;
+        if let JackError::Syntax { span, .. } = &mut diagnostic.error {
+            *span = Span {
+                start: 14,
+                length: 5,
+                line: 2,
+            };
+        }
+        assert_eq!(
+            diagnostic.render(source),
+            "Main.jack, line 2, column 2: Expected ';', got }\n  |\n2 | \tfield int x\n  | \t^^^^^"
+        );
+    }
+}
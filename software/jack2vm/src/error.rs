@@ -0,0 +1,371 @@
+use std::{error, fmt};
+
+use crate::{ast::Span, ast::Type, TokenType};
+
+/// Error of the Tokenizer, Parser, CodeGen or the checks across a project, at a span of the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum JackError {
+    // Source that is not a token
+    Lexical {
+        error: LexicalError,
+        span: Span,
+    },
+    // Token that does not fit the grammar
+    Syntax {
+        expected: Expected,
+        found: TokenType,
+        text: String, // Source of the found token
+        span: Span,
+    },
+    // Grammar that cannot be compiled to VM code, or does not fit the rest of the project
+    Semantic {
+        error: SemanticError,
+        span: Span,
+    },
+}
+impl JackError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lexical { span, .. }
+            | Self::Syntax { span, .. }
+            | Self::Semantic { span, .. } => *span,
+        }
+    }
+}
+impl fmt::Display for JackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lexical { error, .. } => write!(f, "{}", error),
+            Self::Syntax {
+                expected,
+                found,
+                text,
+                ..
+            } => {
+                write!(f, "Expected {}, got ", expected)?;
+                match found {
+                    TokenType::EOF => write!(f, "{}", found),
+                    _ => write!(f, "{}", text),
+                }
+            }
+            Self::Semantic { error, .. } => write!(f, "{}", error),
+        }
+    }
+}
+impl error::Error for JackError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexicalError {
+    UnknownCharacter(char),
+    IntegerTooLarge(String),
+    UnclosedString,
+    UnclosedComment,
+}
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCharacter(c) => write!(f, "Unknown character {:?}", c),
+            Self::IntegerTooLarge(integer) => write!(
+                f,
+                "Integer {} is too large, should be within 0 and 32767",
+                integer
+            ),
+            Self::UnclosedString => write!(f, "String is not closed"),
+            Self::UnclosedComment => write!(f, "Comment is not closed"),
+        }
+    }
+}
+
+/// What the Parser expected instead of the token it found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expected {
+    Tokens(Vec<TokenType>), // Any of them
+    Term,
+}
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tokens(_types) => write!(
+                f,
+                "{}",
+                _types
+                    .iter()
+                    .map(|_type| _type.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" or ")
+            ),
+            Self::Term => write!(f, "a term"),
+        }
+    }
+}
+
+/// Semantic error, by the pass that finds it
+#[derive(Debug, Clone, PartialEq)]
+pub enum SemanticError {
+    CodeGen(CodeGenError),
+    Check(CheckError),
+    Type(TypeError),
+}
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CodeGen(error) => write!(f, "{}", error),
+            Self::Check(error) => write!(f, "{}", error),
+            Self::Type(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodeGenError {
+    UndeclaredVariable(String),
+    VariableDeclaredTwice(String),
+    ShadowsClassVariable(String), // Subroutine variable with the name of a class variable
+    CallOnPrimitive { variable: String, _type: Type },
+    CharacterNotInCharset(char),
+}
+impl CodeGenError {
+    pub fn at(self, span: Span) -> JackError {
+        JackError::Semantic {
+            error: SemanticError::CodeGen(self),
+            span,
+        }
+    }
+}
+impl fmt::Display for CodeGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndeclaredVariable(name) => write!(f, "Variable {} is not declared", name),
+            Self::VariableDeclaredTwice(name) => write!(f, "Variable {} is declared twice", name),
+            Self::ShadowsClassVariable(name) => {
+                write!(f, "Variable {} is already declared by the class", name)
+            }
+            Self::CallOnPrimitive { variable, _type } => write!(
+                f,
+                "Cannot call a subroutine on {}, it is of type {}",
+                variable,
+                _type.name()
+            ),
+            Self::CharacterNotInCharset(c) => {
+                write!(f, "Character {:?} is not in the Jack character set", c)
+            }
+        }
+    }
+}
+
+/// Error of check_project, subroutines are named Class.subroutine
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckError {
+    ClassDefinedTwice {
+        class: String,
+        first_file: String,
+    },
+    SubroutineDefinedTwice(String),
+    UnknownType(String),
+    UnknownClassOrVariable(String),
+    UndefinedSubroutine(String),
+    MethodWithoutObject(String), // Called on a class
+    MethodWithoutThis(String),   // Called from a function without a receiver
+    ArgumentCount {
+        subroutine: String,
+        n_parameters: usize,
+        n_arguments: usize,
+    },
+}
+impl CheckError {
+    pub fn at(self, span: Span) -> JackError {
+        JackError::Semantic {
+            error: SemanticError::Check(self),
+            span,
+        }
+    }
+}
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ClassDefinedTwice { class, first_file } => {
+                write!(f, "Class {} is already defined by {}", class, first_file)
+            }
+            Self::SubroutineDefinedTwice(name) => write!(f, "{} is defined twice", name),
+            Self::UnknownType(name) => write!(f, "Unknown type {}", name),
+            Self::UnknownClassOrVariable(name) => write!(f, "Unknown class or variable {}", name),
+            Self::UndefinedSubroutine(name) => write!(f, "{} is not defined", name),
+            Self::MethodWithoutObject(name) => write!(
+                f,
+                "{} is a method, it needs an object to be called on",
+                name
+            ),
+            Self::MethodWithoutThis(name) => write!(
+                f,
+                "{} is a method, a function has no this to call it on",
+                name
+            ),
+            Self::ArgumentCount {
+                subroutine,
+                n_parameters,
+                n_arguments,
+            } => write!(
+                f,
+                "{} takes {} arguments, got {}",
+                subroutine, n_parameters, n_arguments
+            ),
+        }
+    }
+}
+
+/// Error of check_types, types are given by name as null and unknown are not a Type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeError {
+    Assign {
+        value: String,
+        variable: String,
+        _type: String,
+    },
+    ReturnFromVoid(String),
+    MissingReturnValue {
+        subroutine: String,
+        return_type: String,
+    },
+    Return {
+        value: String,
+        subroutine: String,
+        return_type: String,
+    },
+    Condition(String),
+    BinaryOperands {
+        op: &'static str,
+        left: String,
+        right: String,
+    },
+    UnaryOperand {
+        op: &'static str,
+        operand: String,
+    },
+    CallOnPrimitive {
+        variable: String,
+        subroutine: String,
+        _type: String,
+    },
+}
+impl TypeError {
+    pub fn at(self, span: Span) -> JackError {
+        JackError::Semantic {
+            error: SemanticError::Type(self),
+            span,
+        }
+    }
+}
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Assign {
+                value,
+                variable,
+                _type,
+            } => write!(
+                f,
+                "Cannot assign {} to {} of type {}",
+                value, variable, _type
+            ),
+            Self::ReturnFromVoid(subroutine) => {
+                write!(f, "{} is void, it cannot return a value", subroutine)
+            }
+            Self::MissingReturnValue {
+                subroutine,
+                return_type,
+            } => write!(
+                f,
+                "Missing return value, {} returns {}",
+                subroutine, return_type
+            ),
+            Self::Return {
+                value,
+                subroutine,
+                return_type,
+            } => write!(
+                f,
+                "Cannot return {} from {}, it returns {}",
+                value, subroutine, return_type
+            ),
+            Self::Condition(_type) => write!(f, "Condition must be boolean, got {}", _type),
+            Self::BinaryOperands { op, left, right } => {
+                write!(f, "Cannot use {} on {} and {}", op, left, right)
+            }
+            Self::UnaryOperand { op, operand } => write!(f, "Cannot use {} on {}", op, operand),
+            Self::CallOnPrimitive {
+                variable,
+                subroutine,
+                _type,
+            } => write!(
+                f,
+                "Cannot call {}.{}, {} is of type {}",
+                variable, subroutine, variable, _type
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Parser, Symbols};
+
+    fn first_error(source: &str) -> JackError {
+        Parser::parse(source).unwrap_err().remove(0).error
+    }
+
+    #[test]
+    fn kinds() {
+        assert!(matches!(
+            first_error("class Main { # }"),
+            JackError::Lexical {
+                error: LexicalError::UnknownCharacter('#'),
+                ..
+            }
+        ));
+        match first_error("class Main { field int x }") {
+            JackError::Syntax {
+                expected: Expected::Tokens(expected),
+                found,
+                span,
+                ..
+            } => {
+                assert_eq!(expected, vec![TokenType::Symbol(Symbols::SemiColon)]);
+                assert_eq!(found, TokenType::Symbol(Symbols::RightBrace));
+                assert_eq!(span.start, 25);
+            }
+            e => panic!("Expected a syntax error, got {:?}", e),
+        }
+        assert_eq!(
+            first_error("class Main { function void f() { let x = 1; return; } }"),
+            JackError::Semantic {
+                error: SemanticError::CodeGen(CodeGenError::UndeclaredVariable(String::from("x"))),
+                span: Span {
+                    start: 37,
+                    length: 1,
+                    line: 1
+                },
+            }
+        );
+    }
+    #[test]
+    fn display() {
+        assert_eq!(
+            first_error("class Main { field int x }").to_string(),
+            "Expected ';', got }"
+        );
+        assert_eq!(
+            first_error("class Main { field int").to_string(),
+            "Expected identifier, got end of file"
+        );
+        assert_eq!(
+            first_error("class Main { function void f() { return 100000; } }").to_string(),
+            "Integer 100000 is too large, should be within 0 and 32767"
+        );
+        assert_eq!(
+            first_error("class Main { function void f() { var int x; do x.g(); return; } }")
+                .to_string(),
+            "Cannot call a subroutine on x, it is of type int"
+        );
+    }
+}
@@ -1,11 +1,29 @@
+pub mod ast;
+mod checker;
 mod code_gen;
+mod compare;
+mod diagnostic;
+mod error;
+mod os_api;
 mod parser;
+mod project;
 mod token;
 mod tokenizer;
+mod type_checker;
 mod vm_ref;
+mod xml;
 
+pub use checker::{check_project, ProjectError, ProjectSymbols, Signature};
 pub use code_gen::{CodeGen, VariableKind};
-pub use parser::{Parser, ParserReturn};
+pub use compare::{compare_ignoring_whitespace, Mismatch};
+pub use diagnostic::Diagnostic;
+pub use error::{
+    CheckError, CodeGenError, Expected, JackError, LexicalError, SemanticError, TypeError,
+};
+pub use parser::{Parser, ParserCodeOutput, ParserOptions};
+pub use project::compile_project;
 pub use tokenizer::Tokenizer;
+pub use type_checker::{check_types, TypeCheck};
 pub use vm_ref::VM_OPS;
+pub use xml::{class_to_xml, tokens_to_xml};
 pub use {token::ReservedKeywords, token::Symbols, token::Token, token::TokenType};
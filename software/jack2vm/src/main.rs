@@ -1,11 +1,30 @@
-use std::{env, fs, path::Path};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
 
-use jack2vm::Parser;
+use jack2vm::{
+    class_to_xml, compare_ignoring_whitespace, compile_project, tokens_to_xml, Diagnostic, Parser,
+    ParserOptions, Tokenizer, TypeCheck,
+};
+
+const USAGE: &str = "Usage: jack2vm [--native-math] [--type-check lenient|strict] [--tokens] [--xml] [--compare] [--output folder] [path]";
 
 type ProgResult = Result<(), String>;
 
-fn compile_jack_to_vm(file_path: &Path) -> ProgResult {
-    for entry in fs::read_dir(file_path).map_err(|e| e.to_string())? {
+/// XML of the syntax analyzer of the course, given any of them no VM code is written
+#[derive(Default)]
+struct XmlOptions {
+    tokens: bool,  // XxxT.xml
+    xml: bool,     // Xxx.xml
+    compare: bool, // Against the files of the same name in the folder, instead of writing them
+}
+
+/// (path, file name, source) of the .jack files in folder
+fn read_jack_files(folder: &Path) -> Result<Vec<(PathBuf, String, String)>, String> {
+    let mut files = Vec::default();
+    for entry in fs::read_dir(folder).map_err(|e| e.to_string())? {
         let entry = entry.map_err(|e| e.to_string())?;
         let entry_path = entry.path();
         if !entry_path.is_file() {
@@ -19,47 +38,163 @@ fn compile_jack_to_vm(file_path: &Path) -> ProgResult {
             }
         }
         let source = fs::read_to_string(entry_path.clone()).expect("Read the file contents");
-        let parser_code_output = Parser::parse(&source).map_err(|e| {
-            format!(
-                "Compilation error for file {}\n{}",
-                entry_path.to_str().unwrap(),
-                e
-            )
-        })?;
-        #[cfg(feature = "xml")]
-        {
-            let mut ast_file_path = entry_path.to_path_buf();
-            ast_file_path.set_extension("xml");
-            fs::write(ast_file_path, parser_code_output).map_err(|e| e.to_string())?;
-        }
-        let mut vm_file_path = entry_path.to_path_buf();
-        vm_file_path.set_extension("vm");
+        let file_name = entry_path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        files.push((entry_path, file_name, source));
+    }
+    Ok(files)
+}
+
+fn compile_jack_to_vm(file_path: &Path, output: &Path, options: &ParserOptions) -> ProgResult {
+    let (paths, files): (Vec<PathBuf>, Vec<(String, String)>) = read_jack_files(file_path)?
+        .into_iter()
+        .map(|(path, file_name, source)| (path, (file_name, source)))
+        .unzip();
+    // Every file is checked against the others, before any is written
+    let outputs = compile_project(&files, options)?;
+    for (entry_path, parser_code_output) in paths.iter().zip(outputs) {
+        let parser_code_output = match parser_code_output {
+            Some(parser_code_output) => parser_code_output,
+            None => continue,
+        };
+        let vm_file_path = output.join(entry_path.with_extension("vm").file_name().unwrap());
         fs::write(vm_file_path, parser_code_output.vm).map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
-fn compile_folder(file_path: &str) -> ProgResult {
+/// Tokens and parse tree of every file on its own, like the syntax analyzer of the course
+fn analyze(file_path: &Path, output: &Path, xml_options: &XmlOptions) -> ProgResult {
+    // --compare alone compares both
+    let both = !xml_options.tokens && !xml_options.xml;
+    let mut errors = Vec::default();
+    let mut n_mismatches = 0;
+    for (_, file_name, source) in read_jack_files(file_path)? {
+        let render = |diagnostic: Diagnostic| {
+            let mut diagnostic = diagnostic;
+            diagnostic.file_name = file_name.clone();
+            diagnostic.render(&source)
+        };
+        let chars = source.chars().collect::<Vec<char>>();
+        let tokens = match Tokenizer::generate_tokens(&source) {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                errors.push(render(Diagnostic::new(e, &chars)));
+                continue;
+            }
+        };
+        let class = match Parser::parse_ast(&source) {
+            Ok(Some(class)) => class,
+            // Nothing to analyze
+            Ok(None) => continue,
+            Err(diagnostics) => {
+                errors.extend(diagnostics.into_iter().map(render));
+                continue;
+            }
+        };
+        let stem = file_name.trim_end_matches(".jack");
+        let mut outputs = Vec::default();
+        if xml_options.tokens || both {
+            outputs.push((format!("{}T.xml", stem), tokens_to_xml(&tokens, &chars)));
+        }
+        if xml_options.xml || both {
+            outputs.push((format!("{}.xml", stem), class_to_xml(&class)));
+        }
+        for (xml_file_name, xml) in outputs {
+            if !xml_options.compare {
+                fs::write(output.join(&xml_file_name), xml).map_err(|e| e.to_string())?;
+                continue;
+            }
+            let reference = match fs::read_to_string(file_path.join(&xml_file_name)) {
+                Ok(reference) => reference,
+                Err(_) => {
+                    println!("{}: no reference file", xml_file_name);
+                    continue;
+                }
+            };
+            match compare_ignoring_whitespace(&xml, &reference) {
+                None => println!("{}: same as the reference", xml_file_name),
+                Some(mismatch) => {
+                    println!("{}, {}", xml_file_name, mismatch);
+                    n_mismatches += 1;
+                }
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    if n_mismatches > 0 {
+        return Err(format!(
+            "{} file(s) differ from the reference",
+            n_mismatches
+        ));
+    }
+    Ok(())
+}
+
+fn compile_folder(
+    file_path: &str,
+    output: Option<&PathBuf>,
+    options: &ParserOptions,
+    xml_options: &XmlOptions,
+) -> ProgResult {
     let file_path = Path::new(file_path);
     // Iterate through all the files in the directory
     if !file_path.is_dir() {
         return Err(String::from("Not a directory"));
     }
-    compile_jack_to_vm(file_path)?;
-
-    Ok(())
+    let output = output.map_or(file_path, |output| output.as_path());
+    fs::create_dir_all(output).map_err(|e| e.to_string())?;
+    if xml_options.tokens || xml_options.xml || xml_options.compare {
+        analyze(file_path, output, xml_options)
+    } else {
+        compile_jack_to_vm(file_path, output, options)
+    }
 }
 
-/// Compiles a single .asm file, outputs the binary as outputs/*.hack
+/// Compiles the .jack files of a folder into .vm files next to them
 fn main() -> ProgResult {
     let args: Vec<String> = env::args().collect();
-    if args.len() == 2 {
-        // run main prog
-        if let Err(e) = compile_folder(&args[1]) {
-            println!("{}", e);
+    let mut options = ParserOptions::default();
+    let mut xml_options = XmlOptions::default();
+    let mut folder = None;
+    let mut output = None;
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--native-math" => options.native_math = true,
+            "--type-check" => {
+                options.type_check = match args.next().map(|mode| mode.as_str()) {
+                    Some("lenient") => Some(TypeCheck::Lenient),
+                    Some("strict") => Some(TypeCheck::Strict),
+                    _ => return Err(String::from(USAGE)),
+                }
+            }
+            "--tokens" => xml_options.tokens = true,
+            "--xml" => xml_options.xml = true,
+            "--compare" => xml_options.compare = true,
+            "--output" => match args.next() {
+                Some(folder) => output = Some(PathBuf::from(folder)),
+                None => return Err(String::from(USAGE)),
+            },
+            _ if folder.is_none() && !arg.starts_with("--") => folder = Some(arg),
+            _ => return Err(String::from(USAGE)),
         }
-        Ok(())
-    } else {
-        Err(String::from("Usage: jack2vm [path]"))
+    }
+    match folder {
+        Some(folder) => {
+            // run main prog
+            if let Err(e) = compile_folder(folder, output.as_ref(), &options, &xml_options) {
+                println!("{}", e);
+                process::exit(1);
+            }
+            Ok(())
+        }
+        None => Err(String::from(USAGE)),
     }
 }
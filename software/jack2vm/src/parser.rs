@@ -1,8 +1,14 @@
 use crate::{
-    CodeGen, ReservedKeywords, Symbols, Token, TokenType, Tokenizer, VariableKind, VM_OPS,
+    ast::{
+        BinaryOp, Class, ClassVarDec, ClassVarKind, Expression, Identifier, KeywordConstant,
+        Parameter, Span, Statement, SubroutineCall, SubroutineDec, SubroutineKind, Term, Type,
+        UnaryOp, VarDec,
+    },
+    CodeGen, Diagnostic, Expected, JackError, ReservedKeywords, Symbols, Token, TokenType,
+    Tokenizer, TypeCheck,
 };
 
-pub type ParserReturn = Result<(), String>;
+type ParseResult<T> = Result<T, JackError>;
 
 // Forbidden arts
 // Used to help writing code that check and consumes a terminal token
@@ -11,27 +17,21 @@ pub type ParserReturn = Result<(), String>;
 // match token._type {
 //     TokenType::Symbol(Symbols::RightBrace) => {}
 //     _ => {
-//         return Err(Self::error_expected_token_type(
+//         return Err(self.error_unexpected_token(
 //             &token,
 //             &[TokenType::Symbol(Symbols::RightBrace)],
 //             source,
 //         ));
 //     }
 // }
-// self.push_terminal(&token, source);
 macro_rules! consume_single_terminal_token {
     ($self:ident, $token:ident, $_type_p:pat, $_type_e:expr, $source:ident) => {
         match $token._type {
             $_type_p => {}
             _ => {
-                return Err(Parser::error_expected_token_type(
-                    &$token,
-                    &[$_type_e],
-                    $source,
-                ));
+                return Err($self.error_unexpected_token(&$token, &[$_type_e], $source));
             }
         }
-        $self.push_terminal(&$token, $source);
     };
 }
 
@@ -43,78 +43,112 @@ macro_rules! consume_single_terminal_token {
 ///
 /// Example where this needs to be considered is that when peeking to check if we need to consume 0 or more tokens,
 /// we do no need to worry about not having any more tokens to consume
+///
+/// The Parser only builds the AST, the XML and VM code are separate passes over it
+///
+/// On an error the Parser skips ahead to the next statement, declaration or subroutine and goes on,
+/// so every error of a file is reported at once
+#[derive(Default)]
 pub struct Parser {
     current: usize,
-    xml_ast: Vec<String>,
-    class_name: Option<String>, // This is set when we look at the class dec
-    pub code_gen: CodeGen,
+    errors: Vec<JackError>, // Errors recovered from
 }
-#[allow(clippy::derivable_impls)]
-impl Default for Parser {
-    fn default() -> Self {
-        Self {
-            current: 0,
-            xml_ast: Vec::default(),
-            class_name: None,
-            code_gen: CodeGen::default(),
-        }
-    }
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    // * and / become the mul and div VM commands, only vm2hack --math-commands can compile them
+    pub native_math: bool,
+    // Types are only checked by compile_project, which knows the signatures of every class
+    pub type_check: Option<TypeCheck>,
 }
 #[derive(Debug)]
 pub struct ParserCodeOutput {
-    pub xml: String,
     pub vm: String,
 }
 impl Parser {
     pub fn new() -> Self {
         Self::default()
     }
-    pub fn parse(source: &str) -> Result<ParserCodeOutput, String> {
-        let tokens = Tokenizer::generate_tokens(source)?;
-        // Debug show tokens
+    pub fn parse(source: &str) -> Result<ParserCodeOutput, Vec<Diagnostic>> {
+        Self::parse_with_options(source, &ParserOptions::default())
+    }
+    pub fn parse_with_options(
+        source: &str,
+        options: &ParserOptions,
+    ) -> Result<ParserCodeOutput, Vec<Diagnostic>> {
+        let class = match Self::parse_ast(source)? {
+            Some(class) => class,
+            // Nothing to compile
+            None => return Ok(ParserCodeOutput { vm: String::new() }),
+        };
+        let mut code_gen = CodeGen::new(options);
+        code_gen
+            .class(&class)
+            .map_err(|e| vec![Diagnostic::new(e, &source.chars().collect::<Vec<char>>())])?;
         #[cfg(feature = "debug")]
         {
-            println!("{:?}", tokens);
+            println!("{} class symbol ", class.name.name);
+            println!("{:?}", code_gen.class_symbol_table);
             println!();
         }
-        let source = source.chars().collect::<Vec<char>>();
-        let mut parser = Parser::new();
-        let result = parser.parse_tokens(&tokens, &source);
+        Ok(ParserCodeOutput {
+            vm: code_gen.gen_vm_code(),
+        })
+    }
+    /// AST of the class in source, None if the source has no class at all
+    /// On errors, all of them in the order of the source
+    pub fn parse_ast(source: &str) -> Result<Option<Class>, Vec<Diagnostic>> {
+        let chars = source.chars().collect::<Vec<char>>();
+        let tokens =
+            Tokenizer::generate_tokens(source).map_err(|e| vec![Diagnostic::new(e, &chars)])?;
+        // Debug show tokens
         #[cfg(feature = "debug")]
         {
-            println!(
-                "{} class symbol ",
-                parser.class_name.unwrap_or(String::from("empty class"))
-            );
-            println!("{:?}", parser.code_gen.class_symbol_table);
+            println!("{:?}", tokens);
             println!();
         }
-        result
+        Parser::new()
+            .parse_tokens(&tokens, &chars)
+            .map_err(|errors| {
+                errors
+                    .into_iter()
+                    .map(|error| Diagnostic::new(error, &chars))
+                    .collect()
+            })
     }
     fn parse_tokens(
         &mut self,
         tokens: &[Token],
         source: &[char],
-    ) -> Result<ParserCodeOutput, String> {
-        // Returns XML string to write to file
+    ) -> Result<Option<Class>, Vec<JackError>> {
+        let mut class = None;
         if let TokenType::Keyword(ReservedKeywords::Class) = &tokens[self.current]._type {
-            self.class(tokens, source)?;
+            match self.class(tokens, source) {
+                Ok(parsed) => class = Some(parsed),
+                // The class itself could not be parsed, nothing after it is checked
+                Err(e) => {
+                    self.errors.push(e);
+                    return Err(std::mem::take(&mut self.errors));
+                }
+            }
         }
-        if let TokenType::EOF = &tokens[self.current]._type {
-            // Check if last token is EOF
-            Ok(ParserCodeOutput {
-                xml: self.xml_ast.join("\n"),
-                vm: self.code_gen.gen_vm_code(),
-            })
+        // Check if last token is EOF
+        let token = self.peek(tokens);
+        if !matches!(token._type, TokenType::EOF) {
+            self.errors.push(Self::error_expected_token_type(
+                &token,
+                &[TokenType::EOF],
+                source,
+            ));
+        }
+        if self.errors.is_empty() {
+            Ok(class)
         } else {
-            Err(String::from("Could not compile file at all, at the start"))
+            Err(std::mem::take(&mut self.errors))
         }
     }
-    fn class(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<class>".to_string());
-
+    fn class(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Class> {
         // Consume class
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -122,14 +156,13 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::Class),
             source
         );
+        let start = token.span();
 
-        // Consume className, ignored
-        self.identifier(tokens, source)?;
-        let token = &tokens[self.current - 1];
-        self.class_name = Some(token.get_source(source));
+        // className
+        let name = self.identifier(tokens, source)?;
 
         // Consume '{'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -139,22 +172,30 @@ impl Parser {
         );
 
         // Check for 0 or more classVarDec
+        let mut var_decs = Vec::default();
         while let TokenType::Keyword(ReservedKeywords::Static | ReservedKeywords::Field) =
             self.peek(tokens)._type
         {
-            self.class_var_dec(tokens, source)?;
+            match self.class_var_dec(tokens, source) {
+                Ok(var_dec) => var_decs.push(var_dec),
+                Err(e) => self.recover(e, tokens),
+            }
         }
 
         // subroutineDec*
+        let mut subroutines = Vec::default();
         while let TokenType::Keyword(
             ReservedKeywords::Constructor | ReservedKeywords::Function | ReservedKeywords::Method,
         ) = self.peek(tokens)._type
         {
-            self.subroutine_dec(tokens, source)?;
+            match self.subroutine_dec(tokens, source) {
+                Ok(subroutine) => subroutines.push(subroutine),
+                Err(e) => self.recover_subroutine(e, tokens),
+            }
         }
 
         // '}'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -163,54 +204,40 @@ impl Parser {
             source
         );
 
-        self.xml_ast.push("</class>".to_string());
-        Ok(())
+        Ok(Class {
+            name,
+            var_decs,
+            subroutines,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn class_var_dec(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<classVarDec>".to_string());
-
+    fn class_var_dec(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<ClassVarDec> {
         // Consume ('static' | 'field')
-        let token = self.advance(tokens, source)?;
-        let variable_kind = &token;
-        if !matches!(
-            token._type,
-            TokenType::Keyword(ReservedKeywords::Static | ReservedKeywords::Field)
-        ) {
-            return Err(Self::error_expected_token_type(
-                &token,
-                &[
-                    TokenType::Keyword(ReservedKeywords::Static),
-                    TokenType::Keyword(ReservedKeywords::Field),
-                ],
-                source,
-            ));
-        }
-        self.push_terminal(&token, source);
-
-        // Pass to type
-        self._type(tokens, source)?;
-        let variable_type = &tokens[self.current - 1];
+        let token = self.advance(tokens);
+        let kind = match token._type {
+            TokenType::Keyword(ReservedKeywords::Static) => ClassVarKind::Static,
+            TokenType::Keyword(ReservedKeywords::Field) => ClassVarKind::Field,
+            _ => {
+                return Err(self.error_unexpected_token(
+                    &token,
+                    &[
+                        TokenType::Keyword(ReservedKeywords::Static),
+                        TokenType::Keyword(ReservedKeywords::Field),
+                    ],
+                    source,
+                ));
+            }
+        };
+        let start = token.span();
 
-        // Consume varName, ignored
-        self.identifier(tokens, source)?;
-        let name = tokens[self.current - 1].get_source(source);
-        self.code_gen
-            .insert_class_variable(name, variable_kind, variable_type, source)?;
+        // type
+        let (_type, type_span) = self._type(tokens, source)?;
 
-        // (',' varName)*
-        while let TokenType::Symbol(Symbols::Comma) = self.peek(tokens)._type {
-            // ','
-            let token = self.advance(tokens, source)?;
-            self.push_terminal(&token, source);
-            // varName
-            self.identifier(tokens, source)?;
-            let name = tokens[self.current - 1].get_source(source);
-            self.code_gen
-                .insert_class_variable(name, variable_kind, variable_type, source)?;
-        }
+        // varName (',' varName)*
+        let names = self.var_names(tokens, source)?;
 
         // ';'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -219,27 +246,24 @@ impl Parser {
             source
         );
 
-        self.xml_ast.push("</classVarDec>".to_string());
-        Ok(())
+        Ok(ClassVarDec {
+            kind,
+            _type,
+            type_span,
+            names,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn subroutine_dec(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<subroutineDec>".to_string());
-
+    fn subroutine_dec(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<SubroutineDec> {
         // Consume ('constructor' | 'function' | 'method')
-        let subroutine_kind_token = self.advance(tokens, source)?;
-        self.code_gen.push_comment(format!(
-            "start subroutine_dec, {}",
-            subroutine_kind_token.line
-        ));
-        match subroutine_kind_token._type {
-            TokenType::Keyword(
-                ReservedKeywords::Constructor
-                | ReservedKeywords::Function
-                | ReservedKeywords::Method,
-            ) => {}
+        let token = self.advance(tokens);
+        let kind = match token._type {
+            TokenType::Keyword(ReservedKeywords::Constructor) => SubroutineKind::Constructor,
+            TokenType::Keyword(ReservedKeywords::Function) => SubroutineKind::Function,
+            TokenType::Keyword(ReservedKeywords::Method) => SubroutineKind::Method,
             _ => {
-                return Err(Self::error_expected_token_type(
-                    &subroutine_kind_token,
+                return Err(self.error_unexpected_token(
+                    &token,
                     &[
                         TokenType::Keyword(ReservedKeywords::Constructor),
                         TokenType::Keyword(ReservedKeywords::Function),
@@ -248,28 +272,27 @@ impl Parser {
                     source,
                 ));
             }
-        }
-        self.push_terminal(&subroutine_kind_token, source);
+        };
+        let start = token.span();
 
         // ('void' | type)
         let token = self.peek(tokens);
-        match token._type {
+        let (return_type, return_type_span) = match token._type {
             TokenType::Keyword(ReservedKeywords::Void) => {
-                self.push_terminal(&token, source);
-                self.advance(tokens, source)?;
+                self.advance(tokens);
+                (None, token.span())
             }
             _ => {
-                self._type(tokens, source)?;
+                let (_type, span) = self._type(tokens, source)?;
+                (Some(_type), span)
             }
-        }
+        };
 
-        // subroutineName, identifier, ignored
-        self.identifier(tokens, source)?;
-        let token = &tokens[self.current - 1];
-        let subroutine_name = token.get_source(source);
+        // subroutineName
+        let name = self.identifier(tokens, source)?;
 
         // '('
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -278,15 +301,11 @@ impl Parser {
             source
         );
 
-        // decalring a new subroutine
-        self.code_gen
-            .reset_subroutine_table(self.class_name.clone().unwrap());
-
         // parameterList
-        self.parameter_list(tokens, source)?;
+        let parameters = self.parameter_list(tokens, source)?;
 
         // ')'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -296,10 +315,8 @@ impl Parser {
         );
 
         // subroutineBody
-        self.xml_ast.push("<subroutineBody>".to_string());
-
         // '{'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -309,29 +326,19 @@ impl Parser {
         );
 
         // varDec*
+        let mut var_decs = Vec::default();
         while let TokenType::Keyword(ReservedKeywords::Var) = self.peek(tokens)._type {
-            self.var_dec(tokens, source)?;
-        }
-        // vmcode, declare the function
-        self.code_gen
-            .push_function(&self.class_name.clone().unwrap(), &subroutine_name);
-        match subroutine_kind_token._type {
-            TokenType::Keyword(ReservedKeywords::Constructor) => {
-                self.code_gen.constructor_alloc();
-            }
-            TokenType::Keyword(ReservedKeywords::Method) => {
-                self.code_gen.push_variable(&String::from("this"))?;
-                self.code_gen.pop_pointer(0);
+            match self.var_dec(tokens, source) {
+                Ok(var_dec) => var_decs.push(var_dec),
+                Err(e) => self.recover(e, tokens),
             }
-            TokenType::Keyword(ReservedKeywords::Function) => {}
-            _ => return Err(String::from("unexpected vmcodegen subroutine_dec")),
         }
 
         // statements
-        self.statements(tokens, source)?;
+        let statements = self.statements(tokens, source)?;
 
         // '}'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -340,28 +347,20 @@ impl Parser {
             source
         );
 
-        self.xml_ast.push("</subroutineBody>".to_string());
-
-        self.xml_ast.push("</subroutineDec>".to_string());
-        #[cfg(feature = "debug")]
-        {
-            println!(
-                "{}.{} symbol table",
-                self.class_name.clone().unwrap(),
-                subroutine_name
-            );
-            println!("{:?}", self.code_gen.subroutine_symbol_table);
-            println!();
-        }
-        self.code_gen
-            .push_comment(format!("end subroutine_dec, {}", tokens[self.current].line));
-        Ok(())
+        Ok(SubroutineDec {
+            kind,
+            return_type,
+            return_type_span,
+            name,
+            parameters,
+            var_decs,
+            statements,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn var_dec(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<varDec>".to_string());
-
+    fn var_dec(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<VarDec> {
         // 'var'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -369,41 +368,17 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::Var),
             source
         );
+        let start = token.span();
 
         // type
-        self._type(tokens, source)?;
-        let variable_type = &tokens[self.current - 1];
+        let (_type, type_span) = self._type(tokens, source)?;
 
-        // varName, ignored
-        self.identifier(tokens, source)?;
-        let name = tokens[self.current - 1].get_source(source);
-        self.code_gen.insert_subroutine_variable(
-            name,
-            VariableKind::Local,
-            variable_type,
-            source,
-        )?;
-
-        // (',' varName)*
-        while let TokenType::Symbol(Symbols::Comma) = self.peek(tokens)._type {
-            // ','
-            let token = self.advance(tokens, source)?;
-            self.push_terminal(&token, source);
-            // varName
-            self.identifier(tokens, source)?;
-
-            // variables declared on the same line have the same type
-            let name = tokens[self.current - 1].get_source(source);
-            self.code_gen.insert_subroutine_variable(
-                name,
-                VariableKind::Local,
-                variable_type,
-                source,
-            )?;
-        }
+        // varName (',' varName)*
+        // variables declared on the same line have the same type
+        let names = self.var_names(tokens, source)?;
 
         // ';'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -412,112 +387,91 @@ impl Parser {
             source
         );
 
-        self.xml_ast.push("</varDec>".to_string());
-        Ok(())
+        Ok(VarDec {
+            _type,
+            type_span,
+            names,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn parameter_list(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<parameterList>".to_string());
+    /// varName (',' varName)*
+    fn var_names(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Vec<Identifier>> {
+        let mut names = vec![self.identifier(tokens, source)?];
+        while let TokenType::Symbol(Symbols::Comma) = self.peek(tokens)._type {
+            // ','
+            self.advance(tokens);
+            // varName
+            names.push(self.identifier(tokens, source)?);
+        }
+        Ok(names)
+    }
+    fn parameter_list(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Vec<Parameter>> {
+        let mut parameters = Vec::default();
         // ()?
         if let TokenType::Keyword(
             ReservedKeywords::Int | ReservedKeywords::Char | ReservedKeywords::Boolean,
         )
         | TokenType::Identifier = self.peek(tokens)._type
         {
-            self._type(tokens, source)?;
-            let variable_type = &tokens[self.current - 1];
-            self.identifier(tokens, source)?;
-            let name = tokens[self.current - 1].get_source(source);
-            self.code_gen.insert_subroutine_variable(
-                name,
-                VariableKind::Argument,
-                &variable_type,
-                source,
-            )?;
-
-            // (',' type varName)*
-            while let TokenType::Symbol(Symbols::Comma) = self.peek(tokens)._type {
-                // ','
-                let token = self.advance(tokens, source)?;
-                self.push_terminal(&token, source);
+            loop {
                 // type
-                self._type(tokens, source)?;
-                let variable_type = &tokens[self.current - 1];
+                let (_type, type_span) = self._type(tokens, source)?;
                 // varName
-                self.identifier(tokens, source)?;
-                let name = tokens[self.current - 1].get_source(source);
-                self.code_gen.insert_subroutine_variable(
+                let name = self.identifier(tokens, source)?;
+                parameters.push(Parameter {
+                    _type,
+                    type_span,
                     name,
-                    VariableKind::Argument,
-                    &variable_type,
-                    source,
-                )?;
-            }
-        }
+                });
 
-        self.xml_ast.push("</parameterList>".to_string());
-        Ok(())
-    }
-    fn _type(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        let token = self.advance(tokens, source)?;
-
-        match token._type {
-            TokenType::Keyword(
-                ReservedKeywords::Int | ReservedKeywords::Char | ReservedKeywords::Boolean,
-            )
-            | TokenType::Identifier => {
-                // Consume the Reserved Keyword
-                self.push_terminal(&token, source);
-                Ok(())
+                // (',' type varName)*
+                match self.peek(tokens)._type {
+                    TokenType::Symbol(Symbols::Comma) => {
+                        self.advance(tokens);
+                    }
+                    _ => break,
+                }
             }
-            _ => Err(Self::error_expected_token_type(
-                &token,
-                &[
-                    TokenType::Keyword(ReservedKeywords::Int),
-                    TokenType::Keyword(ReservedKeywords::Char),
-                    TokenType::Keyword(ReservedKeywords::Boolean),
-                    TokenType::Identifier,
-                ],
-                source,
-            )),
         }
+        Ok(parameters)
     }
-    fn identifier(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        // Consume identifier, ignored
-        let token = self.advance(tokens, source)?;
-        match token._type {
-            TokenType::Identifier => {}
+    fn _type(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<(Type, Span)> {
+        let token = self.advance(tokens);
+
+        let _type = match token._type {
+            TokenType::Keyword(ReservedKeywords::Int) => Type::Int,
+            TokenType::Keyword(ReservedKeywords::Char) => Type::Char,
+            TokenType::Keyword(ReservedKeywords::Boolean) => Type::Boolean,
+            TokenType::Identifier => Type::Class(token.get_source(source)),
             _ => {
-                return Err(Self::error_expected_token_type(
+                return Err(self.error_unexpected_token(
                     &token,
-                    &[TokenType::Identifier],
+                    &[
+                        TokenType::Keyword(ReservedKeywords::Int),
+                        TokenType::Keyword(ReservedKeywords::Char),
+                        TokenType::Keyword(ReservedKeywords::Boolean),
+                        TokenType::Identifier,
+                    ],
                     source,
                 ))
             }
-        }
-        self.push_terminal(&token, source);
-
-        Ok(())
-    }
-    fn push_terminal(&mut self, token: &Token, source: &[char]) {
-        let _type = match token._type {
-            TokenType::Identifier => "identifier",
-            TokenType::Integer(_) => "integerConstant",
-            TokenType::Keyword(_) => "keyword",
-            TokenType::String => "stringConstant",
-            TokenType::Symbol(_) => "symbol",
-            TokenType::EOF => panic!("Should not be pushing EOF terminal"),
         };
-        self.xml_ast.push(format!("<{}>", _type));
-        self.xml_ast.push(token.get_source(source));
-        self.xml_ast.push(format!("</{}>", _type));
+        Ok((_type, token.span()))
+    }
+    fn identifier(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Identifier> {
+        let token = self.advance(tokens);
+        match token._type {
+            TokenType::Identifier => Ok(Identifier {
+                name: token.get_source(source),
+                span: token.span(),
+            }),
+            _ => Err(self.error_unexpected_token(&token, &[TokenType::Identifier], source)),
+        }
     }
 
     // Statements
-    // NOTE:
-    // Could combine with self.statement
-    // Match on every type, then call it
-    fn statements(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<statements>".to_string());
+    fn statements(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Vec<Statement>> {
+        let mut statements = Vec::default();
         loop {
             let token = self.peek(tokens);
             match token._type {
@@ -527,55 +481,39 @@ impl Parser {
                     | ReservedKeywords::While
                     | ReservedKeywords::Do
                     | ReservedKeywords::Return,
-                ) => {
-                    self.statement(tokens, source)?;
-                }
+                ) => match self.statement(tokens, source) {
+                    Ok(statement) => statements.push(statement),
+                    Err(e) => self.recover(e, tokens),
+                },
                 _ => break,
             }
         }
-
-        self.xml_ast.push("</statements>".to_string());
-        Ok(())
+        Ok(statements)
     }
-    fn statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
+    fn statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         let token = self.peek(tokens);
         match token._type {
-            TokenType::Keyword(ReservedKeywords::Let) => {
-                self.let_statement(tokens, source)?;
-            }
-            TokenType::Keyword(ReservedKeywords::If) => {
-                self.if_statement(tokens, source)?;
-            }
-            TokenType::Keyword(ReservedKeywords::While) => {
-                self.while_statement(tokens, source)?;
-            }
-            TokenType::Keyword(ReservedKeywords::Do) => {
-                self.do_statement(tokens, source)?;
-            }
-            TokenType::Keyword(ReservedKeywords::Return) => {
-                self.return_statement(tokens, source)?
-            }
-            _ => {
-                return Err(Self::error_expected_token_type(
-                    &token,
-                    &[
-                        TokenType::Keyword(ReservedKeywords::Let),
-                        TokenType::Keyword(ReservedKeywords::If),
-                        TokenType::Keyword(ReservedKeywords::While),
-                        TokenType::Keyword(ReservedKeywords::Do),
-                        TokenType::Keyword(ReservedKeywords::Return),
-                    ],
-                    source,
-                ));
-            }
+            TokenType::Keyword(ReservedKeywords::Let) => self.let_statement(tokens, source),
+            TokenType::Keyword(ReservedKeywords::If) => self.if_statement(tokens, source),
+            TokenType::Keyword(ReservedKeywords::While) => self.while_statement(tokens, source),
+            TokenType::Keyword(ReservedKeywords::Do) => self.do_statement(tokens, source),
+            TokenType::Keyword(ReservedKeywords::Return) => self.return_statement(tokens, source),
+            _ => Err(Self::error_expected_token_type(
+                &token,
+                &[
+                    TokenType::Keyword(ReservedKeywords::Let),
+                    TokenType::Keyword(ReservedKeywords::If),
+                    TokenType::Keyword(ReservedKeywords::While),
+                    TokenType::Keyword(ReservedKeywords::Do),
+                    TokenType::Keyword(ReservedKeywords::Return),
+                ],
+                source,
+            )),
         }
-        Ok(())
     }
-    fn let_statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<letStatement>".to_string());
-
+    fn let_statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         // 'let'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -583,37 +521,22 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::Let),
             source
         );
+        let start = token.span();
 
         // varName
-        self.identifier(tokens, source)?;
-        let variable_token = &tokens[self.current - 1];
-        let variable_name = &variable_token.get_source(source);
-
-        let mut is_array = false;
+        let name = self.identifier(tokens, source)?;
 
         // ('[' expression ']')?
+        let mut index = None;
         if let TokenType::Symbol(Symbols::LeftBracket) = self.peek(tokens)._type {
-            is_array = true;
             // '['
-            let token = self.advance(tokens, source)?;
-            consume_single_terminal_token!(
-                self,
-                token,
-                TokenType::Symbol(Symbols::LeftBracket),
-                TokenType::Symbol(Symbols::LeftBracket),
-                source
-            );
-
-            self.code_gen
-                .push_variable(variable_name)
-                .map_err(Self::map_code_gen_err_with_token_line(variable_token.line))?;
+            self.advance(tokens);
 
             // expression
-            self.expression(tokens, source)?;
-            self.code_gen.push_op(VM_OPS::ADD);
+            index = Some(self.expression(tokens, source)?);
 
             // ']'
-            let token = self.advance(tokens, source)?;
+            let token = self.advance(tokens);
             consume_single_terminal_token!(
                 self,
                 token,
@@ -624,7 +547,7 @@ impl Parser {
         }
 
         // '='
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -633,10 +556,10 @@ impl Parser {
             source
         );
 
-        self.expression(tokens, source)?;
+        let value = self.expression(tokens, source)?;
 
         // ';'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -645,28 +568,16 @@ impl Parser {
             source
         );
 
-        // vmcode, pop the expression into the variable
-        if is_array {
-            self.code_gen.pop_temp(0);
-            self.code_gen.pop_pointer(1);
-            self.code_gen.push_temp();
-            self.code_gen.pop_that();
-        } else {
-            self.code_gen
-                .pop_variable(variable_name)
-                .map_err(Self::map_code_gen_err_with_token_line(variable_token.line))?;
-        }
-
-        self.xml_ast.push("</letStatement>".to_string());
-        Ok(())
+        Ok(Statement::Let {
+            name,
+            index,
+            value,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn if_statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<ifStatement>".to_string());
-
+    fn if_statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         // 'if'
-        let token = self.advance(tokens, source)?;
-        self.code_gen
-            .push_comment(format!("start if, {}", token.line));
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -674,114 +585,32 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::If),
             source
         );
+        let start = token.span();
 
-        // '('
-        let token = self.advance(tokens, source)?;
-        consume_single_terminal_token!(
-            self,
-            token,
-            TokenType::Symbol(Symbols::LeftParam),
-            TokenType::Symbol(Symbols::LeftParam),
-            source
-        );
-
-        // expression is at the top of the stack
-        self.expression(tokens, source)?;
-        // vmcode
-        self.code_gen.push_op(VM_OPS::NOT);
-        let l1 = self
-            .code_gen
-            .get_flow_counter(&self.class_name.clone().unwrap());
-        let l2 = self
-            .code_gen
-            .get_flow_counter(&self.class_name.clone().unwrap());
-        self.code_gen.push_if_goto(&l1);
-
-        // ')'
-        let token = self.advance(tokens, source)?;
-        consume_single_terminal_token!(
-            self,
-            token,
-            TokenType::Symbol(Symbols::RightParam),
-            TokenType::Symbol(Symbols::RightParam),
-            source
-        );
-
-        // '{'
-        let token = self.advance(tokens, source)?;
-        consume_single_terminal_token!(
-            self,
-            token,
-            TokenType::Symbol(Symbols::LeftBrace),
-            TokenType::Symbol(Symbols::LeftBrace),
-            source
-        );
+        // '(' expression ')'
+        let condition = self.condition(tokens, source)?;
 
-        // vmcode
-        self.statements(tokens, source)?;
-        self.code_gen.push_goto(&l2);
-        self.code_gen.push_label(&l1); // Condition did not pass, so we jump to else
+        // '{' statements '}'
+        let statements = self.block(tokens, source)?;
 
-        // '}'
-        let token = self.advance(tokens, source)?;
-        consume_single_terminal_token!(
-            self,
-            token,
-            TokenType::Symbol(Symbols::RightBrace),
-            TokenType::Symbol(Symbols::RightBrace),
-            source
-        );
-
-        // ()?
+        // ('else' '{' statements '}')?
+        let mut else_statements = None;
         if let TokenType::Keyword(ReservedKeywords::Else) = self.peek(tokens)._type {
             // 'else'
-            let token = self.advance(tokens, source)?;
-            self.push_terminal(&token, source);
-
-            // '{'
-            let token = self.advance(tokens, source)?;
-            consume_single_terminal_token!(
-                self,
-                token,
-                TokenType::Symbol(Symbols::LeftBrace),
-                TokenType::Symbol(Symbols::LeftBrace),
-                source
-            );
-
-            self.statements(tokens, source)?;
-
-            // '}'
-            let token = self.advance(tokens, source)?;
-            consume_single_terminal_token!(
-                self,
-                token,
-                TokenType::Symbol(Symbols::RightBrace),
-                TokenType::Symbol(Symbols::RightBrace),
-                source
-            );
+            self.advance(tokens);
+            else_statements = Some(self.block(tokens, source)?);
         }
-        // vmcode
-        self.code_gen.push_label(&l2);
-        self.code_gen
-            .push_comment(format!("end if, {}", tokens[self.current - 1].line));
 
-        self.xml_ast.push("</ifStatement>".to_string());
-        Ok(())
+        Ok(Statement::If {
+            condition,
+            statements,
+            else_statements,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn while_statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<whileStatement>".to_string());
+    fn while_statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         // 'while'
-        let token = self.advance(tokens, source)?;
-        self.code_gen
-            .push_comment(format!("start while, {}", token.line));
-        let l1 = self
-            .code_gen
-            .get_flow_counter(&self.class_name.clone().unwrap());
-        let l2 = self
-            .code_gen
-            .get_flow_counter(&self.class_name.clone().unwrap());
-        self.code_gen.push_label(&l1);
-
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -789,9 +618,24 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::While),
             source
         );
+        let start = token.span();
+
+        // '(' expression ')'
+        let condition = self.condition(tokens, source)?;
 
+        // '{' statements '}'
+        let statements = self.block(tokens, source)?;
+
+        Ok(Statement::While {
+            condition,
+            statements,
+            span: self.span_from(&start, tokens),
+        })
+    }
+    /// '(' expression ')' of if and while
+    fn condition(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Expression> {
         // '('
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -800,12 +644,10 @@ impl Parser {
             source
         );
 
-        self.expression(tokens, source)?;
-        self.code_gen.push_op(VM_OPS::NOT);
-        self.code_gen.push_if_goto(&l2);
+        let expression = self.expression(tokens, source)?;
 
         // ')'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -813,9 +655,12 @@ impl Parser {
             TokenType::Symbol(Symbols::RightParam),
             source
         );
-
+        Ok(expression)
+    }
+    /// '{' statements '}'
+    fn block(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Vec<Statement>> {
         // '{'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -824,12 +669,10 @@ impl Parser {
             source
         );
 
-        self.statements(tokens, source)?;
-        self.code_gen.push_goto(&l1);
-        self.code_gen.push_label(&l2);
+        let statements = self.statements(tokens, source)?;
 
         // '}'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -837,17 +680,11 @@ impl Parser {
             TokenType::Symbol(Symbols::RightBrace),
             source
         );
-        self.code_gen
-            .push_comment(format!("end while, {}", tokens[self.current - 1].line));
-
-        self.xml_ast.push("</whileStatement>".to_string());
-        Ok(())
+        Ok(statements)
     }
-    fn do_statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<doStatement>".to_string());
-
+    fn do_statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         // 'do'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -855,11 +692,12 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::Do),
             source
         );
+        let start = token.span();
 
-        self.subroutine_call(tokens, source)?;
+        let call = self.subroutine_call(tokens, source)?;
 
         // ';'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -868,17 +706,14 @@ impl Parser {
             source
         );
 
-        // needs to remove uncessary value from top of stack from function return
-        self.code_gen.pop_temp(0);
-
-        self.xml_ast.push("</doStatement>".to_string());
-        Ok(())
+        Ok(Statement::Do {
+            call,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn return_statement(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<returnStatement>".to_string());
-
+    fn return_statement(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Statement> {
         // 'return'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -886,21 +721,16 @@ impl Parser {
             TokenType::Keyword(ReservedKeywords::Return),
             source
         );
+        let start = token.span();
 
         // expression? ';'
-        match self.peek(tokens)._type {
-            TokenType::Symbol(Symbols::SemiColon) => {
-                // Skip expression
-                self.code_gen.push_integer_constant(0);
-            }
-            _ => {
-                // Consume expression
-                self.expression(tokens, source)?;
-            }
-        }
+        let value = match self.peek(tokens)._type {
+            TokenType::Symbol(Symbols::SemiColon) => None,
+            _ => Some(self.expression(tokens, source)?),
+        };
 
         // ';'
-        let token = self.advance(tokens, source)?;
+        let token = self.advance(tokens);
         consume_single_terminal_token!(
             self,
             token,
@@ -908,161 +738,56 @@ impl Parser {
             TokenType::Symbol(Symbols::SemiColon),
             source
         );
-        self.code_gen.push_return();
 
-        self.xml_ast.push("</returnStatement>".to_string());
-        Ok(())
+        Ok(Statement::Return {
+            value,
+            span: self.span_from(&start, tokens),
+        })
     }
 
     // Expressions
-    fn expression(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        self.xml_ast.push("<expression>".to_string());
-        self.term(tokens, source)?;
+    fn expression(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Expression> {
+        let term = self.term(tokens, source)?;
+        let start = term.span();
+        let mut ops = Vec::default();
         // (op term)*
-        while let TokenType::Symbol(
-            Symbols::Plus
-            | Symbols::Minus
-            | Symbols::Asterisk
-            | Symbols::Slash
-            | Symbols::And
-            | Symbols::Or
-            | Symbols::GreaterThan
-            | Symbols::LessThan
-            | Symbols::Equal,
-        ) = self.peek(tokens)._type
-        {
-            let op_token = self.advance(tokens, source)?;
-            self.push_terminal(&op_token, source);
-            self.term(tokens, source)?;
-            // push the op after the term
-            match op_token._type {
-                TokenType::Symbol(Symbols::Plus) => {
-                    self.code_gen.push_op(VM_OPS::ADD);
-                }
-                TokenType::Symbol(Symbols::Minus) => {
-                    self.code_gen.push_op(VM_OPS::SUB);
-                }
-                TokenType::Symbol(Symbols::And) => {
-                    self.code_gen.push_op(VM_OPS::AND);
-                }
-                TokenType::Symbol(Symbols::Or) => {
-                    self.code_gen.push_op(VM_OPS::OR);
-                }
-                TokenType::Symbol(Symbols::GreaterThan) => {
-                    self.code_gen.push_op(VM_OPS::GT);
-                }
-                TokenType::Symbol(Symbols::LessThan) => {
-                    self.code_gen.push_op(VM_OPS::LT);
-                }
-                TokenType::Symbol(Symbols::Equal) => {
-                    self.code_gen.push_op(VM_OPS::EQ);
-                }
-                // TODO: OS method calls
-                TokenType::Symbol(Symbols::Asterisk) => {
-                    self.code_gen.call_math_multiply();
-                }
-                TokenType::Symbol(Symbols::Slash) => {
-                    self.code_gen.call_math_divide();
-                }
-                _ => return Err(String::from("expression codegen, not")),
-            }
+        loop {
+            let op = match self.peek(tokens)._type {
+                TokenType::Symbol(Symbols::Plus) => BinaryOp::Add,
+                TokenType::Symbol(Symbols::Minus) => BinaryOp::Sub,
+                TokenType::Symbol(Symbols::Asterisk) => BinaryOp::Mul,
+                TokenType::Symbol(Symbols::Slash) => BinaryOp::Div,
+                TokenType::Symbol(Symbols::And) => BinaryOp::And,
+                TokenType::Symbol(Symbols::Or) => BinaryOp::Or,
+                TokenType::Symbol(Symbols::GreaterThan) => BinaryOp::GreaterThan,
+                TokenType::Symbol(Symbols::LessThan) => BinaryOp::LessThan,
+                TokenType::Symbol(Symbols::Equal) => BinaryOp::Equal,
+                _ => break,
+            };
+            self.advance(tokens);
+            ops.push((op, self.term(tokens, source)?));
         }
-        self.xml_ast.push("</expression>".to_string());
-        Ok(())
+        Ok(Expression {
+            term,
+            ops,
+            span: self.span_from(&start, tokens),
+        })
     }
-    fn subroutine_call(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
+    fn subroutine_call(
+        &mut self,
+        tokens: &[Token],
+        source: &[char],
+    ) -> ParseResult<SubroutineCall> {
         // subroutineName | className | varName
-        self.identifier(tokens, source)?;
-        self.code_gen.push_comment(format!(
-            "start subroutine_call, {}",
-            tokens[self.current - 1].line
-        ));
-        let l1_token = &tokens[self.current - 1];
-        match self.peek(tokens)._type {
-            TokenType::Symbol(Symbols::LeftParam) => {
-                // '('
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::LeftParam),
-                    TokenType::Symbol(Symbols::LeftParam),
-                    source
-                );
-
-                // since this is a call within the class, we need to set this
-                self.code_gen.push_pointer(0);
-                let no_of_expressions = self.expression_list(tokens, source)?;
-                self.code_gen.push_call(
-                    &self.class_name.clone().unwrap(),
-                    &l1_token.get_source(source),
-                    no_of_expressions as i16 + 1,
-                );
-
-                // ')'
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::RightParam),
-                    TokenType::Symbol(Symbols::RightParam),
-                    source
-                );
-            }
+        let first = self.identifier(tokens, source)?;
+        let start = first.span;
+        let (receiver, name) = match self.peek(tokens)._type {
+            TokenType::Symbol(Symbols::LeftParam) => (None, first),
             TokenType::Symbol(Symbols::Dot) => {
                 // '.'
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::Dot),
-                    TokenType::Symbol(Symbols::Dot),
-                    source
-                );
-
+                self.advance(tokens);
                 // subroutineName
-                self.identifier(tokens, source)?;
-                let l2_token = &tokens[self.current - 1];
-                // error means we are trying to call a Class function, push dummy 0 for dummy this arg
-                if self
-                    .code_gen
-                    .push_variable(&l1_token.get_source(source))
-                    .is_err()
-                {
-                    self.code_gen.push_comment(String::from("dummy 0"));
-                    self.code_gen.push_integer_constant(0);
-                };
-
-                // '('
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::LeftParam),
-                    TokenType::Symbol(Symbols::LeftParam),
-                    source
-                );
-
-                let no_of_expressions = self.expression_list(tokens, source)?;
-                self.code_gen
-                    .complex_subroutine_call(
-                        &l1_token.get_source(source),
-                        &l2_token.get_source(source),
-                        no_of_expressions as i16 + 1,
-                    )
-                    .map_err(Self::map_code_gen_err_with_token_line(
-                        tokens[self.current - 1].line,
-                    ))?;
-
-                // ')'
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::RightParam),
-                    TokenType::Symbol(Symbols::RightParam),
-                    source
-                );
+                (Some(first), self.identifier(tokens, source)?)
             }
             _ => {
                 let token = &self.peek(tokens);
@@ -1075,135 +800,109 @@ impl Parser {
                     source,
                 ));
             }
-        }
-        self.code_gen.push_comment(format!(
-            "end subroutine_call, {}",
-            tokens[self.current - 1].line
-        ));
-        Ok(())
-    }
-    fn term(&mut self, tokens: &[Token], source: &[char]) -> ParserReturn {
-        let vm_codegen_err = Err(String::from("Should not, from term codegen path"));
+        };
 
-        self.xml_ast.push("<term>".to_string());
+        // '('
+        let token = self.advance(tokens);
+        consume_single_terminal_token!(
+            self,
+            token,
+            TokenType::Symbol(Symbols::LeftParam),
+            TokenType::Symbol(Symbols::LeftParam),
+            source
+        );
+
+        let arguments = self.expression_list(tokens, source)?;
+
+        // ')'
+        let token = self.advance(tokens);
+        consume_single_terminal_token!(
+            self,
+            token,
+            TokenType::Symbol(Symbols::RightParam),
+            TokenType::Symbol(Symbols::RightParam),
+            source
+        );
+
+        Ok(SubroutineCall {
+            receiver,
+            name,
+            arguments,
+            span: self.span_from(&start, tokens),
+        })
+    }
+    fn term(&mut self, tokens: &[Token], source: &[char]) -> ParseResult<Term> {
         let token = self.peek(tokens);
-        match token._type {
+        let span = token.span();
+        let term = match token._type {
+            TokenType::Integer(x) => {
+                self.advance(tokens);
+                Term::Integer(x as u16, span)
+            }
+            TokenType::String => {
+                self.advance(tokens);
+                Term::String(token.get_source(source), span)
+            }
             TokenType::Keyword(
                 ReservedKeywords::True
                 | ReservedKeywords::False
                 | ReservedKeywords::Null
                 | ReservedKeywords::This,
-            )
-            | TokenType::String
-            | TokenType::Integer(_) => {
-                // For xml_ast
-                self.push_terminal(&token, source);
-                self.advance(tokens, source)?;
-                // For vm codegen
-                match token._type {
-                    TokenType::Keyword(
-                        ReservedKeywords::True | ReservedKeywords::False | ReservedKeywords::Null,
-                    )
-                    | TokenType::String => {
-                        // TODO
-                    }
-                    TokenType::Keyword(ReservedKeywords::This) => {
-                        self.code_gen.push_pointer(0);
-                    }
-                    TokenType::Integer(x) => {
-                        self.code_gen.push_integer_constant(x as i16);
-                    }
-                    _ => {
-                        return vm_codegen_err;
-                    }
-                }
+            ) => {
+                self.advance(tokens);
+                let keyword = match token._type {
+                    TokenType::Keyword(ReservedKeywords::True) => KeywordConstant::True,
+                    TokenType::Keyword(ReservedKeywords::False) => KeywordConstant::False,
+                    TokenType::Keyword(ReservedKeywords::Null) => KeywordConstant::Null,
+                    _ => KeywordConstant::This,
+                };
+                Term::Keyword(keyword, span)
             }
             TokenType::Identifier => {
                 // Decide between varName, varName '[' and subroutine_call
                 // LL(2)
-                match self.peek_n(1, tokens) {
-                    Some(next_token) => {
-                        match next_token._type {
-                            // index on variable
-                            TokenType::Symbol(Symbols::LeftBracket) => {
-                                self.identifier(tokens, source)?;
-                                let variable_token = &tokens[self.current - 1];
-                                let variable_name = &variable_token.get_source(source);
-                                self.code_gen.push_variable(variable_name).map_err(
-                                    Self::map_code_gen_err_with_token_line(variable_token.line),
-                                )?;
-
-                                // '['
-                                let token = self.advance(tokens, source)?;
-                                consume_single_terminal_token!(
-                                    self,
-                                    token,
-                                    TokenType::Symbol(Symbols::LeftBracket),
-                                    TokenType::Symbol(Symbols::LeftBracket),
-                                    source
-                                );
-
-                                // expression
-                                self.expression(tokens, source)?;
-
-                                // ']'
-                                let token = self.advance(tokens, source)?;
-                                consume_single_terminal_token!(
-                                    self,
-                                    token,
-                                    TokenType::Symbol(Symbols::RightBracket),
-                                    TokenType::Symbol(Symbols::RightBracket),
-                                    source
-                                );
-
-                                self.code_gen.push_op(VM_OPS::ADD);
-                                self.code_gen.pop_pointer(1); // set array base address + idx
-                                self.code_gen.push_that();
-                            }
-                            TokenType::Symbol(Symbols::LeftBrace | Symbols::Dot) => {
-                                self.subroutine_call(tokens, source)?;
-                            }
-                            _ => {
-                                // Just varName
-                                self.identifier(tokens, source)?;
-                                let variable_token = &tokens[self.current - 1];
-                                let variable_name = &variable_token.get_source(source);
-                                self.code_gen.push_variable(variable_name).map_err(
-                                    Self::map_code_gen_err_with_token_line(variable_token.line),
-                                )?;
-                            }
+                match self.peek_n(1, tokens).map(|next_token| next_token._type) {
+                    // index on variable
+                    Some(TokenType::Symbol(Symbols::LeftBracket)) => {
+                        let name = self.identifier(tokens, source)?;
+
+                        // '['
+                        self.advance(tokens);
+
+                        // expression
+                        let index = self.expression(tokens, source)?;
+
+                        // ']'
+                        let token = self.advance(tokens);
+                        consume_single_terminal_token!(
+                            self,
+                            token,
+                            TokenType::Symbol(Symbols::RightBracket),
+                            TokenType::Symbol(Symbols::RightBracket),
+                            source
+                        );
+
+                        Term::Index {
+                            name,
+                            index: Box::new(index),
+                            span: self.span_from(&span, tokens),
                         }
                     }
-                    None => {
-                        // Just varName, should almost never encounter this
-                        #[cfg(feature = "debug")]
-                        {
-                            println!("term, varname with no L(2) peek")
-                        }
-                        self.identifier(tokens, source)?;
-                        let variable_token = &tokens[self.current - 1];
-                        let variable_name = &variable_token.get_source(source);
-                        self.code_gen
-                            .push_variable(variable_name)
-                            .map_err(Self::map_code_gen_err_with_token_line(variable_token.line))?;
+                    Some(TokenType::Symbol(Symbols::LeftParam | Symbols::Dot)) => {
+                        Term::Call(self.subroutine_call(tokens, source)?)
                     }
+                    // Just varName
+                    _ => Term::Variable(self.identifier(tokens, source)?),
                 }
             }
             TokenType::Symbol(Symbols::LeftParam) => {
                 // '('
-                let token = self.advance(tokens, source)?;
-                consume_single_terminal_token!(
-                    self,
-                    token,
-                    TokenType::Symbol(Symbols::LeftParam),
-                    TokenType::Symbol(Symbols::LeftParam),
-                    source
-                );
+                self.advance(tokens);
 
-                self.expression(tokens, source)?;
+                let expression = self.expression(tokens, source)?;
 
                 // ')'
-                let token = self.advance(tokens, source)?;
+                let token = self.advance(tokens);
                 consume_single_terminal_token!(
                     self,
                     token,
@@ -1211,34 +910,38 @@ impl Parser {
                     TokenType::Symbol(Symbols::RightParam),
                     source
                 );
+                Term::Parenthesized(Box::new(expression), self.span_from(&span, tokens))
             }
             TokenType::Symbol(Symbols::Minus | Symbols::Tilde) => {
-                self.push_terminal(&token, source);
-                self.advance(tokens, source)?;
-
+                self.advance(tokens);
                 let op = match token._type {
-                    TokenType::Symbol(Symbols::Minus) => VM_OPS::NEG,
-                    TokenType::Symbol(Symbols::Tilde) => VM_OPS::NOT,
-                    _ => return vm_codegen_err,
+                    TokenType::Symbol(Symbols::Minus) => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
                 };
-                self.term(tokens, source)?;
-                self.code_gen.push_op(op);
+                let term = self.term(tokens, source)?;
+                Term::Unary {
+                    op,
+                    term: Box::new(term),
+                    span: self.span_from(&span, tokens),
+                }
             }
             _ => {
-                return Err(format!(
-                    "Expected a term, got {} on line {}",
-                    token.get_source(source),
-                    token.line
-                ));
+                return Err(JackError::Syntax {
+                    expected: Expected::Term,
+                    found: token._type.clone(),
+                    text: token.get_source(source),
+                    span,
+                });
             }
-        }
-        self.xml_ast.push("</term>".to_string());
-        Ok(())
+        };
+        Ok(term)
     }
-    // Needs to return the number of expressions it pushed onto the stack, for vm codegen
-    fn expression_list(&mut self, tokens: &[Token], source: &[char]) -> Result<usize, String> {
-        self.xml_ast.push("<expressionList>".to_string());
-        let mut no_of_expressions = 0;
+    fn expression_list(
+        &mut self,
+        tokens: &[Token],
+        source: &[char],
+    ) -> ParseResult<Vec<Expression>> {
+        let mut expressions = Vec::default();
         // (expression (',' expression)*)?
         match self.peek(tokens)._type {
             TokenType::Identifier
@@ -1251,38 +954,29 @@ impl Parser {
                 | ReservedKeywords::Null,
             )
             | TokenType::Symbol(Symbols::Minus | Symbols::Tilde | Symbols::LeftParam) => {
-                self.expression(tokens, source)?;
-                no_of_expressions += 1;
+                expressions.push(self.expression(tokens, source)?);
 
                 // (',' expression)*
                 while let TokenType::Symbol(Symbols::Comma) = self.peek(tokens)._type {
                     // ','
-                    let token = self.advance(tokens, source)?;
-                    self.push_terminal(&token, source);
-
-                    self.expression(tokens, source)?;
-                    no_of_expressions += 1;
+                    self.advance(tokens);
+                    expressions.push(self.expression(tokens, source)?);
                 }
             }
             _ => {}
         }
-        self.xml_ast.push("</expressionList>".to_string());
-        Ok(no_of_expressions)
+        Ok(expressions)
     }
 
-    fn advance(&mut self, tokens: &[Token], source: &[char]) -> Result<Token, String> {
+    /// Span from start to the end of the last consumed token
+    fn span_from(&self, start: &Span, tokens: &[Token]) -> Span {
+        start.to(&tokens[self.current - 1].span())
+    }
+    /// Never moves past EOF, as EOF is never consumed, a mismatched token is put back
+    fn advance(&mut self, tokens: &[Token]) -> Token {
         self.current += 1;
-        if self.current > tokens.len() {
-            Err(Self::error_unexpected_end(
-                // Might be a bug, where current is moved forward more
-                &tokens[self.current - 2],
-                source,
-            ))
-        } else {
-            // return the previous token
-            let token = tokens[self.current - 1].clone();
-            Ok(token)
-        }
+        // return the previous token
+        tokens[self.current - 1].clone()
     }
     fn peek(&self, tokens: &[Token]) -> Token {
         tokens[self.current].clone()
@@ -1294,31 +988,101 @@ impl Parser {
             Some(tokens[self.current + n].clone())
         }
     }
-    fn error_unexpected_end(token: &Token, source: &[char]) -> String {
-        format!(
-            "Unexpected end at {}, on line {}",
-            token.get_source(source),
-            token.line
-        )
+
+    /// Records the error and skips to the next statement or declaration
+    /// A ';' is skipped as well, a '}' or a keyword that starts something is not
+    /// Blocks are skipped whole, an error in a condition skips the statements of the if or while
+    fn recover(&mut self, error: JackError, tokens: &[Token]) {
+        self.errors.push(error);
+        let mut depth = 0;
+        loop {
+            match self.peek(tokens)._type {
+                TokenType::EOF
+                | TokenType::Keyword(
+                    ReservedKeywords::Class
+                    | ReservedKeywords::Constructor
+                    | ReservedKeywords::Function
+                    | ReservedKeywords::Method,
+                ) => return,
+                TokenType::Keyword(
+                    ReservedKeywords::Static
+                    | ReservedKeywords::Field
+                    | ReservedKeywords::Var
+                    | ReservedKeywords::Let
+                    | ReservedKeywords::If
+                    | ReservedKeywords::While
+                    | ReservedKeywords::Do
+                    | ReservedKeywords::Return,
+                )
+                | TokenType::Symbol(Symbols::RightBrace)
+                    if depth == 0 =>
+                {
+                    return
+                }
+                TokenType::Symbol(Symbols::SemiColon) if depth == 0 => {
+                    self.current += 1;
+                    return;
+                }
+                TokenType::Symbol(Symbols::LeftBrace) => depth += 1,
+                TokenType::Symbol(Symbols::RightBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.current += 1;
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.current += 1;
+        }
     }
-    pub fn error_expected_token_type(
+    /// Records the error and skips to the next subroutine
+    /// The last '}' of the file is left for the class
+    fn recover_subroutine(&mut self, error: JackError, tokens: &[Token]) {
+        self.errors.push(error);
+        loop {
+            match self.peek(tokens)._type {
+                TokenType::EOF
+                | TokenType::Keyword(
+                    ReservedKeywords::Class
+                    | ReservedKeywords::Constructor
+                    | ReservedKeywords::Function
+                    | ReservedKeywords::Method,
+                ) => return,
+                TokenType::Symbol(Symbols::RightBrace)
+                    if matches!(
+                        self.peek_n(1, tokens).map(|token| token._type),
+                        Some(TokenType::EOF)
+                    ) =>
+                {
+                    return
+                }
+                _ => self.current += 1,
+            }
+        }
+    }
+
+    /// Error for a token that was advanced over, it is put back so the recovery starts from it
+    fn error_unexpected_token(
+        &mut self,
         token: &Token,
         _types: &[TokenType],
         source: &[char],
-    ) -> String {
-        format!(
-            "Expected {}, got {} on line {}",
-            _types
-                .iter()
-                .map(|_type| format!("{:?}", _type))
-                .collect::<Vec<String>>()
-                .join(" | "),
-            token.get_source(source),
-            token.line
-        )
+    ) -> JackError {
+        self.current -= 1;
+        Self::error_expected_token_type(token, _types, source)
     }
-    fn map_code_gen_err_with_token_line(token_line: usize) -> impl Fn(String) -> String {
-        move |e: String| -> String { format!("VM codegen error: {}, on line {}", e, token_line) }
+    pub fn error_expected_token_type(
+        token: &Token,
+        _types: &[TokenType],
+        source: &[char],
+    ) -> JackError {
+        JackError::Syntax {
+            expected: Expected::Tokens(_types.to_vec()),
+            found: token._type.clone(),
+            text: token.get_source(source),
+            span: token.span(),
+        }
     }
 }
 
@@ -1337,10 +1101,151 @@ mod tests {
         let output = parser.parse_tokens(&tokens, &source);
         println!("{:?}", tokens);
         println!("{:?}", output);
-        println!("{:?}", parser.xml_ast);
-        assert!(false);
+        assert!(output.is_ok());
+    }
+
+    #[test]
+    fn native_math() {
+        let source = "class Main { function int f(int a) { return a * 3 / a; } }";
+        let output = Parser::parse(source).unwrap();
+        assert!(output.vm.contains("call Math.multiply 3"));
+        let options = ParserOptions {
+            native_math: true,
+            ..ParserOptions::default()
+        };
+        let output = Parser::parse_with_options(source, &options).unwrap();
+        assert!(!output.vm.contains("call Math"));
+        assert!(output.vm.contains("mul\n"));
+        assert!(output.vm.contains("div\n"));
+    }
+    #[test]
+    fn math_call_argument_order() {
+        let source = "class Main { function int f(int a, int b) { return a / b; } }";
+        let output = Parser::parse(source).unwrap();
+        let call =
+            "pop temp 0\npop temp 1\npush constant 0\npush temp 1\npush temp 0\ncall Math.divide 3";
+        assert!(output.vm.contains(call), "{}", output.vm);
+    }
+
+    #[test]
+    fn string_constant() {
+        let output = Parser::parse(include_str!("../../../projects/11/Average/Main.jack")).unwrap();
+        let string = "How many numbers? ";
+        let mut expected = vec![
+            String::from("push constant 0"),
+            format!("push constant {}", string.len()),
+            String::from("call String.new 2"),
+        ];
+        for c in string.chars() {
+            expected.push(format!("push constant {}", c as i16));
+            expected.push(String::from("call String.appendChar 2"));
+        }
+        expected.push(String::from("call Keyboard.readInt 2"));
+        assert!(output.vm.contains(&expected.join("\n")), "{}", output.vm);
+
+        let source =
+            "class Main { function void main() { do Output.printString(\"\u{e9}\"); return; } }";
+        assert!(Parser::parse(source).is_err());
     }
+    #[test]
+    fn keyword_constants() {
+        let output = Parser::parse(include_str!("../../../projects/10/Square/Main.jack")).unwrap();
+        // let s = null;
+        assert!(
+            output.vm.contains("push constant 0\npop local 2"),
+            "{}",
+            output.vm
+        );
 
+        let output =
+            Parser::parse(include_str!("../../../projects/10/Square/Square.jack")).unwrap();
+        // do Screen.setColor(true);
+        let set_true = "push constant 0\npush constant 0\nnot\ncall Screen.setColor 2";
+        let set_false = "push constant 0\npush constant 0\ncall Screen.setColor 2";
+        assert!(output.vm.contains(set_true), "{}", output.vm);
+        assert!(output.vm.contains(set_false), "{}", output.vm);
+        // Memory.alloc gets the dummy 0 like every other function call
+        let alloc = "function Square.new 0\npush constant 0\npush constant 3\ncall Memory.alloc 2\npop pointer 0";
+        assert!(output.vm.starts_with(alloc), "{}", output.vm);
+
+        let output =
+            Parser::parse(include_str!("../../../projects/10/Square/SquareGame.jack")).unwrap();
+        // let exit = false; and let exit = true;
+        assert!(
+            output.vm.contains("push constant 0\npop local 1"),
+            "{}",
+            output.vm
+        );
+        assert!(
+            output.vm.contains("push constant 0\nnot\npop local 1"),
+            "{}",
+            output.vm
+        );
+    }
+    #[test]
+    fn method_call_in_expression() {
+        let source =
+            "class Main { method int f() { return 1; } method int g() { return f() + 1; } }";
+        let output = Parser::parse(source).unwrap();
+        assert!(output
+            .vm
+            .contains("push pointer 0\ncall Main.f 1\npush constant 1\nadd"));
+    }
+    #[test]
+    fn ast() {
+        let source = "class Main {\n  field int x, y;\n  method void f(int a) {\n    let x[a] = -y + 1;\n    do Output.printInt(x);\n    return;\n  }\n}";
+        let class = Parser::parse_ast(source).unwrap().unwrap();
+        assert_eq!(class.name.name, "Main");
+        assert_eq!(class.span.length, source.len());
+        let names = class.var_decs[0]
+            .names
+            .iter()
+            .map(|name| name.name.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["x", "y"]);
+        assert_eq!(class.var_decs[0].span.line, 2);
+
+        let f = &class.subroutines[0];
+        assert_eq!(f.kind, SubroutineKind::Method);
+        assert_eq!(f.return_type, None);
+        assert_eq!(f.parameters[0]._type, Type::Int);
+        assert_eq!(f.statements.len(), 3);
+        match &f.statements[0] {
+            Statement::Let {
+                name,
+                index: Some(_),
+                value,
+                span,
+            } => {
+                assert_eq!(name.name, "x");
+                assert_eq!(span.line, 4);
+                assert_eq!(&source[span.start..span.end()], "let x[a] = -y + 1;");
+                assert!(matches!(
+                    value.term,
+                    Term::Unary {
+                        op: UnaryOp::Neg,
+                        ..
+                    }
+                ));
+                assert_eq!(value.ops.len(), 1);
+                assert_eq!(&source[value.span.start..value.span.end()], "-y + 1");
+            }
+            statement => panic!("Expected let x[a], got {:?}", statement),
+        }
+        match &f.statements[1] {
+            Statement::Do { call, .. } => {
+                assert_eq!(call.receiver.as_ref().unwrap().name, "Output");
+                assert_eq!(call.name.name, "printInt");
+                assert_eq!(
+                    &source[call.span.start..call.span.end()],
+                    "Output.printInt(x)"
+                );
+            }
+            statement => panic!("Expected do, got {:?}", statement),
+        }
+
+        assert_eq!(Parser::parse_ast("").unwrap(), None);
+    }
     #[test]
     fn empty_class() {
         let source = "class TEstClassName {}".to_string();
@@ -1367,6 +1272,42 @@ mod tests {
         assert!(output.is_err());
     }
     #[test]
+    fn recovery() {
+        let source = "class Main {
+            field int x y;
+            method void f() {
+                var int a;
+                let a = ;
+                if (a { let a = 1; }
+                do Output.printInt(a)
+            }
+            function void g(int) { return; }
+            function void h() { let x = 1; return; }
+        }";
+        let errors = Parser::parse_ast(source)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            errors,
+            vec![
+                "line 2, column 25: Expected ';', got y",
+                "line 5, column 25: Expected a term, got ;",
+                "line 6, column 23: Expected ')', got {",
+                "line 8, column 13: Expected ';', got }",
+                "line 9, column 32: Expected identifier, got )",
+            ]
+        );
+
+        let output = Parser::parse(source).unwrap_err()[0].render(source);
+        let first = "line 2, column 25: Expected ';', got y
+  |
+2 |             field int x y;
+  |                         ^";
+        assert!(output.starts_with(first), "{}", output);
+    }
+    #[test]
     fn class_with_vars() {
         let source =
             "class TEstClassName { static int field1, field2;\nfield someClass name1;}".to_string();
@@ -1391,7 +1332,8 @@ mod tests {
             &source,
         );
         println!("{:?}", output);
-        assert_eq!(output, "Expected Keyword(Do), got class on line 2");
+        assert_eq!(output.to_string(), "Expected do, got class");
+        assert_eq!(output.span().line, 2);
 
         let source = "class".chars().collect::<Vec<char>>();
         let token = &Token {
@@ -1409,39 +1351,6 @@ mod tests {
             &source,
         );
         println!("{:?}", output);
-        assert_eq!(
-            output,
-            "Expected Keyword(Let) | Symbol(And), got class on line 2"
-        );
-    }
-    #[test]
-    fn push_terminal_ast() {
-        let source = "let".chars().collect::<Vec<char>>();
-        let token = Token {
-            _type: TokenType::Keyword(ReservedKeywords::Let),
-            start: 0,
-            length: 3,
-            line: 1,
-        };
-        let mut parser = Parser::new();
-        parser.push_terminal(&token, &source);
-        assert_eq!(parser.xml_ast.len(), 3);
-        assert_eq!(parser.xml_ast[0], "<keyword>");
-        assert_eq!(parser.xml_ast[1], "let");
-        assert_eq!(parser.xml_ast[2], "</keyword>");
-
-        let source = "\"funny string\"".chars().collect::<Vec<char>>();
-        let token = Token {
-            _type: TokenType::String,
-            start: 0,
-            length: 14,
-            line: 1,
-        };
-        let mut parser = Parser::new();
-        parser.push_terminal(&token, &source);
-        assert_eq!(parser.xml_ast.len(), 3);
-        assert_eq!(parser.xml_ast[0], "<stringConstant>");
-        assert_eq!(parser.xml_ast[1], "funny string");
-        assert_eq!(parser.xml_ast[2], "</stringConstant>");
+        assert_eq!(output.to_string(), "Expected let or '&', got class");
     }
 }
@@ -0,0 +1,130 @@
+use crate::{
+    check_project, check_types, CodeGen, Diagnostic, Parser, ParserCodeOutput, ParserOptions,
+};
+
+/// Compiles the .jack files of a project together, given as (file name, source)
+/// Every file is parsed and the project checked before any code is generated,
+/// so on an error there is no output at all
+/// The outputs are in the order of files, None for a file without a class
+pub fn compile_project(
+    files: &[(String, String)],
+    options: &ParserOptions,
+) -> Result<Vec<Option<ParserCodeOutput>>, String> {
+    let mut classes = Vec::default();
+    let mut errors = Vec::default();
+    for (file_name, source) in files {
+        match Parser::parse_ast(source) {
+            Ok(Some(class)) => classes.push((file_name.clone(), class)),
+            Ok(None) => {}
+            Err(diagnostics) => errors.extend(diagnostics.into_iter().map(|mut diagnostic| {
+                diagnostic.file_name = file_name.clone();
+                diagnostic.render(source)
+            })),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    let errors = match check_project(&classes) {
+        Ok(symbols) => match options.type_check {
+            Some(mode) => classes
+                .iter()
+                .flat_map(|(file_name, class)| check_types(file_name, class, &symbols, mode))
+                .collect(),
+            None => Vec::default(),
+        },
+        Err(errors) => errors,
+    };
+    if !errors.is_empty() {
+        return Err(errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<String>>()
+            .join("\n"));
+    }
+
+    let mut classes = classes.into_iter().peekable();
+    let mut outputs = Vec::default();
+    for (file_name, source) in files {
+        let class = match classes.next_if(|(class_file_name, _)| class_file_name == file_name) {
+            Some((_, class)) => class,
+            None => {
+                outputs.push(None);
+                continue;
+            }
+        };
+        let mut code_gen = CodeGen::new(options);
+        code_gen.class(&class).map_err(|e| {
+            let mut diagnostic = Diagnostic::new(e, &source.chars().collect::<Vec<char>>());
+            diagnostic.file_name = file_name.clone();
+            diagnostic.render(source)
+        })?;
+        outputs.push(Some(ParserCodeOutput {
+            vm: code_gen.gen_vm_code(),
+        }));
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TypeCheck;
+
+    fn files(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn compile() {
+        let files = files(&[
+            ("Empty.jack", ""),
+            (
+                "Main.jack",
+                "class Main { function void main() { do Sys.halt(); return; } }",
+            ),
+        ]);
+        let outputs = compile_project(&files, &ParserOptions::default()).unwrap();
+        assert!(outputs[0].is_none());
+        assert!(outputs[1].as_ref().unwrap().vm.contains("call Sys.halt 1"));
+    }
+    #[test]
+    fn errors() {
+        let files = files(&[
+            (
+                "Main.jack",
+                "class Main { function void main() { do Sys.stop(); return; } }",
+            ),
+            ("Broken.jack", "class Broken {"),
+        ]);
+        let e = compile_project(&files, &ParserOptions::default()).unwrap_err();
+        assert!(
+            e.starts_with("Broken.jack, line 1, column 15: Expected '}', got end of file"),
+            "{}",
+            e
+        );
+
+        let e = compile_project(&files[..1], &ParserOptions::default()).unwrap_err();
+        assert_eq!(e, "Main.jack, line 1: Sys.stop is not defined");
+    }
+    #[test]
+    fn type_check() {
+        let files = files(&[(
+            "Main.jack",
+            "class Main { function void main() { var int i; let i = true; return; } }",
+        )]);
+        assert!(compile_project(&files, &ParserOptions::default()).is_ok());
+        let options = ParserOptions {
+            type_check: Some(TypeCheck::Lenient),
+            ..ParserOptions::default()
+        };
+        let e = compile_project(&files, &options).unwrap_err();
+        assert_eq!(
+            e,
+            "Main.jack, line 1: Cannot assign boolean to i of type int"
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::ast::Span;
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub _type: TokenType,
@@ -14,6 +18,13 @@ impl Token {
             line,
         }
     }
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start,
+            length: self.length,
+            line: self.line,
+        }
+    }
     pub fn get_source(&self, source: &[char]) -> String {
         match self._type {
             // Ignore the ""
@@ -28,17 +39,32 @@ impl Token {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     Keyword(ReservedKeywords),
     Symbol(Symbols),
     Integer(usize),
     String,
     Identifier,
+    Comment, // Only from Tokenizer::generate_tokens_with_comments
     EOF,
 }
+/// As written in the source, or what kind of token it is
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keyword(keyword) => write!(f, "{}", keyword.as_str()),
+            Self::Symbol(symbol) => write!(f, "'{}'", symbol.as_char()),
+            Self::Integer(_) => write!(f, "integer"),
+            Self::String => write!(f, "string"),
+            Self::Identifier => write!(f, "identifier"),
+            Self::Comment => write!(f, "comment"),
+            Self::EOF => write!(f, "end of file"),
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ReservedKeywords {
     Class,
     Constructor,
@@ -62,8 +88,35 @@ pub enum ReservedKeywords {
     While,
     Return,
 }
+impl ReservedKeywords {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Class => "class",
+            Self::Constructor => "constructor",
+            Self::Function => "function",
+            Self::Method => "method",
+            Self::Field => "field",
+            Self::Static => "static",
+            Self::Var => "var",
+            Self::Int => "int",
+            Self::Char => "char",
+            Self::Boolean => "boolean",
+            Self::Void => "void",
+            Self::True => "true",
+            Self::False => "false",
+            Self::Null => "null",
+            Self::This => "this",
+            Self::Let => "let",
+            Self::Do => "do",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::While => "while",
+            Self::Return => "return",
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Symbols {
     LeftParam,    // (
     RightParam,   // )
@@ -86,6 +139,32 @@ pub enum Symbols {
     Slash,        // /
     Dot,          // .
 }
+impl Symbols {
+    pub fn as_char(&self) -> char {
+        match self {
+            Self::LeftParam => '(',
+            Self::RightParam => ')',
+            Self::LeftBrace => '{',
+            Self::RightBrace => '}',
+            Self::LeftBracket => '[',
+            Self::RightBracket => ']',
+            Self::SemiColon => ';',
+            Self::Equal => '=',
+            Self::Plus => '+',
+            Self::Minus => '-',
+            Self::And => '&',
+            Self::Or => '|',
+            Self::Bang => '!',
+            Self::Tilde => '~',
+            Self::LessThan => '<',
+            Self::GreaterThan => '>',
+            Self::Comma => ',',
+            Self::Asterisk => '*',
+            Self::Slash => '/',
+            Self::Dot => '.',
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
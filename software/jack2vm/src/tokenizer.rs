@@ -1,9 +1,10 @@
-use crate::{ReservedKeywords, Symbols, Token, TokenType};
+use crate::{ast::Span, JackError, LexicalError, ReservedKeywords, Symbols, Token, TokenType};
 
 pub struct Tokenizer {
     start: usize,
     current: usize,
     line: usize,
+    keep_comments: bool, // Comments are tokens instead of whitespace
 }
 
 impl Tokenizer {
@@ -12,12 +13,27 @@ impl Tokenizer {
             start: 0,
             current: 0,
             line: 1,
+            keep_comments: false,
         }
     }
-    pub fn generate_tokens(source: &str) -> Result<Vec<Token>, String> {
+    /// Tokens of source, ending with EOF
+    pub fn generate_tokens(source: &str) -> Result<Vec<Token>, JackError> {
+        Self::tokenize(source, Self::new())
+    }
+    /// Tokens of source with a Comment token for every comment, for tools that print the source back
+    /// The Parser does not accept them
+    pub fn generate_tokens_with_comments(source: &str) -> Result<Vec<Token>, JackError> {
+        Self::tokenize(
+            source,
+            Self {
+                keep_comments: true,
+                ..Self::new()
+            },
+        )
+    }
+    fn tokenize(source: &str, mut tokenizer: Self) -> Result<Vec<Token>, JackError> {
         let source = source.chars().collect::<Vec<char>>();
         let mut tokens: Vec<Token> = Vec::new();
-        let mut tokenizer = Self::new();
         loop {
             let token = tokenizer.scan_token(&source);
             match token {
@@ -28,21 +44,39 @@ impl Tokenizer {
                     }
                     tokens.push(token);
                 }
-                Err(e) => return Err(e),
+                Err(error) => {
+                    // The token that could not be scanned
+                    let span = Span {
+                        start: tokenizer.start,
+                        length: tokenizer.current.min(source.len()) - tokenizer.start,
+                        line: source[..tokenizer.start]
+                            .iter()
+                            .filter(|c| **c == '\n')
+                            .count()
+                            + 1,
+                    };
+                    return Err(JackError::Lexical { error, span });
+                }
             }
         }
         Ok(tokens)
     }
-    fn scan_token(&mut self, source: &[char]) -> Result<Token, String> {
+    fn scan_token(&mut self, source: &[char]) -> Result<Token, LexicalError> {
         if let Some(err) = self.skip_whitespace_and_comments(source) {
             return Err(err);
         };
         if self.is_at_end(source) {
+            return Ok(Token::new(TokenType::EOF, self.current, 0, self.line));
+        }
+        // Only left by skip_whitespace_and_comments when they are kept
+        if self.is_comment_start(source) {
+            let line = self.line;
+            self.comment(source)?;
             return Ok(Token::new(
-                TokenType::EOF,
+                TokenType::Comment,
                 self.start,
                 self.current - self.start,
-                self.line,
+                line,
             ));
         }
 
@@ -75,17 +109,17 @@ impl Tokenizer {
                 '>' => Ok(self.make_token(TokenType::Symbol(Symbols::GreaterThan))),
                 '=' => Ok(self.make_token(TokenType::Symbol(Symbols::Equal))),
                 '~' => Ok(self.make_token(TokenType::Symbol(Symbols::Tilde))),
-                _ => Err(format!("Unknown token at {}, {}", self.start, c)),
+                _ => Err(LexicalError::UnknownCharacter(c)),
             }
         }
     }
-    fn is_at_end(&mut self, source: &[char]) -> bool {
+    fn is_at_end(&self, source: &[char]) -> bool {
         self.current >= source.len()
     }
     fn is_alpha(c: char) -> bool {
         c.is_alphabetic() || c == '_'
     }
-    fn identifier(&mut self, source: &[char]) -> Result<Token, String> {
+    fn identifier(&mut self, source: &[char]) -> Result<Token, LexicalError> {
         // Take chars if its still alpha or numeric
         while !self.is_at_end(source)
             && (Self::is_alpha(self.peek(source)) || self.peek(source).is_numeric())
@@ -118,41 +152,27 @@ impl Tokenizer {
             _ => Ok(self.make_token(TokenType::Identifier)),
         }
     }
-    fn integer(&mut self, source: &[char]) -> Result<Token, String> {
+    fn integer(&mut self, source: &[char]) -> Result<Token, LexicalError> {
         // Take chars if its  numeric
         while !self.is_at_end(source) && self.peek(source).is_numeric() {
             self.advance(source);
         }
-        match source[self.start..self.current]
-            .iter()
-            .collect::<String>()
-            .parse::<usize>()
-        {
-            Ok(value) => {
-                if value > 32767 {
-                    Err(format!(
-                        "Integer {} at {} is too large, should be within 0 and 32767",
-                        value, self.start
-                    ))
-                } else {
-                    Ok(self.make_token(TokenType::Integer(value)))
-                }
-            }
-            Err(e) => Err(format!(
-                "Failed to tokenize integer at {}, {}",
-                self.start, e
-            )),
+        let integer = source[self.start..self.current].iter().collect::<String>();
+        // is_numeric takes digits of other scripts as well
+        if let Some(c) = integer.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(LexicalError::UnknownCharacter(c));
+        }
+        match integer.parse::<usize>() {
+            Ok(value) if value <= 32767 => Ok(self.make_token(TokenType::Integer(value))),
+            _ => Err(LexicalError::IntegerTooLarge(integer)),
         }
     }
-    fn string(&mut self, source: &[char]) -> Result<Token, String> {
+    fn string(&mut self, source: &[char]) -> Result<Token, LexicalError> {
         while !self.is_at_end(source) && self.peek(source) != '\"' {
             self.advance(source);
         }
         if self.is_at_end(source) {
-            return Err(format!(
-                "String opened at {}, not closed at {}",
-                self.start, self.current
-            ));
+            return Err(LexicalError::UnclosedString);
         }
         // Consume the final "
         self.advance(source);
@@ -184,7 +204,45 @@ impl Tokenizer {
     fn peek_next(&self, source: &[char]) -> Option<char> {
         self.peek_n(source, 1)
     }
-    fn skip_whitespace_and_comments(&mut self, source: &[char]) -> Option<String> {
+    fn is_comment_start(&self, source: &[char]) -> bool {
+        !self.is_at_end(source)
+            && self.peek(source) == '/'
+            && matches!(self.peek_next(source), Some('/' | '*'))
+    }
+    /// Consumes the // or /* comment at current
+    fn comment(&mut self, source: &[char]) -> Result<(), LexicalError> {
+        self.start = self.current;
+        if self.peek_next(source) == Some('/') {
+            // // comment until end of line
+            self.current += 2; // Skip the //
+            while !self.is_at_end(source) && self.peek(source) != '\n' {
+                self.advance(source);
+            }
+            return Ok(());
+        }
+        self.current += 2; // Skip /*
+        loop {
+            while !self.is_at_end(source) && self.peek(source) != '*' {
+                let token = self.advance(source).unwrap();
+                // Skip any newlines here as well
+                if token == '\n' {
+                    self.line += 1;
+                }
+            }
+            if let Some(closing_c) = self.peek_next(source) {
+                if closing_c == '/' {
+                    self.current += 2;
+                    return Ok(());
+                } else {
+                    // Have not reached the end of the comment, consume "*"
+                    self.advance(source);
+                }
+            } else {
+                return Err(LexicalError::UnclosedComment);
+            }
+        }
+    }
+    fn skip_whitespace_and_comments(&mut self, source: &[char]) -> Option<LexicalError> {
         loop {
             if self.is_at_end(source) {
                 return None;
@@ -197,55 +255,12 @@ impl Tokenizer {
                 // new line
                 self.line += 1;
                 self.advance(source);
-            } else if c == '/' {
-                // Could be start of comment
-                let nc = self.peek_next(source);
-                match nc {
-                    Some(nc) => {
-                        match nc {
-                            '/' => {
-                                // // comment until end of line
-                                self.current += 2; // Skip the //
-                                while !self.is_at_end(source) && self.peek(source) != '\n' {
-                                    self.advance(source);
-                                }
-                            }
-                            '*' => {
-                                self.current += 2; // Skip /*
-                                loop {
-                                    while !self.is_at_end(source) && self.peek(source) != '*' {
-                                        let token = self.advance(source).unwrap();
-                                        // Skip any newlines here as well
-                                        if token == '\n' {
-                                            self.line += 1;
-                                        }
-                                    }
-                                    if let Some(closing_c) = self.peek_next(source) {
-                                        if closing_c == '/' {
-                                            self.current += 2;
-                                            break;
-                                        } else {
-                                            // Have not reached the end of the comment, consume "*"
-                                            self.advance(source);
-                                        }
-                                    } else {
-                                        return Some(format!(
-                                            "Expected comment opened at {}, to close",
-                                            self.start
-                                        ));
-                                    }
-                                }
-                            }
-                            _ => {
-                                // Not a comment
-                                return None;
-                            }
-                        }
-                    }
-                    None => {
-                        // Is a slash
-                        return None;
-                    }
+            } else if self.is_comment_start(source) {
+                if self.keep_comments {
+                    return None;
+                }
+                if let Err(e) = self.comment(source) {
+                    return Some(e);
                 }
             } else {
                 // Hit something that is not a whitespace
@@ -316,7 +331,7 @@ mod tests {
         let output = tokenizer.skip_whitespace_and_comments(&source);
         assert_eq!(tokenizer.current, 25);
         assert!(output.is_some());
-        assert_eq!(output.unwrap(), "Expected comment opened at 0, to close");
+        assert_eq!(output.unwrap(), LexicalError::UnclosedComment);
     }
     #[test]
     fn scan_whitespace() {
@@ -342,10 +357,7 @@ mod tests {
         let source = source.chars().collect::<Vec<char>>();
         let output = tokenizer.scan_token(&source);
         assert!(output.is_err());
-        assert_eq!(
-            output.err().unwrap(),
-            "Expected comment opened at 0, to close"
-        );
+        assert_eq!(output.err().unwrap(), LexicalError::UnclosedComment);
     }
     #[test]
     fn scan_unknown_token() {
@@ -354,7 +366,7 @@ mod tests {
         let source = source.chars().collect::<Vec<char>>();
         let output = tokenizer.scan_token(&source);
         assert!(output.is_err());
-        assert_eq!(output.err().unwrap(), "Unknown token at 0, %");
+        assert_eq!(output.err().unwrap(), LexicalError::UnknownCharacter('%'));
     }
     #[test]
     fn scan_string_token() {
@@ -372,10 +384,7 @@ mod tests {
         let source = source.chars().collect::<Vec<char>>();
         let output = tokenizer.scan_token(&source);
         assert!(output.is_err());
-        assert_eq!(
-            output.err().unwrap(),
-            "String opened at 0, not closed at 19"
-        );
+        assert_eq!(output.err().unwrap(), LexicalError::UnclosedString);
     }
     #[test]
     fn scan_integer() {
@@ -395,7 +404,7 @@ mod tests {
         assert!(output.is_err());
         assert_eq!(
             output.err().unwrap(),
-            "Integer 1234567 at 0 is too large, should be within 0 and 32767"
+            LexicalError::IntegerTooLarge(String::from("1234567"))
         );
     }
     #[test]
@@ -481,4 +490,27 @@ mod tests {
             TokenType::Keyword(ReservedKeywords::Boolean)
         ));
     }
+    #[test]
+    fn generate_comments() {
+        let source = "/** doc\n */ class // Main\nMain {} /* end */";
+        let tokens = Tokenizer::generate_tokens_with_comments(source).unwrap();
+        let chars = source.chars().collect::<Vec<char>>();
+        let comments = tokens
+            .iter()
+            .filter(|token| token._type == TokenType::Comment)
+            .map(|token| (token.get_source(&chars), token.line))
+            .collect::<Vec<(String, usize)>>();
+        assert_eq!(
+            comments,
+            vec![
+                (String::from("/** doc\n */"), 1),
+                (String::from("// Main"), 2),
+                (String::from("/* end */"), 3)
+            ]
+        );
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(tokens[3].line, 3);
+        // Without them the tokens are the same as ever
+        assert_eq!(Tokenizer::generate_tokens(source).unwrap().len(), 5);
+    }
 }
@@ -0,0 +1,451 @@
+//! Opt-in type checker over the AST of a class
+//!
+//! Jack only has 16 bit words, the declared types of variables are never used by the CodeGen
+//! beyond method calls. Needs the signatures of the project, so it runs after check_project
+use std::collections::HashMap;
+
+use crate::{
+    ast::{
+        BinaryOp, Class, Expression, KeywordConstant, Span, Statement, SubroutineCall,
+        SubroutineDec, Term, Type, UnaryOp,
+    },
+    ProjectError, ProjectSymbols, TypeError,
+};
+
+/// How strict the type checker is, null can be assigned to any object in both
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeCheck {
+    Strict,
+    // Jack's weak typing, int and char are the same, and an Array is a pointer
+    // that can be used as an int or any object, as Memory.alloc returns one
+    Lenient,
+}
+
+/// Type of an expression
+#[derive(Debug, Clone, PartialEq)]
+enum Inferred {
+    Type(Type),
+    Null,
+    Unknown, // Array elements, void calls and what the other passes report
+}
+impl Inferred {
+    fn name(&self) -> &str {
+        match self {
+            Self::Type(_type) => _type.name(),
+            Self::Null => "null",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Type errors of a class, given the signatures of the whole project
+pub fn check_types(
+    file_name: &str,
+    class: &Class,
+    symbols: &ProjectSymbols,
+    mode: TypeCheck,
+) -> Vec<ProjectError> {
+    let mut type_checker = TypeChecker {
+        symbols,
+        mode,
+        file_name,
+        class,
+        subroutine: None,
+        variables: HashMap::new(),
+        errors: Vec::default(),
+    };
+    type_checker.class();
+    type_checker.errors
+}
+
+/// Pass over a single class
+struct TypeChecker<'a> {
+    symbols: &'a ProjectSymbols,
+    mode: TypeCheck,
+    file_name: &'a str,
+    class: &'a Class,
+    subroutine: Option<&'a SubroutineDec>,
+    variables: HashMap<&'a str, &'a Type>, // Every variable in scope
+    errors: Vec<ProjectError>,
+}
+impl<'a> TypeChecker<'a> {
+    fn error(&mut self, span: Span, error: TypeError) {
+        self.errors.push(ProjectError {
+            file_name: self.file_name.to_string(),
+            error: error.at(span),
+        });
+    }
+
+    fn is_array(_type: &Type) -> bool {
+        *_type == Type::Class(String::from("Array"))
+    }
+    /// Can be used in arithmetic
+    fn is_numeric(&self, inferred: &Inferred) -> bool {
+        match inferred {
+            Inferred::Type(Type::Int | Type::Char) | Inferred::Unknown => true,
+            Inferred::Type(_type) => self.mode == TypeCheck::Lenient && Self::is_array(_type),
+            Inferred::Null => false,
+        }
+    }
+    fn is_boolean(inferred: &Inferred) -> bool {
+        matches!(inferred, Inferred::Type(Type::Boolean) | Inferred::Unknown)
+    }
+    fn is_assignable(&self, to: &Type, from: &Inferred) -> bool {
+        let from = match from {
+            Inferred::Unknown => return true,
+            Inferred::Null => return matches!(to, Type::Class(_)),
+            Inferred::Type(from) => from,
+        };
+        if from == to {
+            return true;
+        }
+        if self.mode == TypeCheck::Strict {
+            return false;
+        }
+        match (from, to) {
+            (Type::Int | Type::Char, Type::Int | Type::Char) => true,
+            (Type::Int | Type::Char | Type::Class(_), Type::Class(_))
+            | (Type::Class(_), Type::Int | Type::Char) => {
+                Self::is_array(from) || Self::is_array(to)
+            }
+            _ => false,
+        }
+    }
+
+    fn class(&mut self) {
+        let class = self.class;
+        let mut class_variables = HashMap::new();
+        for var_dec in &class.var_decs {
+            for name in &var_dec.names {
+                class_variables.insert(name.name.as_str(), &var_dec._type);
+            }
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine = Some(subroutine);
+            self.variables = class_variables.clone();
+            for parameter in &subroutine.parameters {
+                self.variables
+                    .insert(&parameter.name.name, &parameter._type);
+            }
+            for var_dec in &subroutine.var_decs {
+                for name in &var_dec.names {
+                    self.variables.insert(&name.name, &var_dec._type);
+                }
+            }
+            self.statements(&subroutine.statements);
+        }
+    }
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name,
+                index,
+                value,
+                span,
+            } => {
+                let value_type = self.expression(value);
+                if let Some(index) = index {
+                    // Elements of an Array have no type
+                    self.expression(index);
+                    return;
+                }
+                let variable_type = match self.variables.get(name.name.as_str()) {
+                    Some(variable_type) => *variable_type,
+                    // Reported by the CodeGen
+                    None => return,
+                };
+                if !self.is_assignable(variable_type, &value_type) {
+                    self.error(
+                        *span,
+                        TypeError::Assign {
+                            value: value_type.name().to_string(),
+                            variable: name.name.clone(),
+                            _type: variable_type.name().to_string(),
+                        },
+                    );
+                }
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+                ..
+            } => {
+                self.condition(condition);
+                self.statements(statements);
+                if let Some(else_statements) = else_statements {
+                    self.statements(else_statements);
+                }
+            }
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => {
+                self.condition(condition);
+                self.statements(statements);
+            }
+            Statement::Do { call, .. } => {
+                self.subroutine_call(call);
+            }
+            Statement::Return { value, span } => {
+                let subroutine = self.subroutine.unwrap();
+                let name = format!("{}.{}", self.class.name.name, subroutine.name.name);
+                let value_type = value.as_ref().map(|value| self.expression(value));
+                match (&subroutine.return_type, value_type) {
+                    (None, Some(_)) => self.error(*span, TypeError::ReturnFromVoid(name)),
+                    (Some(return_type), None) => self.error(
+                        *span,
+                        TypeError::MissingReturnValue {
+                            subroutine: name,
+                            return_type: return_type.name().to_string(),
+                        },
+                    ),
+                    (Some(return_type), Some(value_type)) => {
+                        if !self.is_assignable(return_type, &value_type) {
+                            self.error(
+                                *span,
+                                TypeError::Return {
+                                    value: value_type.name().to_string(),
+                                    subroutine: name,
+                                    return_type: return_type.name().to_string(),
+                                },
+                            );
+                        }
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+    fn condition(&mut self, condition: &Expression) {
+        let condition_type = self.expression(condition);
+        if !Self::is_boolean(&condition_type) {
+            self.error(
+                condition.span,
+                TypeError::Condition(condition_type.name().to_string()),
+            );
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Inferred {
+        let mut left = self.term(&expression.term);
+        for (op, term) in &expression.ops {
+            let right = self.term(term);
+            left = self.binary_op(op, left, right, expression.span);
+        }
+        left
+    }
+    fn binary_op(
+        &mut self,
+        op: &BinaryOp,
+        left: Inferred,
+        right: Inferred,
+        span: Span,
+    ) -> Inferred {
+        let (operands_ok, result) = match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => (
+                self.is_numeric(&left) && self.is_numeric(&right),
+                Inferred::Type(Type::Int),
+            ),
+            BinaryOp::LessThan | BinaryOp::GreaterThan => (
+                self.is_numeric(&left) && self.is_numeric(&right),
+                Inferred::Type(Type::Boolean),
+            ),
+            // Bitwise on ints
+            BinaryOp::And | BinaryOp::Or => {
+                if Self::is_boolean(&left) && Self::is_boolean(&right) {
+                    (true, Inferred::Type(Type::Boolean))
+                } else {
+                    (
+                        self.is_numeric(&left) && self.is_numeric(&right),
+                        Inferred::Type(Type::Int),
+                    )
+                }
+            }
+            BinaryOp::Equal => (true, Inferred::Type(Type::Boolean)),
+        };
+        if !operands_ok {
+            self.error(
+                span,
+                TypeError::BinaryOperands {
+                    op: op.symbol(),
+                    left: left.name().to_string(),
+                    right: right.name().to_string(),
+                },
+            );
+        }
+        result
+    }
+    fn term(&mut self, term: &Term) -> Inferred {
+        match term {
+            Term::Integer(..) => Inferred::Type(Type::Int),
+            Term::String(..) => Inferred::Type(Type::Class(String::from("String"))),
+            Term::Keyword(keyword, _) => match keyword {
+                KeywordConstant::True | KeywordConstant::False => Inferred::Type(Type::Boolean),
+                KeywordConstant::Null => Inferred::Null,
+                KeywordConstant::This => Inferred::Type(Type::Class(self.class.name.name.clone())),
+            },
+            Term::Variable(name) => match self.variables.get(name.name.as_str()) {
+                Some(_type) => Inferred::Type((*_type).clone()),
+                None => Inferred::Unknown,
+            },
+            Term::Index { index, .. } => {
+                self.expression(index);
+                Inferred::Unknown
+            }
+            Term::Call(call) => self.subroutine_call(call),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary { op, term, span } => {
+                let operand = self.term(term);
+                let result = match op {
+                    UnaryOp::Neg => self
+                        .is_numeric(&operand)
+                        .then_some(Inferred::Type(Type::Int)),
+                    UnaryOp::Not if Self::is_boolean(&operand) => {
+                        Some(Inferred::Type(Type::Boolean))
+                    }
+                    UnaryOp::Not => self
+                        .is_numeric(&operand)
+                        .then_some(Inferred::Type(Type::Int)),
+                };
+                result.unwrap_or_else(|| {
+                    self.error(
+                        *span,
+                        TypeError::UnaryOperand {
+                            op: op.symbol(),
+                            operand: operand.name().to_string(),
+                        },
+                    );
+                    Inferred::Unknown
+                })
+            }
+        }
+    }
+    fn subroutine_call(&mut self, call: &SubroutineCall) -> Inferred {
+        for argument in &call.arguments {
+            self.expression(argument);
+        }
+        let class_name = match &call.receiver {
+            None => self.class.name.name.as_str(),
+            Some(receiver) => match self.variables.get(receiver.name.as_str()) {
+                Some(Type::Class(class_name)) => class_name.as_str(),
+                Some(_type) => {
+                    self.error(
+                        receiver.span,
+                        TypeError::CallOnPrimitive {
+                            variable: receiver.name.clone(),
+                            subroutine: call.name.name.clone(),
+                            _type: _type.name().to_string(),
+                        },
+                    );
+                    return Inferred::Unknown;
+                }
+                None => &receiver.name,
+            },
+        };
+        match self
+            .symbols
+            .signature(class_name, &call.name.name)
+            .and_then(|signature| signature.return_type.clone())
+        {
+            Some(return_type) => Inferred::Type(return_type),
+            None => Inferred::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_project, Parser};
+
+    fn check(sources: &[&str], mode: TypeCheck) -> Vec<String> {
+        let classes = sources
+            .iter()
+            .map(|source| {
+                let class = Parser::parse_ast(source).unwrap().unwrap();
+                (format!("{}.jack", class.name.name), class)
+            })
+            .collect::<Vec<(String, Class)>>();
+        let symbols = check_project(&classes).unwrap();
+        classes
+            .iter()
+            .flat_map(|(file_name, class)| check_types(file_name, class, &symbols, mode))
+            .map(|error| error.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn weak_typing() {
+        let main = "class Main {
+            function Point main() {
+                var Array a;
+                var Point p;
+                var char c;
+                var int i;
+                let a = Memory.alloc(2);
+                let a = a + 1;
+                let p = a;
+                let p = null;
+                let c = 65 + i;
+                let i = c - a[0];
+                if ((i < 3) & ~(p = null)) { let i = i & 255; }
+                return Point.new();
+            }
+        }";
+        let point = "class Point {
+            constructor Point new() { return this; }
+        }";
+        assert_eq!(
+            check(&[main, point], TypeCheck::Lenient),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check(&[main, point], TypeCheck::Strict),
+            vec![
+                "Main.jack, line 8: Cannot use + on Array and int",
+                "Main.jack, line 8: Cannot assign int to a of type Array",
+                "Main.jack, line 9: Cannot assign Array to p of type Point",
+                "Main.jack, line 11: Cannot assign int to c of type char",
+            ]
+        );
+    }
+    #[test]
+    fn type_errors() {
+        let main = "class Main {
+            field int x;
+            method void f() {
+                var String s;
+                var boolean b;
+                let s = 3;
+                let x = s + 1;
+                let b = -b;
+                while (x) { do x.g(); }
+                return x;
+            }
+            method int g() {
+                if (true) { return; }
+                return \"x\";
+            }
+        }";
+        assert_eq!(
+            check(&[main], TypeCheck::Lenient),
+            vec![
+                "Main.jack, line 6: Cannot assign int to s of type String",
+                "Main.jack, line 7: Cannot use + on String and int",
+                "Main.jack, line 8: Cannot use - on boolean",
+                "Main.jack, line 9: Condition must be boolean, got int",
+                "Main.jack, line 9: Cannot call x.g, x is of type int",
+                "Main.jack, line 10: Main.f is void, it cannot return a value",
+                "Main.jack, line 13: Missing return value, Main.g returns int",
+                "Main.jack, line 14: Cannot return String from Main.g, it returns int",
+            ]
+        );
+    }
+}
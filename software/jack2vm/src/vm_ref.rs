@@ -1,3 +1,4 @@
+#[allow(non_camel_case_types)]
 pub enum VM_OPS {
     ADD,
     SUB,
@@ -8,6 +9,9 @@ pub enum VM_OPS {
     AND,
     OR,
     NOT,
+    // Extended commands, vm2hack --math-commands
+    MUL,
+    DIV,
 }
 impl VM_OPS {
     pub fn to_vm_string(&self) -> String {
@@ -21,6 +25,8 @@ impl VM_OPS {
             Self::AND => "and",
             Self::OR => "or",
             Self::NOT => "not",
+            Self::MUL => "mul",
+            Self::DIV => "div",
         };
         s.to_string()
     }
@@ -0,0 +1,352 @@
+use crate::{
+    ast::{
+        Class, ClassVarDec, Expression, Parameter, Statement, SubroutineCall, SubroutineDec, Term,
+        Type, VarDec,
+    },
+    Token, TokenType,
+};
+
+/// Lines end in CRLF like the course files
+const NEWLINE: &str = "\r\n";
+
+/// XML parse tree of a class in the format of the course, Xxx.xml
+pub fn class_to_xml(class: &Class) -> String {
+    let mut xml = XmlWriter::default();
+    xml.class(class);
+    xml.lines.join(NEWLINE) + NEWLINE
+}
+
+/// XML tokens of a file in the format of the course, XxxT.xml
+pub fn tokens_to_xml(tokens: &[Token], source: &[char]) -> String {
+    let mut xml = XmlWriter::default();
+    xml.open("tokens");
+    // Not nested in the course files
+    xml.depth = 0;
+    for token in tokens {
+        let tag = match token._type {
+            TokenType::Keyword(_) => "keyword",
+            TokenType::Symbol(_) => "symbol",
+            TokenType::Integer(_) => "integerConstant",
+            TokenType::String => "stringConstant",
+            TokenType::Identifier => "identifier",
+            TokenType::Comment | TokenType::EOF => continue,
+        };
+        xml.terminal(tag, &token.get_source(source));
+    }
+    xml.close("tokens");
+    xml.lines.join(NEWLINE) + NEWLINE
+}
+
+/// Text of a terminal, escaped like the course files
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Pass over the AST of a class, writing it out as the parse tree
+/// The terminals the AST does not keep, like symbols, follow from the grammar
+#[derive(Default)]
+struct XmlWriter {
+    lines: Vec<String>,
+    depth: usize, // Of the open tags, indented by 2 spaces each
+}
+impl XmlWriter {
+    fn indent(&self) -> String {
+        "  ".repeat(self.depth)
+    }
+    fn open(&mut self, tag: &str) {
+        self.lines.push(format!("{}<{}>", self.indent(), tag));
+        self.depth += 1;
+    }
+    fn close(&mut self, tag: &str) {
+        self.depth = self.depth.saturating_sub(1);
+        self.lines.push(format!("{}</{}>", self.indent(), tag));
+    }
+    fn terminal(&mut self, tag: &str, text: &str) {
+        self.lines.push(format!(
+            "{}<{1}> {2} </{1}>",
+            self.indent(),
+            tag,
+            escape(text)
+        ));
+    }
+    fn keyword(&mut self, keyword: &str) {
+        self.terminal("keyword", keyword);
+    }
+    fn symbol(&mut self, symbol: &str) {
+        self.terminal("symbol", symbol);
+    }
+    fn identifier(&mut self, name: &str) {
+        self.terminal("identifier", name);
+    }
+    fn _type(&mut self, _type: &Type) {
+        match _type {
+            Type::Class(name) => self.identifier(name),
+            _ => self.keyword(_type.name()),
+        }
+    }
+    /// name (',' name)*
+    fn names<'a>(&mut self, names: impl Iterator<Item = &'a str>) {
+        for (i, name) in names.enumerate() {
+            if i > 0 {
+                self.symbol(",");
+            }
+            self.identifier(name);
+        }
+    }
+
+    fn class(&mut self, class: &Class) {
+        self.open("class");
+        self.keyword("class");
+        self.identifier(&class.name.name);
+        self.symbol("{");
+        for var_dec in &class.var_decs {
+            self.class_var_dec(var_dec);
+        }
+        for subroutine in &class.subroutines {
+            self.subroutine_dec(subroutine);
+        }
+        self.symbol("}");
+        self.close("class");
+    }
+    fn class_var_dec(&mut self, var_dec: &ClassVarDec) {
+        self.open("classVarDec");
+        self.keyword(var_dec.kind.keyword());
+        self._type(&var_dec._type);
+        self.names(var_dec.names.iter().map(|name| name.name.as_str()));
+        self.symbol(";");
+        self.close("classVarDec");
+    }
+    fn subroutine_dec(&mut self, subroutine: &SubroutineDec) {
+        self.open("subroutineDec");
+        self.keyword(subroutine.kind.keyword());
+        match &subroutine.return_type {
+            Some(_type) => self._type(_type),
+            None => self.keyword("void"),
+        }
+        self.identifier(&subroutine.name.name);
+        self.symbol("(");
+        self.parameter_list(&subroutine.parameters);
+        self.symbol(")");
+        self.open("subroutineBody");
+        self.symbol("{");
+        for var_dec in &subroutine.var_decs {
+            self.var_dec(var_dec);
+        }
+        self.statements(&subroutine.statements);
+        self.symbol("}");
+        self.close("subroutineBody");
+        self.close("subroutineDec");
+    }
+    fn parameter_list(&mut self, parameters: &[Parameter]) {
+        self.open("parameterList");
+        for (i, parameter) in parameters.iter().enumerate() {
+            if i > 0 {
+                self.symbol(",");
+            }
+            self._type(&parameter._type);
+            self.identifier(&parameter.name.name);
+        }
+        self.close("parameterList");
+    }
+    fn var_dec(&mut self, var_dec: &VarDec) {
+        self.open("varDec");
+        self.keyword("var");
+        self._type(&var_dec._type);
+        self.names(var_dec.names.iter().map(|name| name.name.as_str()));
+        self.symbol(";");
+        self.close("varDec");
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        self.open("statements");
+        for statement in statements {
+            self.statement(statement);
+        }
+        self.close("statements");
+    }
+    /// '{' statements '}'
+    fn block(&mut self, statements: &[Statement]) {
+        self.symbol("{");
+        self.statements(statements);
+        self.symbol("}");
+    }
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => {
+                self.open("letStatement");
+                self.keyword("let");
+                self.identifier(&name.name);
+                if let Some(index) = index {
+                    self.symbol("[");
+                    self.expression(index);
+                    self.symbol("]");
+                }
+                self.symbol("=");
+                self.expression(value);
+                self.symbol(";");
+                self.close("letStatement");
+            }
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+                ..
+            } => {
+                self.open("ifStatement");
+                self.keyword("if");
+                self.symbol("(");
+                self.expression(condition);
+                self.symbol(")");
+                self.block(statements);
+                if let Some(else_statements) = else_statements {
+                    self.keyword("else");
+                    self.block(else_statements);
+                }
+                self.close("ifStatement");
+            }
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => {
+                self.open("whileStatement");
+                self.keyword("while");
+                self.symbol("(");
+                self.expression(condition);
+                self.symbol(")");
+                self.block(statements);
+                self.close("whileStatement");
+            }
+            Statement::Do { call, .. } => {
+                self.open("doStatement");
+                self.keyword("do");
+                self.subroutine_call(call);
+                self.symbol(";");
+                self.close("doStatement");
+            }
+            Statement::Return { value, .. } => {
+                self.open("returnStatement");
+                self.keyword("return");
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.symbol(";");
+                self.close("returnStatement");
+            }
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.open("expression");
+        self.term(&expression.term);
+        for (op, term) in &expression.ops {
+            self.symbol(op.symbol());
+            self.term(term);
+        }
+        self.close("expression");
+    }
+    fn term(&mut self, term: &Term) {
+        self.open("term");
+        match term {
+            Term::Integer(x, _) => self.terminal("integerConstant", &x.to_string()),
+            Term::String(string, _) => self.terminal("stringConstant", string),
+            Term::Keyword(keyword, _) => self.keyword(keyword.keyword()),
+            Term::Variable(name) => self.identifier(&name.name),
+            Term::Index { name, index, .. } => {
+                self.identifier(&name.name);
+                self.symbol("[");
+                self.expression(index);
+                self.symbol("]");
+            }
+            Term::Call(call) => self.subroutine_call(call),
+            Term::Parenthesized(expression, _) => {
+                self.symbol("(");
+                self.expression(expression);
+                self.symbol(")");
+            }
+            Term::Unary { op, term, .. } => {
+                self.symbol(op.symbol());
+                self.term(term);
+            }
+        }
+        self.close("term");
+    }
+    fn subroutine_call(&mut self, call: &SubroutineCall) {
+        if let Some(receiver) = &call.receiver {
+            self.identifier(&receiver.name);
+            self.symbol(".");
+        }
+        self.identifier(&call.name.name);
+        self.symbol("(");
+        self.open("expressionList");
+        for (i, argument) in call.arguments.iter().enumerate() {
+            if i > 0 {
+                self.symbol(",");
+            }
+            self.expression(argument);
+        }
+        self.close("expressionList");
+        self.symbol(")");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compare_ignoring_whitespace, Parser, Tokenizer};
+    use std::{fs, path::Path};
+
+    #[test]
+    fn terminal() {
+        let mut xml = XmlWriter::default();
+        xml.open("term");
+        xml.keyword("this");
+        xml.symbol("<");
+        xml.terminal("stringConstant", "funny string");
+        xml.close("term");
+        assert_eq!(
+            xml.lines,
+            vec![
+                "<term>",
+                "  <keyword> this </keyword>",
+                "  <symbol> &lt; </symbol>",
+                "  <stringConstant> funny string </stringConstant>",
+                "</term>"
+            ]
+        );
+    }
+    /// Same as the files the course ships with projects/10, but for the line endings of the checkout
+    #[test]
+    fn course_files() {
+        let projects = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../projects/10");
+        let mut n_compared = 0;
+        for project in ["ArrayTest", "ExpressionLessSquare", "Square"] {
+            for entry in fs::read_dir(projects.join(project)).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_none_or(|e| e != "jack") {
+                    continue;
+                }
+                let source = fs::read_to_string(&path).unwrap();
+                let chars = source.chars().collect::<Vec<char>>();
+                let tokens = tokens_to_xml(&Tokenizer::generate_tokens(&source).unwrap(), &chars);
+                let tree = class_to_xml(&Parser::parse_ast(&source).unwrap().unwrap());
+                let stem = path.file_stem().unwrap().to_string_lossy();
+                for (xml, reference) in [
+                    (tokens, path.with_file_name(format!("{}T.xml", stem))),
+                    (tree, path.with_file_name(format!("{}.xml", stem))),
+                ] {
+                    let reference = fs::read_to_string(reference).unwrap();
+                    assert_eq!(compare_ignoring_whitespace(&xml, &reference), None);
+                    assert_eq!(xml.replace("\r\n", "\n"), reference.replace("\r\n", "\n"));
+                    n_compared += 1;
+                }
+            }
+        }
+        assert_eq!(n_compared, 14);
+    }
+}
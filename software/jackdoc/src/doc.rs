@@ -0,0 +1,195 @@
+//! Doc comments of a class, attached to the declarations they come right before
+use jack2vm::{
+    ast::{ClassVarKind, SubroutineKind},
+    Diagnostic, Parser, TokenType, Tokenizer,
+};
+
+#[derive(Debug, PartialEq)]
+pub struct ClassDoc {
+    pub name: String,
+    pub doc: String,
+    pub fields: Vec<FieldDoc>,
+    pub subroutines: Vec<SubroutineDoc>,
+}
+
+/// One declaration, which can name several variables
+#[derive(Debug, PartialEq)]
+pub struct FieldDoc {
+    pub kind: ClassVarKind,
+    pub declaration: String, // As in the source, field int x, y
+    pub doc: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SubroutineDoc {
+    pub kind: SubroutineKind,
+    pub name: String,
+    pub signature: String, // method int getX(int a), without the class name
+    pub doc: String,
+}
+
+/// None for a file without a class
+pub fn class_doc(source: &str) -> Result<Option<ClassDoc>, Vec<Diagnostic>> {
+    let class = match Parser::parse_ast(source)? {
+        Some(class) => class,
+        None => return Ok(None),
+    };
+    let chars = source.chars().collect::<Vec<char>>();
+    let tokens = Tokenizer::generate_tokens_with_comments(source)
+        .map_err(|e| vec![Diagnostic::new(e, &chars)])?;
+    // Doc comments by the offset of the token that follows them
+    let mut docs = Vec::default();
+    for (comment, next) in tokens.iter().zip(tokens.iter().skip(1)) {
+        let text = chars[comment.start..comment.start + comment.length]
+            .iter()
+            .collect::<String>();
+        if comment._type == TokenType::Comment && text.starts_with("/**") && text.len() > 4 {
+            docs.push((next.start, doc_text(&text)));
+        }
+    }
+    let doc_before = |start: usize| {
+        docs.iter()
+            .find(|(next_start, _)| *next_start == start)
+            .map(|(_, doc)| doc.clone())
+            .unwrap_or_default()
+    };
+
+    let fields = class
+        .var_decs
+        .iter()
+        .map(|var_dec| FieldDoc {
+            kind: var_dec.kind,
+            declaration: format!(
+                "{} {} {}",
+                var_dec.kind.keyword(),
+                var_dec._type.name(),
+                var_dec
+                    .names
+                    .iter()
+                    .map(|name| name.name.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+            doc: doc_before(var_dec.span.start),
+        })
+        .collect();
+    let subroutines = class
+        .subroutines
+        .iter()
+        .map(|subroutine| SubroutineDoc {
+            kind: subroutine.kind,
+            name: subroutine.name.name.clone(),
+            signature: format!(
+                "{} {} {}({})",
+                subroutine.kind.keyword(),
+                subroutine
+                    .return_type
+                    .as_ref()
+                    .map_or("void", |_type| _type.name()),
+                subroutine.name.name,
+                subroutine
+                    .parameters
+                    .iter()
+                    .map(|parameter| format!("{} {}", parameter._type.name(), parameter.name.name))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            doc: doc_before(subroutine.span.start),
+        })
+        .collect();
+    Ok(Some(ClassDoc {
+        doc: doc_before(class.span.start),
+        name: class.name.name,
+        fields,
+        subroutines,
+    }))
+}
+
+/// Text of a /** */ comment without the stars that start its lines
+/// Lines are joined into paragraphs, a blank line separates them
+fn doc_text(comment: &str) -> String {
+    let inner = comment
+        .trim_start_matches("/**")
+        .trim_end_matches("*/")
+        .trim_end_matches('*');
+    let mut paragraphs: Vec<String> = vec![String::new()];
+    for line in inner.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix('*').unwrap_or(line).trim();
+        let paragraph = paragraphs.last_mut().unwrap();
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(String::new());
+            }
+        } else {
+            if !paragraph.is_empty() {
+                paragraph.push(' ');
+            }
+            paragraph.push_str(line);
+        }
+    }
+    paragraphs.retain(|paragraph| !paragraph.is_empty());
+    paragraphs.join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attach() {
+        let source = "/**
+ * A point.
+ *
+ * Immutable.
+ */
+class Point {
+    /** Coordinates */
+    field int x, y;
+    static int count; /** Not a doc of anything */
+
+    // Plain comments are not docs
+    constructor Point new(int ax, int ay) {
+        return this;
+    }
+    /** Horizontal
+      coordinate */
+    method int getX() { return x; }
+}";
+        let doc = class_doc(source).unwrap().unwrap();
+        assert_eq!(doc.name, "Point");
+        assert_eq!(doc.doc, "A point.\n\nImmutable.");
+        assert_eq!(
+            doc.fields,
+            vec![
+                FieldDoc {
+                    kind: ClassVarKind::Field,
+                    declaration: String::from("field int x, y"),
+                    doc: String::from("Coordinates"),
+                },
+                FieldDoc {
+                    kind: ClassVarKind::Static,
+                    declaration: String::from("static int count"),
+                    doc: String::new(),
+                }
+            ]
+        );
+        assert_eq!(doc.subroutines[0].doc, "");
+        assert_eq!(
+            doc.subroutines[0].signature,
+            "constructor Point new(int ax, int ay)"
+        );
+        assert_eq!(
+            doc.subroutines[1],
+            SubroutineDoc {
+                kind: SubroutineKind::Method,
+                name: String::from("getX"),
+                signature: String::from("method int getX()"),
+                doc: String::from("Horizontal coordinate"),
+            }
+        );
+
+        assert_eq!(class_doc("/** nothing */").unwrap(), None);
+        assert!(class_doc("class {").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! API pages of a Jack project from its /** */ doc comments
+//!
+//! Writes a page per class and an index into <project>/doc, the OS classes the project
+//! does not define itself are documented along with it
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+mod doc;
+mod render;
+
+use doc::ClassDoc;
+use render::Format;
+
+const USAGE: &str =
+    "Usage: jackdoc [--html] [--output folder] [--os-folder folder] [--no-os] project";
+
+/// OS of this repo
+const OS_FOLDER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../jack/os");
+
+type ProgResult = Result<(), String>;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    project: PathBuf,
+    output: Option<PathBuf>, // <project>/doc by default
+    os: Option<PathBuf>,
+    format: Format,
+}
+impl Options {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut project = None;
+        let mut output = None;
+        let mut os = Some(PathBuf::from(OS_FOLDER));
+        let mut format = Format::Markdown;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--html" => format = Format::Html,
+                "--output" => match args.next() {
+                    Some(folder) => output = Some(PathBuf::from(folder)),
+                    None => return Err(String::from("Expected a folder after --output")),
+                },
+                "--os-folder" => match args.next() {
+                    Some(folder) => os = Some(PathBuf::from(folder)),
+                    None => return Err(String::from("Expected a folder after --os-folder")),
+                },
+                "--no-os" => os = None,
+                _ if project.is_none() && !arg.starts_with("--") => {
+                    project = Some(PathBuf::from(arg))
+                }
+                _ => return Err(String::from(USAGE)),
+            }
+        }
+        match project {
+            Some(project) => Ok(Self {
+                project,
+                output,
+                os,
+                format,
+            }),
+            None => Err(String::from(USAGE)),
+        }
+    }
+}
+
+/// Docs of the classes of the .jack files in folder, sorted by class name
+fn folder_docs(folder: &Path) -> Result<Vec<ClassDoc>, String> {
+    let mut docs = Vec::default();
+    let mut errors = Vec::default();
+    for entry in fs::read_dir(folder).map_err(|e| format!("{}: {}", folder.display(), e))? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if !path.is_file() || path.extension().is_none_or(|e| e != "jack") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        match doc::class_doc(&source) {
+            Ok(Some(doc)) => docs.push(doc),
+            Ok(None) => {}
+            Err(diagnostics) => {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                errors.extend(diagnostics.into_iter().map(|mut diagnostic| {
+                    diagnostic.file_name = file_name.to_string();
+                    diagnostic.render(&source)
+                }))
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    docs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(docs)
+}
+
+fn generate(options: &Options) -> ProgResult {
+    let project = folder_docs(&options.project)?;
+    let os = match &options.os {
+        Some(folder) => folder_docs(folder)?
+            .into_iter()
+            .filter(|os_class| project.iter().all(|class| class.name != os_class.name))
+            .collect(),
+        None => Vec::default(),
+    };
+    let output = options
+        .output
+        .clone()
+        .unwrap_or(options.project.join("doc"));
+    fs::create_dir_all(&output).map_err(|e| format!("{}: {}", output.display(), e))?;
+    let extension = options.format.extension();
+    for class in project.iter().chain(&os) {
+        let path = output.join(format!("{}.{}", class.name, extension));
+        fs::write(&path, render::class_page(class, options.format))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    let path = output.join(format!("index.{}", extension));
+    fs::write(&path, render::index_page(&project, &os, options.format))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if let Err(e) = Options::from_args(&args).and_then(|options| generate(&options)) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn from_args() {
+        let options = Options::from_args(&args(&["--html", "--no-os", "Pong"])).unwrap();
+        assert_eq!(
+            options,
+            Options {
+                project: PathBuf::from("Pong"),
+                output: None,
+                os: None,
+                format: Format::Html,
+            }
+        );
+        let options = Options::from_args(&args(&["Pong", "--output", "out"])).unwrap();
+        assert_eq!(options.os, Some(PathBuf::from(OS_FOLDER)));
+        assert_eq!(options.output, Some(PathBuf::from("out")));
+        assert!(Options::from_args(&args(&["--html"])).is_err());
+        assert!(Options::from_args(&args(&["a", "b"])).is_err());
+    }
+    #[test]
+    fn project_with_os() {
+        let root = env::temp_dir().join(format!("jackdoc-{}", process::id()));
+        let project = root.join("project");
+        fs::create_dir_all(&project).unwrap();
+        fs::write(
+            project.join("Main.jack"),
+            "/** Entry point */\nclass Main { function void main() { return; } }",
+        )
+        .unwrap();
+        let options = Options::from_args(&args(&[project.to_str().unwrap()])).unwrap();
+        generate(&options).unwrap();
+
+        let doc = project.join("doc");
+        let index = fs::read_to_string(doc.join("index.md")).unwrap();
+        assert!(index.contains("## Project\n\n- [Main](Main.md): Entry point\n"));
+        // The OS Main is shadowed by the project
+        assert!(index.contains("- [Array](Array.md)\n"));
+        assert_eq!(index.matches("[Main]").count(), 1);
+        let array = fs::read_to_string(doc.join("Array.md")).unwrap();
+        assert!(array.contains("### new\n\n```jack\nfunction Array new(int size)\n```\n\nConstructs a new Array of the given size.\n"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! Markdown or static HTML pages of the classes, one per class and an index
+use jack2vm::ast::{ClassVarKind, SubroutineKind};
+
+use crate::doc::ClassDoc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// Subroutines are grouped by kind, in the order of the source within a group
+const SECTIONS: [(SubroutineKind, &str); 3] = [
+    (SubroutineKind::Constructor, "Constructors"),
+    (SubroutineKind::Method, "Methods"),
+    (SubroutineKind::Function, "Functions"),
+];
+
+pub fn class_page(class: &ClassDoc, format: Format) -> String {
+    let mut page = Page::new(format, &format!("class {}", class.name));
+    page.paragraphs(&class.doc);
+    if !class.fields.is_empty() {
+        page.heading(2, "Fields");
+        for kind in [ClassVarKind::Field, ClassVarKind::Static] {
+            for field in class.fields.iter().filter(|field| field.kind == kind) {
+                page.item(&field.declaration, &field.doc);
+            }
+        }
+        page.end_list();
+    }
+    for (kind, title) in SECTIONS {
+        let subroutines = class
+            .subroutines
+            .iter()
+            .filter(|subroutine| subroutine.kind == kind)
+            .collect::<Vec<_>>();
+        if subroutines.is_empty() {
+            continue;
+        }
+        page.heading(2, title);
+        for subroutine in subroutines {
+            page.heading(3, &subroutine.name);
+            page.code(&subroutine.signature);
+            page.paragraphs(&subroutine.doc);
+        }
+    }
+    page.finish()
+}
+
+/// Classes of the project, then the OS classes the project does not define itself
+pub fn index_page(project: &[ClassDoc], os: &[ClassDoc], format: Format) -> String {
+    let mut page = Page::new(format, "Index");
+    for (title, classes) in [("Project", project), ("OS", os)] {
+        if classes.is_empty() {
+            continue;
+        }
+        page.heading(2, title);
+        for class in classes {
+            let summary = class.doc.split("\n\n").next().unwrap_or_default();
+            page.link_item(
+                &class.name,
+                &format!("{}.{}", class.name, format.extension()),
+                summary,
+            );
+        }
+        page.end_list();
+    }
+    page.finish()
+}
+
+struct Page {
+    format: Format,
+    text: String,
+    in_list: bool,
+}
+impl Page {
+    fn new(format: Format, title: &str) -> Self {
+        let mut page = Self {
+            format,
+            text: String::new(),
+            in_list: false,
+        };
+        if format == Format::Html {
+            page.text.push_str(&format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n",
+                escape(title)
+            ));
+        }
+        page.heading(1, title);
+        page
+    }
+    fn finish(mut self) -> String {
+        if self.format == Format::Html {
+            self.text.push_str("</body>\n</html>\n");
+        }
+        self.text
+    }
+
+    fn heading(&mut self, level: usize, text: &str) {
+        match self.format {
+            Format::Markdown => {
+                if !self.text.is_empty() {
+                    self.text.push('\n');
+                }
+                self.text
+                    .push_str(&format!("{} {}\n", "#".repeat(level), text));
+            }
+            Format::Html => self
+                .text
+                .push_str(&format!("<h{0}>{1}</h{0}>\n", level, escape(text))),
+        }
+    }
+    /// Doc text, paragraphs are separated by a blank line
+    fn paragraphs(&mut self, doc: &str) {
+        for paragraph in doc.split("\n\n").filter(|paragraph| !paragraph.is_empty()) {
+            match self.format {
+                Format::Markdown => self.text.push_str(&format!("\n{}\n", paragraph)),
+                Format::Html => self
+                    .text
+                    .push_str(&format!("<p>{}</p>\n", escape(paragraph))),
+            }
+        }
+    }
+    fn code(&mut self, code: &str) {
+        match self.format {
+            Format::Markdown => self.text.push_str(&format!("\n```jack\n{}\n```\n", code)),
+            Format::Html => self
+                .text
+                .push_str(&format!("<pre><code>{}</code></pre>\n", escape(code))),
+        }
+    }
+    fn start_list(&mut self) {
+        if self.in_list {
+            return;
+        }
+        self.in_list = true;
+        match self.format {
+            Format::Markdown => self.text.push('\n'),
+            Format::Html => self.text.push_str("<ul>\n"),
+        }
+    }
+    fn end_list(&mut self) {
+        if self.in_list && self.format == Format::Html {
+            self.text.push_str("</ul>\n");
+        }
+        self.in_list = false;
+    }
+    fn item(&mut self, code: &str, doc: &str) {
+        self.start_list();
+        let doc = doc.replace("\n\n", " ");
+        match (self.format, doc.is_empty()) {
+            (Format::Markdown, true) => self.text.push_str(&format!("- `{}`\n", code)),
+            (Format::Markdown, false) => self.text.push_str(&format!("- `{}`: {}\n", code, doc)),
+            (Format::Html, true) => self
+                .text
+                .push_str(&format!("<li><code>{}</code></li>\n", escape(code))),
+            (Format::Html, false) => self.text.push_str(&format!(
+                "<li><code>{}</code>: {}</li>\n",
+                escape(code),
+                escape(&doc)
+            )),
+        }
+    }
+    fn link_item(&mut self, text: &str, target: &str, summary: &str) {
+        self.start_list();
+        let link = match self.format {
+            Format::Markdown => format!("[{}]({})", text, target),
+            Format::Html => format!("<a href=\"{}\">{}</a>", escape(target), escape(text)),
+        };
+        match (self.format, summary.is_empty()) {
+            (Format::Markdown, true) => self.text.push_str(&format!("- {}\n", link)),
+            (Format::Markdown, false) => self.text.push_str(&format!("- {}: {}\n", link, summary)),
+            (Format::Html, true) => self.text.push_str(&format!("<li>{}</li>\n", link)),
+            (Format::Html, false) => {
+                self.text
+                    .push_str(&format!("<li>{}: {}</li>\n", link, escape(summary)))
+            }
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc::class_doc;
+
+    const POINT: &str = "/** A point, x < y. */
+class Point {
+    static int count;
+    /** Coordinates */
+    field int x, y;
+    /** Origin */
+    function Point origin() { return Point.new(0, 0); }
+    constructor Point new(int ax, int ay) { return this; }
+}";
+
+    #[test]
+    fn markdown() {
+        let point = class_doc(POINT).unwrap().unwrap();
+        assert_eq!(
+            class_page(&point, Format::Markdown),
+            "# class Point
+
+A point, x < y.
+
+## Fields
+
+- `field int x, y`: Coordinates
+- `static int count`
+
+## Constructors
+
+### new
+
+```jack
+constructor Point new(int ax, int ay)
+```
+
+## Functions
+
+### origin
+
+```jack
+function Point origin()
+```
+
+Origin
+"
+        );
+        let os = class_doc("class Math { }").unwrap().unwrap();
+        assert_eq!(
+            index_page(&[point], &[os], Format::Markdown),
+            "# Index
+
+## Project
+
+- [Point](Point.md): A point, x < y.
+
+## OS
+
+- [Math](Math.md)
+"
+        );
+    }
+    #[test]
+    fn html() {
+        let point = class_doc(POINT).unwrap().unwrap();
+        let page = class_page(&point, Format::Html);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>class Point</title>"));
+        assert!(page.contains("<p>A point, x &lt; y.</p>"));
+        assert!(page.contains("<li><code>field int x, y</code>: Coordinates</li>"));
+        assert!(page.contains(
+            "<h3>new</h3>\n<pre><code>constructor Point new(int ax, int ay)</code></pre>"
+        ));
+        assert!(page.ends_with("</body>\n</html>\n"));
+        assert!(index_page(&[point], &[], Format::Html)
+            .contains("<li><a href=\"Point.html\">Point</a>: A point, x &lt; y.</li>"));
+    }
+}
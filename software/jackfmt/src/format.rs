@@ -0,0 +1,336 @@
+//! Canonical printing of a Jack file from its tokens, comments included
+//!
+//! - 4 spaces per level of braces, a brace opens at the end of a line
+//! - One declaration or statement per line, a blank line between subroutines
+//! - Spaces around binary ops and after commas, none inside brackets or after unary ops
+//! - Blank lines of the source are kept, at most one in a row
+//! - Comments stay on their own line or after the code they followed
+use jack2vm::{Diagnostic, Parser, ReservedKeywords, Symbols, Token, TokenType, Tokenizer};
+
+const INDENT: &str = "    ";
+
+/// Only code that parses is formatted, the tokens alone do not tell where a statement ends
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    Parser::parse_ast(source)?;
+    let chars = source.chars().collect::<Vec<char>>();
+    let tokens = Tokenizer::generate_tokens_with_comments(source)
+        .map_err(|e| vec![Diagnostic::new(e, &chars)])?;
+    let mut printer = Printer {
+        chars: &chars,
+        output: String::new(),
+        depth: 0,
+        brackets: 0,
+        previous: None,
+        previous_code: None,
+        unary: false,
+    };
+    for token in tokens
+        .iter()
+        .take_while(|token| token._type != TokenType::EOF)
+    {
+        printer.token(token);
+    }
+    if !printer.output.is_empty() {
+        printer.output.push('\n');
+    }
+    Ok(printer.output)
+}
+
+/// Whitespace between two tokens
+#[derive(Debug, PartialEq)]
+enum Gap {
+    Nothing,
+    Space,
+    Line,
+    BlankLine,
+}
+
+struct Printer<'a> {
+    chars: &'a [char],
+    output: String,
+    depth: usize,    // Braces
+    brackets: usize, // Parentheses and square brackets, a line broken inside them is indented once more
+    previous: Option<&'a Token>,
+    previous_code: Option<&'a Token>, // Not a comment
+    unary: bool,                      // previous_code is a unary op
+}
+impl<'a> Printer<'a> {
+    fn text(&self, token: &Token) -> String {
+        self.chars[token.start..token.start + token.length]
+            .iter()
+            .collect()
+    }
+    fn indent(&self) -> String {
+        INDENT.repeat(self.depth + if self.brackets > 0 { 1 } else { 0 })
+    }
+
+    fn token(&mut self, token: &'a Token) {
+        if is_symbol(token, Symbols::RightBrace) {
+            self.depth = self.depth.saturating_sub(1);
+        }
+        if matches!(
+            token._type,
+            TokenType::Symbol(Symbols::RightParam | Symbols::RightBracket)
+        ) {
+            self.brackets = self.brackets.saturating_sub(1);
+        }
+        if let Some(previous) = self.previous {
+            match self.gap(previous, token) {
+                Gap::Nothing => {}
+                Gap::Space => self.output.push(' '),
+                Gap::Line => self.output.push('\n'),
+                Gap::BlankLine => self.output.push_str("\n\n"),
+            }
+            if self.output.ends_with('\n') {
+                self.output.push_str(&self.indent());
+            }
+        }
+
+        if token._type == TokenType::Comment {
+            self.comment(token);
+        } else {
+            self.output.push_str(&self.text(token));
+            self.unary = match token._type {
+                TokenType::Symbol(Symbols::Tilde) => true,
+                // A minus is binary after a term
+                TokenType::Symbol(Symbols::Minus) => !self.previous_code.is_some_and(ends_term),
+                _ => false,
+            };
+            self.previous_code = Some(token);
+        }
+        match token._type {
+            TokenType::Symbol(Symbols::LeftBrace) => self.depth += 1,
+            TokenType::Symbol(Symbols::LeftParam | Symbols::LeftBracket) => self.brackets += 1,
+            _ => {}
+        }
+        self.previous = Some(token);
+    }
+    /// Lines of a block comment starting with * are lined up under the first one
+    fn comment(&mut self, token: &Token) {
+        let text = self.text(token);
+        let indent = self.indent();
+        for (i, line) in text.lines().enumerate() {
+            if i > 0 {
+                self.output.push('\n');
+                let trimmed = line.trim_start();
+                if trimmed.starts_with('*') {
+                    self.output.push_str(&indent);
+                    self.output.push(' ');
+                    self.output.push_str(trimmed.trim_end());
+                    continue;
+                }
+            }
+            self.output.push_str(line.trim_end());
+        }
+    }
+
+    fn gap(&self, previous: &Token, next: &Token) -> Gap {
+        let newlines = self.chars[previous.start + previous.length..next.start]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        // A blank line of the source, where a line is broken anyway
+        let line = if newlines > 1
+            && !is_symbol(next, Symbols::RightBrace)
+            && !is_symbol(previous, Symbols::LeftBrace)
+        {
+            Gap::BlankLine
+        } else {
+            Gap::Line
+        };
+
+        if next._type == TokenType::Comment && newlines == 0 {
+            return Gap::Space;
+        }
+        // Between subroutines
+        if is_symbol(previous, Symbols::RightBrace)
+            && self.depth == 1
+            && !is_symbol(next, Symbols::RightBrace)
+        {
+            return Gap::BlankLine;
+        }
+        if previous._type == TokenType::Comment {
+            if newlines > 0 || self.text(previous).starts_with("//") {
+                return line;
+            }
+            return match next._type {
+                TokenType::Symbol(
+                    Symbols::SemiColon
+                    | Symbols::Comma
+                    | Symbols::RightParam
+                    | Symbols::RightBracket,
+                ) => Gap::Nothing,
+                _ => Gap::Space,
+            };
+        }
+        if next._type == TokenType::Comment {
+            return line;
+        }
+        match (&previous._type, &next._type) {
+            (
+                TokenType::Symbol(Symbols::RightBrace),
+                TokenType::Keyword(ReservedKeywords::Else),
+            ) => Gap::Space,
+            (
+                TokenType::Symbol(Symbols::SemiColon | Symbols::LeftBrace | Symbols::RightBrace),
+                _,
+            )
+            | (_, TokenType::Symbol(Symbols::RightBrace)) => line,
+            (
+                _,
+                TokenType::Symbol(
+                    Symbols::SemiColon
+                    | Symbols::Comma
+                    | Symbols::Dot
+                    | Symbols::RightParam
+                    | Symbols::RightBracket
+                    | Symbols::LeftBracket,
+                ),
+            )
+            | (TokenType::Symbol(Symbols::LeftParam | Symbols::LeftBracket | Symbols::Dot), _)
+            | (TokenType::Identifier, TokenType::Symbol(Symbols::LeftParam)) => Gap::Nothing,
+            _ if self.unary => Gap::Nothing,
+            _ => Gap::Space,
+        }
+    }
+}
+
+fn is_symbol(token: &Token, symbol: Symbols) -> bool {
+    token._type == TokenType::Symbol(symbol)
+}
+/// Tokens that can end a term, a minus after them is binary
+fn ends_term(token: &Token) -> bool {
+    matches!(
+        token._type,
+        TokenType::Identifier
+            | TokenType::Integer(_)
+            | TokenType::String
+            | TokenType::Keyword(
+                ReservedKeywords::True
+                    | ReservedKeywords::False
+                    | ReservedKeywords::Null
+                    | ReservedKeywords::This
+            )
+            | TokenType::Symbol(Symbols::RightParam | Symbols::RightBracket)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path};
+
+    #[test]
+    fn canonical() {
+        let source = "// Main
+class Main{field int x,y;
+  static boolean b;
+
+
+function void main(){var int i;let i=-1+(~x*2)-3;
+if(i<0){do Output.printInt(i);}else{let a[i]=Math.max(i,-i);}
+  while (i) { let i = i - 1; }   // count down
+  return;}
+    /** Doc
+        * of f
+    */
+method int f(){return -x;}}";
+        let expected = "// Main
+class Main {
+    field int x, y;
+    static boolean b;
+
+    function void main() {
+        var int i;
+        let i = -1 + (~x * 2) - 3;
+        if (i < 0) {
+            do Output.printInt(i);
+        } else {
+            let a[i] = Math.max(i, -i);
+        }
+        while (i) {
+            let i = i - 1;
+        } // count down
+        return;
+    }
+
+    /** Doc
+     * of f
+     */
+    method int f() {
+        return -x;
+    }
+}
+";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+    #[test]
+    fn comments_inside_code() {
+        let source = "class Main {
+    function void main() {
+        do f(1, // first
+        2 /* second */);
+        return;
+    }
+}
+";
+        let expected = "class Main {
+    function void main() {
+        do f(1, // first
+            2 /* second */);
+        return;
+    }
+}
+";
+        assert_eq!(format(source).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+    #[test]
+    fn not_parsed() {
+        assert!(format("class Main { field int x }").is_err());
+        assert_eq!(format("").unwrap(), "");
+    }
+
+    fn jack_files(folder: &Path, files: &mut Vec<String>) {
+        for entry in fs::read_dir(folder).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                jack_files(&path, files);
+            } else if path.extension().is_some_and(|e| e == "jack") {
+                files.push(fs::read_to_string(path).unwrap());
+            }
+        }
+    }
+    fn code(source: &str) -> Vec<String> {
+        let chars = source.chars().collect::<Vec<char>>();
+        Tokenizer::generate_tokens_with_comments(source)
+            .unwrap()
+            .iter()
+            .map(|token| {
+                let text = chars[token.start..token.start + token.length]
+                    .iter()
+                    .collect::<String>();
+                // Block comments are re-indented
+                text.split_whitespace().collect::<Vec<&str>>().join(" ")
+            })
+            .collect()
+    }
+    /// Every Jack file of the repo keeps its tokens and comments, and formats to itself again
+    #[test]
+    fn repo_files() {
+        let mut files = Vec::default();
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        jack_files(&root.join("jack"), &mut files);
+        jack_files(&root.join("../projects"), &mut files);
+        assert!(files.len() > 20);
+        for source in files {
+            let formatted = match format(&source) {
+                Ok(formatted) => formatted,
+                Err(_) => continue,
+            };
+            assert_eq!(code(&source), code(&formatted));
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Formats Jack files in place, see format.rs for the style
+//!
+//! A folder stands for the .jack files in it, without any path stdin is formatted to stdout
+//! With --check nothing is written, the files that are not formatted are listed and the exit code is 1
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process,
+};
+
+mod format;
+
+const USAGE: &str = "Usage: jackfmt [--check] [path...]";
+
+type ProgResult = Result<(), String>;
+
+/// .jack files of the paths, a folder for the files directly in it
+fn jack_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::default();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut folder_files = Vec::default();
+        for entry in fs::read_dir(path).map_err(|e| format!("{}: {}", path.display(), e))? {
+            let entry_path = entry.map_err(|e| e.to_string())?.path();
+            if entry_path.is_file() && entry_path.extension().is_some_and(|e| e == "jack") {
+                folder_files.push(entry_path);
+            }
+        }
+        folder_files.sort();
+        files.extend(folder_files);
+    }
+    Ok(files)
+}
+
+/// Formatted source, or the parse errors rendered against it
+fn format_source(file_name: &str, source: &str) -> Result<String, String> {
+    format::format(source).map_err(|diagnostics| {
+        diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                diagnostic.file_name = file_name.to_string();
+                diagnostic.render(source)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    })
+}
+
+/// Whether every file was formatted already
+fn run(paths: &[PathBuf], check: bool) -> Result<bool, String> {
+    if paths.is_empty() {
+        let mut source = String::new();
+        io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|e| e.to_string())?;
+        let formatted = format_source("<stdin>", &source)?;
+        if !check {
+            print!("{}", formatted);
+        }
+        return Ok(formatted == source);
+    }
+    let mut formatted_already = true;
+    let mut errors = Vec::default();
+    for path in jack_files(paths)? {
+        let source = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let formatted = match format_source(&file_name, &source) {
+            Ok(formatted) => formatted,
+            // The other files are still formatted
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        formatted_already = false;
+        if check {
+            println!("{}", path.display());
+        } else {
+            write(&path, &formatted)?;
+        }
+    }
+    if errors.is_empty() {
+        Ok(formatted_already)
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+fn write(path: &Path, formatted: &str) -> ProgResult {
+    fs::write(path, formatted).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn main() {
+    let mut check = false;
+    let mut paths = Vec::default();
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    match run(&paths, check) {
+        Ok(true) => {}
+        Ok(false) if !check => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
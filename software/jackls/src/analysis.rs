@@ -0,0 +1,705 @@
+//! What the server knows of a project, the .jack files in the folder of a document
+//!
+//! Everything is recomputed from the sources on each request, Jack projects are small
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use jack2vm::{
+    ast::{
+        Class, Expression, Identifier, Span, Statement, SubroutineCall, SubroutineDec,
+        SubroutineKind, Term, Type,
+    },
+    check_project, CodeGen, Diagnostic, Parser, ProjectSymbols, Signature, VariableKind,
+};
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub chars: Vec<char>,
+    pub class: Option<Class>, // Last class that parsed, older than the source when there are parse errors
+    pub parse_errors: Vec<Diagnostic>,
+}
+impl SourceFile {
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Error of any stage of the compiler, at a span of a file
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub span: Span,
+    pub msg: String,
+}
+
+/// What a name in the source refers to
+#[derive(Debug, PartialEq)]
+pub enum Symbol<'a> {
+    Class(&'a str),
+    // Declared by the subroutine or else by the class
+    Variable {
+        name: &'a str,
+        subroutine: Option<&'a SubroutineDec>,
+    },
+    Subroutine {
+        class_name: String,
+        name: &'a str,
+    },
+}
+
+/// Subroutine offered after ClassName. or variable.
+pub struct Member {
+    pub name: String,
+    pub kind: SubroutineKind,
+    pub detail: String, // Signature
+}
+
+pub struct Project {
+    pub files: Vec<SourceFile>,
+}
+impl Project {
+    /// Open documents are used instead of what is on disk
+    /// parsed keeps the last class that parsed of every file, for when the source is being edited
+    pub fn load(
+        folder: &Path,
+        documents: &HashMap<PathBuf, String>,
+        parsed: &mut HashMap<PathBuf, Class>,
+    ) -> Self {
+        let mut paths = fs::read_dir(folder)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "jack"))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .unwrap_or_default();
+        for path in documents.keys() {
+            if path.parent() == Some(folder) && !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        paths.sort();
+
+        let mut files = Vec::default();
+        for path in paths {
+            let source = match documents.get(&path) {
+                Some(text) => text.clone(),
+                None => match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(_) => continue,
+                },
+            };
+            let parse_errors = match Parser::parse_ast(&source) {
+                Ok(Some(class)) => {
+                    parsed.insert(path.clone(), class);
+                    Vec::default()
+                }
+                Ok(None) => {
+                    parsed.remove(&path);
+                    Vec::default()
+                }
+                Err(diagnostics) => diagnostics,
+            };
+            files.push(SourceFile {
+                class: parsed.get(&path).cloned(),
+                chars: source.chars().collect(),
+                path,
+                parse_errors,
+            });
+        }
+        Self { files }
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&SourceFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+    fn class(&self, name: &str) -> Option<(&SourceFile, &Class)> {
+        self.files.iter().find_map(|file| match &file.class {
+            Some(class) if class.name.name == name => Some((file, class)),
+            _ => None,
+        })
+    }
+
+    /// Problems of every file, like compile_project finds them
+    /// The checks across files need every file to parse
+    pub fn problems(&self) -> Vec<(&SourceFile, Vec<Problem>)> {
+        let mut problems = self
+            .files
+            .iter()
+            .map(|file| {
+                let mut problems = file
+                    .parse_errors
+                    .iter()
+                    .map(|diagnostic| Problem {
+                        span: diagnostic.span(),
+                        msg: diagnostic.error.to_string(),
+                    })
+                    .collect::<Vec<Problem>>();
+                if let (true, Some(class)) = (file.parse_errors.is_empty(), &file.class) {
+                    if let Err(e) = CodeGen::default().class(class) {
+                        problems.push(Problem {
+                            span: e.span(),
+                            msg: e.to_string(),
+                        });
+                    }
+                }
+                (file, problems)
+            })
+            .collect::<Vec<(&SourceFile, Vec<Problem>)>>();
+
+        if self.files.iter().all(|file| file.parse_errors.is_empty()) {
+            let classes = self
+                .files
+                .iter()
+                .filter_map(|file| Some((file.file_name(), file.class.clone()?)))
+                .collect::<Vec<(String, Class)>>();
+            if let Err(errors) = check_project(&classes) {
+                for error in errors {
+                    if let Some((_, problems)) = problems
+                        .iter_mut()
+                        .find(|(file, _)| file.file_name() == error.file_name)
+                    {
+                        problems.push(Problem {
+                            span: error.span(),
+                            msg: error.error.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    /// File and span of the name that declares symbol
+    pub fn definition<'a>(
+        &'a self,
+        file: &'a SourceFile,
+        class: &'a Class,
+        symbol: &Symbol,
+    ) -> Option<(&'a Path, Span)> {
+        match symbol {
+            Symbol::Class(name) => {
+                let (file, class) = self.class(name)?;
+                Some((&file.path, class.name.span))
+            }
+            Symbol::Variable { name, subroutine } => {
+                let (_, identifier) = variable_declaration(class, *subroutine, name)?;
+                Some((&file.path, identifier.span))
+            }
+            Symbol::Subroutine { class_name, name } => {
+                let (file, class) = self.class(class_name)?;
+                let subroutine = class
+                    .subroutines
+                    .iter()
+                    .find(|subroutine| subroutine.name.name == *name)?;
+                Some((&file.path, subroutine.name.span))
+            }
+        }
+    }
+
+    /// Markdown describing symbol
+    pub fn hover(&self, class: &Class, symbol: &Symbol) -> Option<String> {
+        let text = match symbol {
+            Symbol::Class(name) => format!("class {}", name),
+            Symbol::Variable { name, subroutine } => {
+                return variable_hover(class, *subroutine, name)
+            }
+            Symbol::Subroutine { class_name, name } => match self.class(class_name) {
+                Some((_, class)) => {
+                    let subroutine = class
+                        .subroutines
+                        .iter()
+                        .find(|subroutine| subroutine.name.name == *name)?;
+                    subroutine_detail(class_name, subroutine)
+                }
+                None => {
+                    let signature = ProjectSymbols::os().signature(class_name, name)?.clone();
+                    signature_detail(class_name, name, &signature)
+                }
+            },
+        };
+        Some(format!("```jack\n{}\n```", text))
+    }
+
+    /// Subroutines that can follow the receiver before offset, as in Output. or point.
+    /// Methods for a variable, constructors and functions for a class name
+    pub fn completions(&self, file: &SourceFile, offset: usize) -> Vec<Member> {
+        let chars = &file.chars[..offset.min(file.chars.len())];
+        let is_identifier = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+        let prefix_start =
+            chars.len() - chars.iter().rev().take_while(|c| is_identifier(c)).count();
+        if prefix_start == 0 || chars[prefix_start - 1] != '.' {
+            return Vec::default();
+        }
+        let receiver_chars = &chars[..prefix_start - 1];
+        let receiver_start = receiver_chars.len()
+            - receiver_chars
+                .iter()
+                .rev()
+                .take_while(|c| is_identifier(c))
+                .count();
+        let receiver = receiver_chars[receiver_start..].iter().collect::<String>();
+        if receiver.is_empty() {
+            return Vec::default();
+        }
+
+        let declaration = file.class.as_ref().and_then(|class| {
+            let subroutine = class.subroutines.iter().find(|subroutine| {
+                subroutine.span.start <= offset && offset <= subroutine.span.end()
+            });
+            variable_declaration(class, subroutine, &receiver)
+        });
+        let (class_name, methods) = match declaration {
+            Some((Type::Class(class_name), _)) => (class_name.clone(), true),
+            Some(_) => return Vec::default(),
+            None => (receiver, false),
+        };
+        let wanted = |kind: SubroutineKind| (kind == SubroutineKind::Method) == methods;
+
+        let mut members: Vec<Member> = match self.class(&class_name) {
+            Some((_, class)) => class
+                .subroutines
+                .iter()
+                .filter(|subroutine| wanted(subroutine.kind))
+                .map(|subroutine| Member {
+                    name: subroutine.name.name.clone(),
+                    kind: subroutine.kind,
+                    detail: subroutine_detail(&class_name, subroutine),
+                })
+                .collect(),
+            None => ProjectSymbols::os()
+                .subroutines(&class_name)
+                .map(|subroutines| {
+                    subroutines
+                        .iter()
+                        .filter(|(_, signature)| wanted(signature.kind))
+                        .map(|(name, signature)| Member {
+                            name: name.clone(),
+                            kind: signature.kind,
+                            detail: signature_detail(&class_name, name, signature),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+        members
+    }
+}
+
+/// Type and name that declare a variable, seen from subroutine
+pub fn variable_declaration<'a>(
+    class: &'a Class,
+    subroutine: Option<&'a SubroutineDec>,
+    name: &str,
+) -> Option<(&'a Type, &'a Identifier)> {
+    if let Some(subroutine) = subroutine {
+        if let Some(parameter) = subroutine
+            .parameters
+            .iter()
+            .find(|parameter| parameter.name.name == name)
+        {
+            return Some((&parameter._type, &parameter.name));
+        }
+        for var_dec in &subroutine.var_decs {
+            if let Some(identifier) = var_dec.names.iter().find(|n| n.name == name) {
+                return Some((&var_dec._type, identifier));
+            }
+        }
+    }
+    class.var_decs.iter().find_map(|var_dec| {
+        let identifier = var_dec.names.iter().find(|n| n.name == name)?;
+        Some((&var_dec._type, identifier))
+    })
+}
+
+/// Kind, type and index of a variable, from the symbol tables of CodeGen
+fn variable_hover(class: &Class, subroutine: Option<&SubroutineDec>, name: &str) -> Option<String> {
+    // Declared twice or shadowing is reported as a diagnostic, the first declaration wins here
+    let mut code_gen = CodeGen::default();
+    let is_declared =
+        |code_gen: &CodeGen, name: &String| code_gen.get_variable_metadata(name).is_ok();
+    for var_dec in &class.var_decs {
+        for identifier in &var_dec.names {
+            if is_declared(&code_gen, &identifier.name) {
+                continue;
+            }
+            let _ = code_gen.insert_class_variable(
+                identifier.name.clone(),
+                &var_dec.kind,
+                &var_dec._type,
+            );
+        }
+    }
+    if let Some(subroutine) = subroutine {
+        code_gen.reset_subroutine_table(class.name.name.clone());
+        for parameter in &subroutine.parameters {
+            if is_declared(&code_gen, &parameter.name.name) {
+                continue;
+            }
+            let _ = code_gen.insert_subroutine_variable(
+                parameter.name.name.clone(),
+                VariableKind::Argument,
+                &parameter._type,
+            );
+        }
+        for var_dec in &subroutine.var_decs {
+            for identifier in &var_dec.names {
+                if is_declared(&code_gen, &identifier.name) {
+                    continue;
+                }
+                let _ = code_gen.insert_subroutine_variable(
+                    identifier.name.clone(),
+                    VariableKind::Local,
+                    &var_dec._type,
+                );
+            }
+        }
+    }
+    let variable = code_gen.get_variable_metadata(&name.to_string()).ok()?;
+    let keyword = match variable.kind {
+        VariableKind::Field => "field",
+        VariableKind::Static => "static",
+        VariableKind::Argument => "argument",
+        VariableKind::Local => "var",
+    };
+    Some(format!(
+        "```jack\n{} {} {}\n```\n{} {}",
+        keyword,
+        variable._type.name(),
+        name,
+        variable.kind.to_vm_segment(),
+        variable.number
+    ))
+}
+
+fn subroutine_detail(class_name: &str, subroutine: &SubroutineDec) -> String {
+    format!(
+        "{} {} {}.{}({})",
+        subroutine.kind.keyword(),
+        subroutine
+            .return_type
+            .as_ref()
+            .map_or("void", |_type| _type.name()),
+        class_name,
+        subroutine.name.name,
+        subroutine
+            .parameters
+            .iter()
+            .map(|parameter| format!("{} {}", parameter._type.name(), parameter.name.name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+/// The OS signatures only know the types of the parameters
+fn signature_detail(class_name: &str, name: &str, signature: &Signature) -> String {
+    format!(
+        "{} {} {}.{}({})",
+        signature.kind.keyword(),
+        signature
+            .return_type
+            .as_ref()
+            .map_or("void", |_type| _type.name()),
+        class_name,
+        name,
+        signature
+            .parameters
+            .iter()
+            .map(|_type| _type.name())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    )
+}
+
+/// Symbol whose name is at offset, a cursor right after a name is still on it
+pub fn symbol_at(class: &Class, offset: usize) -> Option<Symbol<'_>> {
+    Finder {
+        class,
+        subroutine: None,
+        offset,
+    }
+    .class()
+}
+
+/// Pass over the AST, looking for the name at offset
+struct Finder<'a> {
+    class: &'a Class,
+    subroutine: Option<&'a SubroutineDec>,
+    offset: usize,
+}
+impl<'a> Finder<'a> {
+    fn hit(&self, span: Span) -> bool {
+        span.start <= self.offset && self.offset <= span.end()
+    }
+    fn _type(&self, _type: &'a Type, span: Span) -> Option<Symbol<'a>> {
+        match _type {
+            Type::Class(name) if self.hit(span) => Some(Symbol::Class(name)),
+            _ => None,
+        }
+    }
+    fn variable(&self, name: &'a Identifier) -> Option<Symbol<'a>> {
+        self.hit(name.span).then_some(Symbol::Variable {
+            name: &name.name,
+            subroutine: self.subroutine,
+        })
+    }
+
+    fn class(&mut self) -> Option<Symbol<'a>> {
+        let class = self.class;
+        if self.hit(class.name.span) {
+            return Some(Symbol::Class(&class.name.name));
+        }
+        for var_dec in &class.var_decs {
+            if let Some(symbol) = self._type(&var_dec._type, var_dec.type_span) {
+                return Some(symbol);
+            }
+            if let Some(symbol) = var_dec.names.iter().find_map(|name| self.variable(name)) {
+                return Some(symbol);
+            }
+        }
+        let subroutine = class
+            .subroutines
+            .iter()
+            .find(|subroutine| self.hit(subroutine.span))?;
+        self.subroutine = Some(subroutine);
+        if let Some(return_type) = &subroutine.return_type {
+            if let Some(symbol) = self._type(return_type, subroutine.return_type_span) {
+                return Some(symbol);
+            }
+        }
+        if self.hit(subroutine.name.span) {
+            return Some(Symbol::Subroutine {
+                class_name: class.name.name.clone(),
+                name: &subroutine.name.name,
+            });
+        }
+        for parameter in &subroutine.parameters {
+            if let Some(symbol) = self
+                ._type(&parameter._type, parameter.type_span)
+                .or_else(|| self.variable(&parameter.name))
+            {
+                return Some(symbol);
+            }
+        }
+        for var_dec in &subroutine.var_decs {
+            if let Some(symbol) = self
+                ._type(&var_dec._type, var_dec.type_span)
+                .or_else(|| var_dec.names.iter().find_map(|name| self.variable(name)))
+            {
+                return Some(symbol);
+            }
+        }
+        self.statements(&subroutine.statements)
+    }
+    fn statements(&self, statements: &'a [Statement]) -> Option<Symbol<'a>> {
+        statements
+            .iter()
+            .find_map(|statement| self.statement(statement))
+    }
+    fn statement(&self, statement: &'a Statement) -> Option<Symbol<'a>> {
+        if !self.hit(statement.span()) {
+            return None;
+        }
+        match statement {
+            Statement::Let {
+                name, index, value, ..
+            } => self
+                .variable(name)
+                .or_else(|| index.as_ref().and_then(|index| self.expression(index)))
+                .or_else(|| self.expression(value)),
+            Statement::If {
+                condition,
+                statements,
+                else_statements,
+                ..
+            } => self
+                .expression(condition)
+                .or_else(|| self.statements(statements))
+                .or_else(|| {
+                    else_statements
+                        .as_deref()
+                        .and_then(|statements| self.statements(statements))
+                }),
+            Statement::While {
+                condition,
+                statements,
+                ..
+            } => self
+                .expression(condition)
+                .or_else(|| self.statements(statements)),
+            Statement::Do { call, .. } => self.call(call),
+            Statement::Return { value, .. } => {
+                value.as_ref().and_then(|value| self.expression(value))
+            }
+        }
+    }
+    fn expression(&self, expression: &'a Expression) -> Option<Symbol<'a>> {
+        self.term(&expression.term)
+            .or_else(|| expression.ops.iter().find_map(|(_, term)| self.term(term)))
+    }
+    fn term(&self, term: &'a Term) -> Option<Symbol<'a>> {
+        match term {
+            Term::Variable(name) => self.variable(name),
+            Term::Index { name, index, .. } => {
+                self.variable(name).or_else(|| self.expression(index))
+            }
+            Term::Call(call) => self.call(call),
+            Term::Parenthesized(expression, _) => self.expression(expression),
+            Term::Unary { term, .. } => self.term(term),
+            Term::Integer(..) | Term::String(..) | Term::Keyword(..) => None,
+        }
+    }
+    fn call(&self, call: &'a SubroutineCall) -> Option<Symbol<'a>> {
+        if self.hit(call.name.span) {
+            let class_name = match &call.receiver {
+                None => self.class.name.name.clone(),
+                Some(receiver) => {
+                    match variable_declaration(self.class, self.subroutine, &receiver.name) {
+                        Some((Type::Class(class_name), _)) => class_name.clone(),
+                        Some(_) => return None,
+                        None => receiver.name.clone(),
+                    }
+                }
+            };
+            return Some(Symbol::Subroutine {
+                class_name,
+                name: &call.name.name,
+            });
+        }
+        if let Some(receiver) = &call.receiver {
+            if self.hit(receiver.span) {
+                return match variable_declaration(self.class, self.subroutine, &receiver.name) {
+                    Some(_) => self.variable(receiver),
+                    None => Some(Symbol::Class(&receiver.name)),
+                };
+            }
+        }
+        call.arguments
+            .iter()
+            .find_map(|argument| self.expression(argument))
+    }
+}
+
+/// LSP positions count lines from 0 and characters in UTF-16 code units
+pub fn position(chars: &[char], offset: usize) -> (usize, usize) {
+    let offset = offset.min(chars.len());
+    let line = chars[..offset].iter().filter(|c| **c == '\n').count();
+    let line_start = chars[..offset]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1);
+    let character = chars[line_start..offset]
+        .iter()
+        .map(|c| c.len_utf16())
+        .sum();
+    (line, character)
+}
+/// Offset in chars of an LSP position, clamped to its line
+pub fn offset(chars: &[char], line: usize, character: usize) -> usize {
+    let mut offset = 0;
+    for _ in 0..line {
+        match chars[offset..].iter().position(|c| *c == '\n') {
+            Some(i) => offset += i + 1,
+            None => return chars.len(),
+        }
+    }
+    let mut units = 0;
+    while offset < chars.len() && chars[offset] != '\n' && units < character {
+        units += chars[offset].len_utf16();
+        offset += 1;
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let chars = "ab\n\u{1f600}c\n".chars().collect::<Vec<char>>();
+        assert_eq!(position(&chars, 1), (0, 1));
+        assert_eq!(position(&chars, 3), (1, 0));
+        // The emoji is 2 UTF-16 units
+        assert_eq!(position(&chars, 4), (1, 2));
+        assert_eq!(offset(&chars, 1, 2), 4);
+        assert_eq!(offset(&chars, 0, 10), 2);
+        assert_eq!(offset(&chars, 5, 0), chars.len());
+    }
+    #[test]
+    fn symbols() {
+        let source = "class Main {
+    field Point p;
+    method int f(int a) {
+        var int b;
+        let b = a + p.getX();
+        do Main.g(b);
+        return b;
+    }
+}";
+        let class = Parser::parse_ast(source).unwrap().unwrap();
+        let at = |needle: &str, skip: usize| {
+            let offset = source.match_indices(needle).nth(skip).unwrap().0;
+            symbol_at(&class, source[..offset].chars().count())
+        };
+        assert_eq!(at("Point", 0), Some(Symbol::Class("Point")));
+        assert!(matches!(
+            at("a +", 0),
+            Some(Symbol::Variable {
+                name: "a",
+                subroutine: Some(_)
+            })
+        ));
+        assert!(matches!(
+            at("p.", 0),
+            Some(Symbol::Variable { name: "p", .. })
+        ));
+        assert_eq!(
+            at("getX", 0),
+            Some(Symbol::Subroutine {
+                class_name: String::from("Point"),
+                name: "getX"
+            })
+        );
+        assert_eq!(at("Main.", 0), Some(Symbol::Class("Main")));
+        assert_eq!(
+            at("g(", 0),
+            Some(Symbol::Subroutine {
+                class_name: String::from("Main"),
+                name: "g"
+            })
+        );
+        assert_eq!(at("return", 0), None);
+
+        let hover = |name| variable_hover(&class, Some(&class.subroutines[0]), name);
+        assert_eq!(
+            hover("a").unwrap(),
+            "```jack\nargument int a\n```\nargument 1"
+        );
+        assert_eq!(hover("b").unwrap(), "```jack\nvar int b\n```\nlocal 0");
+        assert_eq!(hover("p").unwrap(), "```jack\nfield Point p\n```\nthis 0");
+        assert_eq!(hover("c"), None);
+    }
+    #[test]
+    fn hover_first_declaration() {
+        let source = "class Main {
+    field int x;
+    static char x;
+    function void f(int a, boolean a) {
+        var Array b, b;
+        var int x;
+        return;
+    }
+}";
+        let class = Parser::parse_ast(source).unwrap().unwrap();
+        let hover = |name| variable_hover(&class, Some(&class.subroutines[0]), name).unwrap();
+        assert_eq!(hover("x"), "```jack\nfield int x\n```\nthis 0");
+        assert_eq!(hover("a"), "```jack\nargument int a\n```\nargument 1");
+        assert_eq!(hover("b"), "```jack\nvar Array b\n```\nlocal 0");
+    }
+}
@@ -0,0 +1,333 @@
+//! Just enough JSON for the Language Server Protocol
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // Keys in the order they were written
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut parser = JsonParser {
+            chars: &chars,
+            current: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.current < chars.len() {
+            return Err(format!(
+                "Unexpected {:?} after the JSON value",
+                chars[parser.current]
+            ));
+        }
+        Ok(value)
+    }
+
+    pub fn object(entries: Vec<(&str, Json)>) -> Self {
+        Self::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+    /// Value of key in an object, Null when there is none, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Self::Object(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Self::Number(x) if *x >= 0.0 && x.fract() == 0.0 => Some(*x as usize),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+    pub fn is_null(&self) -> bool {
+        *self == Self::Null
+    }
+}
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+impl From<usize> for Json {
+    fn from(x: usize) -> Self {
+        Self::Number(x as f64)
+    }
+}
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Self::Array(values)
+    }
+}
+
+/// Compact, without any whitespace
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Number(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Self::Number(x) => write!(f, "{}", x),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonParser<'a> {
+    chars: &'a [char],
+    current: usize,
+}
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.current < self.chars.len() && self.chars[self.current].is_whitespace() {
+            self.current += 1;
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+    fn next(&mut self) -> Result<char, String> {
+        let c = self
+            .peek()
+            .ok_or_else(|| String::from("Unexpected end of the JSON"))?;
+        self.current += 1;
+        Ok(c)
+    }
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format!("Expected {:?}, got {:?}", expected, c)),
+        }
+    }
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        for expected in literal.chars() {
+            if self.next()? != expected {
+                return Err(format!("Expected {}", literal));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.current += 1;
+                let mut values = Vec::default();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.current += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        ']' => return Ok(Json::Array(values)),
+                        c => return Err(format!("Expected ',' or ']', got {:?}", c)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.current += 1;
+                let mut entries = Vec::default();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.current += 1;
+                    return Ok(Json::Object(entries));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        '}' => return Ok(Json::Object(entries)),
+                        c => return Err(format!("Expected ',' or '}}', got {:?}", c)),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let start = self.current;
+                while self
+                    .peek()
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c))
+                {
+                    self.current += 1;
+                }
+                let number = self.chars[start..self.current].iter().collect::<String>();
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("Invalid number {}", number))
+            }
+            Some(c) => Err(format!("Unexpected {:?}", c)),
+            None => Err(String::from("Unexpected end of the JSON")),
+        }
+    }
+    fn string(&mut self) -> Result<String, String> {
+        if self.next()? != '"' {
+            return Err(String::from("Expected a string"));
+        }
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let mut code = self.hex4()?;
+                        // Characters outside the BMP are two escaped surrogates
+                        if (0xd800..0xdc00).contains(&code) && self.peek() == Some('\\') {
+                            self.current += 1;
+                            self.expect('u')?;
+                            let low = self.hex4()?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                        }
+                        s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()?
+                .to_digit(16)
+                .ok_or_else(|| String::from("Invalid \\u escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let json = Json::parse(
+            r#" {"id": 1, "params": {"text": "a\"b\né😀", "list": [true, null, -2.5e1]}} "#,
+        )
+        .unwrap();
+        assert_eq!(json.get("id").as_usize(), Some(1));
+        assert_eq!(
+            json.get("params").get("text").as_str(),
+            Some("a\"b\n\u{e9}\u{1f600}")
+        );
+        assert_eq!(
+            json.get("params").get("list"),
+            &Json::Array(vec![Json::Bool(true), Json::Null, Json::Number(-25.0)])
+        );
+        assert!(json.get("missing").get("deeper").is_null());
+
+        assert!(Json::parse("{\"a\" 1}").is_err());
+        assert!(Json::parse("[1, 2").is_err());
+        assert!(Json::parse("1 2").is_err());
+    }
+    #[test]
+    fn to_string() {
+        let json = Json::object(vec![
+            ("id", Json::from(3)),
+            ("text", Json::from("a\"b\\c\n")),
+            ("list", Json::from(vec![Json::Null, Json::from(false)])),
+            ("half", Json::Number(0.5)),
+        ]);
+        let text = json.to_string();
+        assert_eq!(
+            text,
+            r#"{"id":3,"text":"a\"b\\c\n","list":[null,false],"half":0.5}"#
+        );
+        assert_eq!(Json::parse(&text).unwrap(), json);
+    }
+}
@@ -0,0 +1,22 @@
+//! Jack language server, speaks the Language Server Protocol over stdio
+//!
+//! Diagnostics on open and save, go to definition, hover, completion of ClassName. and
+//! document symbols, from the Tokenizer, Parser and CodeGen of jack2vm
+use std::{io, process};
+
+mod analysis;
+mod json;
+mod rpc;
+mod server;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match server::serve(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
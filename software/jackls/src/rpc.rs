@@ -0,0 +1,92 @@
+//! Base protocol of the Language Server Protocol, JSON-RPC messages after a Content-Length header
+use std::io::{BufRead, Write};
+
+use crate::json::Json;
+
+/// Next message, None at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Json>, String> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        // An empty line ends the header
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("Content-Length {}: {}", value.trim(), e))?,
+            );
+        }
+    }
+    let mut body = vec![0; content_length.unwrap()];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let body = String::from_utf8(body).map_err(|e| e.to_string())?;
+    Json::parse(&body).map(Some)
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> Result<(), String> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| writer.flush())
+        .map_err(|e| e.to_string())
+}
+
+pub fn response(id: &Json, result: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id.clone()),
+        ("result", result),
+    ])
+}
+pub fn error_response(id: &Json, code: i32, msg: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id.clone()),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::Number(code as f64)),
+                ("message", Json::from(msg)),
+            ]),
+        ),
+    ])
+}
+pub fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from(method)),
+        ("params", params),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn framing() {
+        let mut bytes = Vec::default();
+        let message = notification("exit", Json::Null);
+        write_message(&mut bytes, &message).unwrap();
+        write_message(&mut bytes, &response(&Json::from(1), Json::from("\u{e9}"))).unwrap();
+        assert!(bytes.starts_with(b"Content-Length: 47\r\n\r\n{\"jsonrpc\""));
+
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        // The length is in bytes, not chars
+        let second = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(second.get("result").as_str(), Some("\u{e9}"));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+}
@@ -0,0 +1,647 @@
+//! Requests and notifications of the Language Server Protocol that the server handles
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use jack2vm::ast::{Class, Span, SubroutineKind};
+
+use crate::{
+    analysis::{self, Project, SourceFile},
+    json::Json,
+    rpc::{error_response, notification, read_message, response, write_message},
+};
+
+const METHOD_NOT_FOUND: i32 = -32601;
+
+// LSP enums
+const SEVERITY_ERROR: usize = 1;
+const SYNC_FULL: usize = 1;
+const SYMBOL_CLASS: usize = 5;
+const SYMBOL_METHOD: usize = 6;
+const SYMBOL_FIELD: usize = 8;
+const SYMBOL_CONSTRUCTOR: usize = 9;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+const COMPLETION_METHOD: usize = 2;
+const COMPLETION_FUNCTION: usize = 3;
+const COMPLETION_CONSTRUCTOR: usize = 4;
+
+/// Reads messages until exit or the end of the input, returns the exit code
+pub fn serve(reader: &mut impl BufRead, writer: &mut impl Write) -> Result<i32, String> {
+    let mut server = Server::default();
+    while let Some(message) = read_message(reader)? {
+        for outgoing in server.handle(&message) {
+            write_message(writer, &outgoing)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+    Ok(1)
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<PathBuf, String>, // Text of the open documents
+    parsed: HashMap<PathBuf, Class>,     // Last class that parsed of every file
+    shutdown: bool,
+    exit_code: Option<i32>, // Set by exit, 0 after a shutdown
+}
+impl Server {
+    /// Responses and notifications to send back
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or_default();
+        let params = message.get("params");
+        let id = message.get("id");
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                None
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                if let (Some(path), Some(text)) = (
+                    document.get("uri").as_str().and_then(uri_to_path),
+                    document.get("text").as_str(),
+                ) {
+                    self.documents.insert(path.clone(), text.to_string());
+                    return self.publish_diagnostics(&path);
+                }
+                None
+            }
+            "textDocument/didChange" => {
+                // Full sync, the last change is the whole text
+                let changes = params.get("contentChanges").as_array().unwrap_or_default();
+                if let (Some(path), Some(text)) = (
+                    self.document_path(params),
+                    changes
+                        .last()
+                        .and_then(|change| change.get("text").as_str()),
+                ) {
+                    self.documents.insert(path, text.to_string());
+                }
+                None
+            }
+            "textDocument/didSave" => {
+                if let Some(path) = self.document_path(params) {
+                    if let Some(text) = params.get("text").as_str() {
+                        self.documents.insert(path.clone(), text.to_string());
+                    }
+                    return self.publish_diagnostics(&path);
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                if let Some(path) = self.document_path(params) {
+                    self.documents.remove(&path);
+                }
+                None
+            }
+            "textDocument/definition" => Some(self.definition(params).unwrap_or(Json::Null)),
+            "textDocument/hover" => Some(self.hover(params).unwrap_or(Json::Null)),
+            "textDocument/completion" => Some(Json::from(self.completion(params))),
+            "textDocument/documentSymbol" => {
+                Some(self.document_symbols(params).unwrap_or(Json::Null))
+            }
+            // Unknown notifications are ignored
+            _ if id.is_null() => None,
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Unknown method {}", method),
+                )]
+            }
+        };
+        match result {
+            Some(result) if !id.is_null() => vec![response(id, result)],
+            _ => Vec::default(),
+        }
+    }
+
+    fn document_path(&self, params: &Json) -> Option<PathBuf> {
+        uri_to_path(params.get("textDocument").get("uri").as_str()?)
+    }
+    fn project(&mut self, path: &Path) -> Project {
+        let folder = path.parent().unwrap_or(Path::new("."));
+        Project::load(folder, &self.documents, &mut self.parsed)
+    }
+    /// Project, document and offset in it of a TextDocumentPositionParams
+    fn locate(&mut self, params: &Json) -> Option<(Project, PathBuf, usize)> {
+        let path = self.document_path(params)?;
+        let project = self.project(&path);
+        let position = params.get("position");
+        let offset = analysis::offset(
+            &project.file(&path)?.chars,
+            position.get("line").as_usize()?,
+            position.get("character").as_usize()?,
+        );
+        Some((project, path, offset))
+    }
+
+    /// Diagnostics of every file of the project of path, so fixed files are cleared
+    fn publish_diagnostics(&mut self, path: &Path) -> Vec<Json> {
+        let project = self.project(path);
+        project
+            .problems()
+            .into_iter()
+            .map(|(file, problems)| {
+                let diagnostics = problems
+                    .iter()
+                    .map(|problem| {
+                        Json::object(vec![
+                            ("range", range(file, problem.span)),
+                            ("severity", Json::from(SEVERITY_ERROR)),
+                            ("source", Json::from("jack2vm")),
+                            ("message", Json::from(problem.msg.as_str())),
+                        ])
+                    })
+                    .collect::<Vec<Json>>();
+                notification(
+                    "textDocument/publishDiagnostics",
+                    Json::object(vec![
+                        ("uri", Json::from(path_to_uri(&file.path))),
+                        ("diagnostics", Json::from(diagnostics)),
+                    ]),
+                )
+            })
+            .collect()
+    }
+    fn definition(&mut self, params: &Json) -> Option<Json> {
+        let (project, path, offset) = self.locate(params)?;
+        let file = project.file(&path)?;
+        let class = file.class.as_ref()?;
+        let symbol = analysis::symbol_at(class, offset)?;
+        let (path, span) = project.definition(file, class, &symbol)?;
+        Some(Json::object(vec![
+            ("uri", Json::from(path_to_uri(path))),
+            ("range", range(project.file(path)?, span)),
+        ]))
+    }
+    fn hover(&mut self, params: &Json) -> Option<Json> {
+        let (project, path, offset) = self.locate(params)?;
+        let class = project.file(&path)?.class.as_ref()?;
+        let symbol = analysis::symbol_at(class, offset)?;
+        Some(Json::object(vec![(
+            "contents",
+            Json::object(vec![
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(project.hover(class, &symbol)?)),
+            ]),
+        )]))
+    }
+    fn completion(&mut self, params: &Json) -> Vec<Json> {
+        let Some((project, path, offset)) = self.locate(params) else {
+            return Vec::default();
+        };
+        let Some(file) = project.file(&path) else {
+            return Vec::default();
+        };
+        project
+            .completions(file, offset)
+            .into_iter()
+            .map(|member| {
+                let kind = match member.kind {
+                    SubroutineKind::Method => COMPLETION_METHOD,
+                    SubroutineKind::Function => COMPLETION_FUNCTION,
+                    SubroutineKind::Constructor => COMPLETION_CONSTRUCTOR,
+                };
+                Json::object(vec![
+                    ("label", Json::from(member.name)),
+                    ("kind", Json::from(kind)),
+                    ("detail", Json::from(member.detail)),
+                ])
+            })
+            .collect()
+    }
+    /// The class, with its variables and subroutines as children
+    fn document_symbols(&mut self, params: &Json) -> Option<Json> {
+        let path = self.document_path(params)?;
+        let project = self.project(&path);
+        let file = project.file(&path)?;
+        let class = file.class.as_ref()?;
+        let symbol = |name: &str, kind: usize, span: Span, selection: Span| {
+            Json::object(vec![
+                ("name", Json::from(name)),
+                ("kind", Json::from(kind)),
+                ("range", range(file, span)),
+                ("selectionRange", range(file, selection)),
+            ])
+        };
+        let mut children = Vec::default();
+        for var_dec in &class.var_decs {
+            for name in &var_dec.names {
+                let kind = match var_dec.kind {
+                    jack2vm::ast::ClassVarKind::Field => SYMBOL_FIELD,
+                    jack2vm::ast::ClassVarKind::Static => SYMBOL_VARIABLE,
+                };
+                children.push(symbol(&name.name, kind, var_dec.span, name.span));
+            }
+        }
+        for subroutine in &class.subroutines {
+            let kind = match subroutine.kind {
+                SubroutineKind::Constructor => SYMBOL_CONSTRUCTOR,
+                SubroutineKind::Function => SYMBOL_FUNCTION,
+                SubroutineKind::Method => SYMBOL_METHOD,
+            };
+            children.push(symbol(
+                &subroutine.name.name,
+                kind,
+                subroutine.span,
+                subroutine.name.span,
+            ));
+        }
+        let mut class_symbol = symbol(&class.name.name, SYMBOL_CLASS, class.span, class.name.span);
+        if let Json::Object(entries) = &mut class_symbol {
+            entries.push((String::from("children"), Json::from(children)));
+        }
+        Some(Json::from(vec![class_symbol]))
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                (
+                    "textDocumentSync",
+                    Json::object(vec![
+                        ("openClose", Json::from(true)),
+                        ("change", Json::from(SYNC_FULL)),
+                        (
+                            "save",
+                            Json::object(vec![("includeText", Json::from(true))]),
+                        ),
+                    ]),
+                ),
+                ("definitionProvider", Json::from(true)),
+                ("hoverProvider", Json::from(true)),
+                (
+                    "completionProvider",
+                    Json::object(vec![(
+                        "triggerCharacters",
+                        Json::from(vec![Json::from(".")]),
+                    )]),
+                ),
+                ("documentSymbolProvider", Json::from(true)),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![("name", Json::from("jackls"))]),
+        ),
+    ])
+}
+
+fn range(file: &SourceFile, span: Span) -> Json {
+    let position = |offset| {
+        let (line, character) = analysis::position(&file.chars, offset);
+        Json::object(vec![
+            ("line", Json::from(line)),
+            ("character", Json::from(character)),
+        ])
+    };
+    Json::object(vec![
+        ("start", position(span.start)),
+        ("end", position(span.end())),
+    ])
+}
+
+/// Only file URIs are supported
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::default();
+    let mut i = 0;
+    while i < path.len() {
+        let escaped = (path[i] == b'%')
+            .then(|| std::str::from_utf8(path.get(i + 1..i + 3)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(path[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, io::Cursor};
+
+    const POINT: &str = "class Point {
+    field int x, y;
+    static int count;
+
+    constructor Point new(int ax, int ay) {
+        let x = ax;
+        let y = ay;
+        return this;
+    }
+    method int getX() { return x; }
+}
+";
+    const MAIN: &str = "class Main {
+    function void main() {
+        var Point p;
+        let p = Point.new(1, 2);
+        do /* \u{e9}\u{1f600} */ Output.printInt(p.getX());
+        return;
+    }
+}
+";
+
+    /// (line, character) of the nth occurrence of needle
+    fn position(text: &str, needle: &str, nth: usize) -> Json {
+        position_at(text, text.match_indices(needle).nth(nth).unwrap().0)
+    }
+    fn position_after(text: &str, needle: &str) -> Json {
+        position_at(text, text.find(needle).unwrap() + needle.len())
+    }
+    /// The character is in UTF-16 code units, like an LSP client counts them
+    fn position_at(text: &str, offset: usize) -> Json {
+        let line = text[..offset].matches('\n').count();
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let character = text[line_start..offset].encode_utf16().count();
+        Json::object(vec![
+            ("line", Json::from(line)),
+            ("character", Json::from(character)),
+        ])
+    }
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", params),
+        ])
+    }
+    fn at(uri: &str, position: Json) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(uri))])),
+            ("position", position),
+        ])
+    }
+    fn document(uri: &str) -> Json {
+        Json::object(vec![(
+            "textDocument",
+            Json::object(vec![("uri", Json::from(uri))]),
+        )])
+    }
+    fn with_text(uri: &str, key: &str, text: &str) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(uri))])),
+            (key, Json::from(text)),
+        ])
+    }
+
+    /// Writes the messages as a client would and reads back what the server sent
+    fn session(messages: &[Json]) -> (i32, Vec<Json>) {
+        let mut input = Vec::default();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut output = Vec::default();
+        let code = serve(&mut Cursor::new(input), &mut output).unwrap();
+        let mut reader = Cursor::new(output);
+        let mut outgoing = Vec::default();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            outgoing.push(message);
+        }
+        (code, outgoing)
+    }
+    fn result(outgoing: &[Json], id: usize) -> &Json {
+        outgoing
+            .iter()
+            .find(|message| message.get("id").as_usize() == Some(id))
+            .unwrap()
+            .get("result")
+    }
+    fn diagnostics<'a>(outgoing: &'a [Json], uri: &str) -> Vec<&'a Json> {
+        outgoing
+            .iter()
+            .filter(|message| {
+                message.get("method").as_str() == Some("textDocument/publishDiagnostics")
+                    && message.get("params").get("uri").as_str() == Some(uri)
+            })
+            .map(|message| message.get("params").get("diagnostics"))
+            .collect()
+    }
+
+    #[test]
+    fn session_over_stdio() {
+        let folder = env::temp_dir().join(format!("jackls {}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("Point.jack"), POINT).unwrap();
+        fs::write(folder.join("Main.jack"), MAIN).unwrap();
+        let main = path_to_uri(&folder.join("Main.jack"));
+        let point = path_to_uri(&folder.join("Point.jack"));
+        assert!(main.contains("%20"));
+
+        // Being typed, does not parse, the last class that parsed is used
+        let typing = MAIN.replace("p.getX()", "p.");
+        let broken = MAIN.replace("let p =", "let q =");
+        let (code, outgoing) = session(&[
+            request(1, "initialize", Json::object(vec![])),
+            notification("initialized", Json::object(vec![])),
+            notification(
+                "textDocument/didOpen",
+                Json::object(vec![(
+                    "textDocument",
+                    Json::object(vec![
+                        ("uri", Json::from(main.as_str())),
+                        ("languageId", Json::from("jack")),
+                        ("version", Json::from(1)),
+                        ("text", Json::from(MAIN)),
+                    ]),
+                )]),
+            ),
+            request(
+                2,
+                "textDocument/definition",
+                at(&main, position(MAIN, "getX", 0)),
+            ),
+            request(
+                3,
+                "textDocument/definition",
+                at(&main, position(MAIN, "p;", 0)),
+            ),
+            request(4, "textDocument/hover", at(&main, position(MAIN, "p.", 0))),
+            request(
+                5,
+                "textDocument/definition",
+                at(&main, position(MAIN, "Point", 1)),
+            ),
+            request(6, "textDocument/documentSymbol", document(&point)),
+            notification(
+                "textDocument/didChange",
+                Json::object(vec![
+                    (
+                        "textDocument",
+                        Json::object(vec![("uri", Json::from(main.as_str()))]),
+                    ),
+                    (
+                        "contentChanges",
+                        Json::from(vec![Json::object(vec![(
+                            "text",
+                            Json::from(typing.as_str()),
+                        )])]),
+                    ),
+                ]),
+            ),
+            request(
+                7,
+                "textDocument/completion",
+                at(&main, position_after(&typing, "p.")),
+            ),
+            request(
+                8,
+                "textDocument/completion",
+                at(&main, position(&typing, "new", 0)),
+            ),
+            request(
+                9,
+                "textDocument/completion",
+                at(&main, position(&typing, "Int", 0)),
+            ),
+            notification("textDocument/didSave", with_text(&main, "text", &broken)),
+            request(
+                10,
+                "textDocument/hover",
+                at(&main, position(&broken, "return", 0)),
+            ),
+            request(11, "workspace/symbol", Json::object(vec![])),
+            request(12, "shutdown", Json::Null),
+            notification("exit", Json::Null),
+        ]);
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(code, 0);
+
+        let capabilities = result(&outgoing, 1).get("capabilities");
+        assert_eq!(capabilities.get("hoverProvider"), &Json::Bool(true));
+
+        // Every file of the project, clean on open and then an error on save
+        let main_diagnostics = diagnostics(&outgoing, &main);
+        assert_eq!(main_diagnostics.len(), 2);
+        assert_eq!(main_diagnostics[0], &Json::Array(vec![]));
+        assert_eq!(diagnostics(&outgoing, &point).len(), 2);
+        let error = &main_diagnostics[1].as_array().unwrap()[0];
+        assert_eq!(
+            error.get("message").as_str(),
+            Some("Variable q is not declared")
+        );
+        assert_eq!(
+            error.get("range").get("start"),
+            &position(&broken, "q =", 0)
+        );
+
+        let definition = result(&outgoing, 2);
+        assert_eq!(definition.get("uri").as_str(), Some(point.as_str()));
+        assert_eq!(
+            definition.get("range").get("start"),
+            &position(POINT, "getX", 0)
+        );
+        let definition = result(&outgoing, 3);
+        assert_eq!(definition.get("uri").as_str(), Some(main.as_str()));
+        assert_eq!(
+            definition.get("range").get("start"),
+            &position(MAIN, "p;", 0)
+        );
+        assert_eq!(
+            result(&outgoing, 4).get("contents").get("value").as_str(),
+            Some("```jack\nvar Point p\n```\nlocal 0")
+        );
+        assert_eq!(
+            result(&outgoing, 5).get("range").get("start"),
+            &position(POINT, "Point", 0)
+        );
+
+        let symbols = result(&outgoing, 6).as_array().unwrap();
+        assert_eq!(symbols[0].get("name").as_str(), Some("Point"));
+        let children = symbols[0]
+            .get("children")
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|child| {
+                (
+                    child.get("name").as_str().unwrap(),
+                    child.get("kind").as_usize().unwrap(),
+                )
+            })
+            .collect::<Vec<(&str, usize)>>();
+        assert_eq!(
+            children,
+            vec![
+                ("x", SYMBOL_FIELD),
+                ("y", SYMBOL_FIELD),
+                ("count", SYMBOL_VARIABLE),
+                ("new", SYMBOL_CONSTRUCTOR),
+                ("getX", SYMBOL_METHOD)
+            ]
+        );
+
+        let labels = |id| {
+            result(&outgoing, id)
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item.get("label").as_str().unwrap().to_string())
+                .collect::<Vec<String>>()
+        };
+        // Methods of a variable, the cursor is right after the dot
+        let items = result(&outgoing, 7).as_array().unwrap();
+        assert_eq!(labels(7), vec!["getX"]);
+        assert_eq!(
+            items[0].get("detail").as_str(),
+            Some("method int Point.getX()")
+        );
+        assert_eq!(labels(8), vec!["new"]);
+        // OS classes, completed from the start of printInt
+        assert!(labels(9).contains(&String::from("printInt")));
+
+        assert!(result(&outgoing, 10).is_null());
+        let unknown = outgoing
+            .iter()
+            .find(|message| message.get("id").as_usize() == Some(11))
+            .unwrap();
+        assert_eq!(
+            unknown.get("error").get("code"),
+            &Json::Number(METHOD_NOT_FOUND as f64)
+        );
+    }
+    #[test]
+    fn uris() {
+        let path = PathBuf::from("/tmp/a b/Main\u{e9}.jack");
+        let uri = path_to_uri(&path);
+        assert_eq!(uri, "file:///tmp/a%20b/Main%C3%A9.jack");
+        assert_eq!(uri_to_path(&uri), Some(path));
+        assert_eq!(uri_to_path("untitled:1"), None);
+    }
+}
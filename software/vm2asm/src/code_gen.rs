@@ -1,4 +1,12 @@
-const SP: &str = "@SP";
+use std::collections::BTreeSet;
+
+use asm2hack::{Address, Comp, Dest, Instruction, Jump};
+
+use crate::MemoryLayout;
+
+fn sp() -> Instruction {
+    Instruction::symbol("SP")
+}
 
 #[derive(Debug)]
 pub enum MemorySegments {
@@ -48,154 +56,279 @@ impl MemorySegments {
     }
 }
 
+/// Debug mode checks in the generated asm
+/// A fault writes its error code to error_address and halts, see CodeGen::runtime_check_handlers
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeChecks {
+    // Faults when SP goes past this after a push, call or function entry
+    pub stack_limit: usize,
+    pub error_address: usize,
+}
+impl RuntimeChecks {
+    pub const STACK_OVERFLOW: usize = 1;
+    // pointer or that pointing outside of RAM
+    pub const SEGMENT_FAULT: usize = 2;
+    pub const DIVIDE_BY_ZERO: usize = 3;
+    /// Last address of RAM, including the keyboard
+    const RAM_END: usize = 24576;
+
+    pub fn new(layout: &MemoryLayout) -> Self {
+        Self {
+            stack_limit: layout.heap_start,
+            // Last word of the heap, right before the screen
+            error_address: 16383,
+        }
+    }
+}
+impl Default for RuntimeChecks {
+    fn default() -> Self {
+        Self::new(&MemoryLayout::default())
+    }
+}
+
 #[derive(Default)]
 pub struct CodeGen {
     binary_counter: usize,
     call_counter: usize,
+    math_counter: usize,
+    layout: MemoryLayout,
+    runtime_checks: Option<RuntimeChecks>,
 }
 impl CodeGen {
-    pub fn bootstrap() -> Vec<String> {
+    /// Range of push constant, the 16 bits of a word read as either i16 or u16
+    pub const CONSTANT_MIN: i32 = i16::MIN as i32;
+    pub const CONSTANT_MAX: i32 = u16::MAX as i32;
+    /// Asm variables of the math routines, they live with the statics
+    pub const MATH_VARIABLES: [&'static str; 10] = [
+        "__MATH.x",
+        "__MATH.y",
+        "__MATH.return",
+        "__MATH.result",
+        "__MATH.mask",
+        "__MATH.r",
+        "__MATH.sign",
+        "__MATH.qsign",
+        "__MATH.count",
+        "__MATH.bit",
+    ];
+    pub const MATH_COMMANDS: [&'static str; 5] = ["mul", "div", "mod", "shl", "shr"];
+    /// Words pushed by a call, return address, LCL, ARG, THIS, THAT
+    pub const CALL_FRAME_SIZE: usize = 5;
+
+    pub fn new(layout: MemoryLayout) -> Self {
+        Self {
+            layout,
+            ..Self::default()
+        }
+    }
+    pub fn with_runtime_checks(mut self, runtime_checks: RuntimeChecks) -> Self {
+        self.runtime_checks = Some(runtime_checks);
+        self
+    }
+    pub fn bootstrap(&self) -> Vec<Instruction> {
         // Called once at the start?
+        Self::set_pointer("SP", self.layout.stack_base)
+    }
+    /// pointer = value, for the bootstrap
+    pub fn set_pointer(pointer: &str, value: usize) -> Vec<Instruction> {
         vec![
-            String::from("@256"),
-            String::from("D=A"),
-            String::from("@SP"),
-            String::from("M=D"),
+            Instruction::value(value),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol(pointer),
+            Instruction::assign(Dest::M, Comp::D),
         ]
     }
-    pub fn add() -> Vec<String> {
-        let mut asm = vec![String::from("//add")];
+    pub fn add() -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("add")];
         asm.append(&mut Self::sp_minus_1_load_d());
         asm.append(&mut Self::sp_a_m_minus_1());
-        asm.push(String::from("M=D+M // *SP-- = *SP-- + D, *SP-- = X + Y"));
+        // *SP-- = *SP-- + D, *SP-- = X + Y
+        asm.push(Instruction::assign(Dest::M, Comp::DPlusM));
         asm
     }
-    pub fn sub() -> Vec<String> {
-        let mut asm = vec![String::from("//sub")];
+    pub fn sub() -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("sub")];
         asm.append(&mut Self::sp_minus_1_load_d());
         asm.append(&mut Self::sp_a_m_minus_1());
-        asm.push(String::from("M=M-D // *SP-- = *SP-- + D, *SP-- = X - Y"));
+        // *SP-- = *SP-- + D, *SP-- = X - Y
+        asm.push(Instruction::assign(Dest::M, Comp::MMinusD));
         asm
     }
-    pub fn neg() -> Vec<String> {
+    pub fn neg() -> Vec<Instruction> {
         vec![
-            String::from("//neg"),
-            SP.to_string(),
-            String::from("A=M-1"),
-            String::from("M=-M // *SP-- = -*SP--"),
+            Instruction::comment("neg"),
+            sp(),
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::M, Comp::NegM), // *SP-- = -*SP--
         ]
     }
-    pub fn and() -> Vec<String> {
-        let mut asm = vec![String::from("//and")];
+    pub fn and() -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("and")];
         asm.append(&mut Self::sp_minus_1_load_d());
         asm.append(&mut Self::sp_a_m_minus_1());
-        asm.push(String::from("M=D&M"));
+        asm.push(Instruction::assign(Dest::M, Comp::DAndM));
         asm
     }
-    pub fn or() -> Vec<String> {
-        let mut asm = vec![String::from("//or")];
+    pub fn or() -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("or")];
         asm.append(&mut Self::sp_minus_1_load_d());
         asm.append(&mut Self::sp_a_m_minus_1());
-        asm.push(String::from("M=D|M"));
+        asm.push(Instruction::assign(Dest::M, Comp::DOrM));
         asm
     }
-    pub fn not() -> Vec<String> {
+    pub fn not() -> Vec<Instruction> {
         vec![
-            String::from("//not"),
-            SP.to_string(),
-            String::from("A=M-1"),
-            String::from("M=!M // *SP-- = !*SP--"),
+            Instruction::comment("not"),
+            sp(),
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::M, Comp::NotM), // *SP-- = !*SP--
         ]
     }
-    fn sp_minus_1_load_d() -> Vec<String> {
+    fn sp_minus_1_load_d() -> Vec<Instruction> {
         vec![
-            SP.to_string(),
-            String::from("AM=M-1 // SP = SP--"),
-            String::from("D=M // D = *SP"),
+            sp(),
+            Instruction::assign(Dest::Am, Comp::MMinusOne), // SP = SP--
+            Instruction::assign(Dest::D, Comp::M),          // D = *SP
         ]
     }
-    fn sp_a_m_minus_1() -> Vec<String> {
-        vec![SP.to_string(), String::from("A=M-1")]
+    fn sp_a_m_minus_1() -> Vec<Instruction> {
+        vec![sp(), Instruction::assign(Dest::A, Comp::MMinusOne)]
+    }
+    /// D = *(pointer), then pushes D
+    fn push_pointer_value(pointer: &str) -> Vec<Instruction> {
+        vec![
+            Instruction::symbol(pointer),
+            Instruction::assign(Dest::D, Comp::M),
+            sp(),
+            Instruction::assign(Dest::M, Comp::MPlusOne), // SP++
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::M, Comp::D), // *SP = D
+        ]
     }
 
-    pub fn bin_comp(&mut self, file_name: &String, comp: &str) -> Vec<String> {
+    pub fn bin_comp(&mut self, file_name: &String, comp: &str) -> Vec<Instruction> {
         let asm_jump_comp = match comp {
-            "eq" => "JEQ",
-            "gt" => "JGT", // x > y
-            "lt" => "JLT", // x < y
+            "eq" => Jump::Jeq,
+            "gt" => Jump::Jgt, // x > y
+            "lt" => Jump::Jlt, // x < y
             _ => panic!("Unexpected comp, {}", comp),
         };
         self.binary_counter += 1;
+        let label = format!("{}.{}.{}", file_name, comp, self.binary_counter - 1);
         vec![
-            format!("//{}", comp),
-            SP.to_string(),
-            String::from("AM=M-1 // SP = SP--"),
-            String::from("D=M // D = *SP"),
-            String::from("A=A-1 // SP--"),
-            String::from("D=M-D // D = *SP-- - D, D=x-y"),
-            String::from("M=-1 // *SP-- = true"),
-            format!("@{}.{}.{}", file_name, comp, self.binary_counter - 1),
-            format!("D;{}", asm_jump_comp),
-            SP.to_string(),
-            String::from("A=M-1"),
-            String::from("M=0 // *SP-- = false"),
-            format!("({}.{}.{})", file_name, comp, self.binary_counter - 1),
+            Instruction::comment(comp),
+            sp(),
+            Instruction::assign(Dest::Am, Comp::MMinusOne), // SP = SP--
+            Instruction::assign(Dest::D, Comp::M),          // D = *SP
+            Instruction::assign(Dest::A, Comp::AMinusOne),  // SP--
+            Instruction::assign(Dest::D, Comp::MMinusD),    // D = *SP-- - D, D=x-y
+            Instruction::assign(Dest::M, Comp::MinusOne),   // *SP-- = true
+            Instruction::symbol(&label),
+            Instruction::jump(Comp::D, asm_jump_comp),
+            sp(),
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::M, Comp::Zero), // *SP-- = false
+            Instruction::label(&label),
         ]
     }
 
+    /// D = value, value can be anything from i16::MIN up to u16::MAX
+    /// @value only holds 15 bits, so the rest are loaded through -A or !A
+    fn load_constant_d(value: i32) -> Vec<Instruction> {
+        if !(Self::CONSTANT_MIN..=Self::CONSTANT_MAX).contains(&value) {
+            panic!("Constant should fit in 16 bits, not {}", value);
+        }
+        // Values past i16::MAX wrap around, 40000 is the same word as -25536
+        let word = value as u16;
+        if word <= 32767 {
+            return vec![
+                Instruction::value(word as usize),
+                Instruction::assign(Dest::D, Comp::A), // D = value
+            ];
+        }
+        let negated = word.wrapping_neg();
+        if negated <= 32767 {
+            vec![
+                Instruction::value(negated as usize),
+                Instruction::assign(Dest::D, Comp::NegA), // D = -(-value)
+            ]
+        } else {
+            // -32768 is the only word whose negation does not fit, !32767 = -32768
+            vec![
+                Instruction::value(!word as usize),
+                Instruction::assign(Dest::D, Comp::NotA), // D = !(!value)
+            ]
+        }
+    }
+    /// push constant that can be negative, push_segment only takes the non negative ones
+    pub fn push_constant(&self, value: i32) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment(&format!("push constant {}", value))];
+        asm.append(&mut Self::load_constant_d(value));
+        asm.append(&mut vec![
+            sp(),
+            Instruction::assign(Dest::Am, Comp::MPlusOne), // *SP+1, SP++
+            Instruction::assign(Dest::A, Comp::AMinusOne),
+            Instruction::assign(Dest::M, Comp::D), // *SP = value
+        ]);
+        asm.append(&mut self.check_stack());
+        asm
+    }
+
     pub fn push_segment(
+        &self,
         file_name: &String,
         memory_segment: MemorySegments,
         i: usize,
-    ) -> Vec<String> {
-        let mut asm = vec![format!("//push {} {}", memory_segment.to_token(), i)];
+    ) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment(&format!(
+            "push {} {}",
+            memory_segment.to_token(),
+            i
+        ))];
+        if let MemorySegments::That = memory_segment {
+            asm.append(&mut self.check_that_i(i));
+        }
         match memory_segment {
             MemorySegments::Local
             | MemorySegments::Argument
             | MemorySegments::That
             | MemorySegments::This => {
                 asm.append(&mut vec![
-                    format!("@{}", memory_segment.to_asm()),
-                    String::from("D=M"),
-                    format!("@{}", i),
-                    String::from("A=D+A"),
-                    format!("D=M // D = *({}+{})", memory_segment.to_asm(), i),
-                    SP.to_string(),
-                    String::from("A=M"),
-                    String::from("M=D // *SP = D"),
-                    SP.to_string(),
-                    String::from("M=M+1 // SP++"),
+                    Instruction::symbol(&memory_segment.to_asm()),
+                    Instruction::assign(Dest::D, Comp::M),
+                    Instruction::value(i),
+                    Instruction::assign(Dest::A, Comp::DPlusA),
+                    Instruction::assign(Dest::D, Comp::M), // D = *(segment+i)
+                    sp(),
+                    Instruction::assign(Dest::A, Comp::M),
+                    Instruction::assign(Dest::M, Comp::D), // *SP = D
+                    sp(),
+                    Instruction::assign(Dest::M, Comp::MPlusOne), // SP++
                 ]);
             }
             MemorySegments::Constant => {
+                asm.append(&mut Self::load_constant_d(i as i32));
                 asm.append(&mut vec![
-                    format!("@{}", i),
-                    format!("D=A // D = {}", i),
-                    SP.to_string(),
-                    String::from("AM=M+1 // *SP+1, SP++"),
-                    String::from("A=A-1"),
-                    format!("M=D // *SP = {}", i),
+                    sp(),
+                    Instruction::assign(Dest::Am, Comp::MPlusOne), // *SP+1, SP++
+                    Instruction::assign(Dest::A, Comp::AMinusOne),
+                    Instruction::assign(Dest::M, Comp::D), // *SP = i
                 ]);
             }
             MemorySegments::Static => {
-                asm.append(&mut vec![
-                    format!("@{}.{}", file_name, i),
-                    String::from("D=M"),
-                    SP.to_string(),
-                    String::from("M=M+1 // SP++"),
-                    String::from("A=M-1"),
-                    String::from("M=D // *SP = D"),
-                ]);
+                asm.append(&mut Self::push_pointer_value(&Self::static_symbol(
+                    file_name, i,
+                )));
             }
             MemorySegments::Temp => {
-                let temp_address = 5 + i;
+                let temp_address = self.layout.temp_base + i;
                 asm.append(&mut vec![
-                    format!("@{}", temp_address),
-                    String::from("D=M"),
-                    SP.to_string(),
-                    String::from("M=M+1 // SP++"),
-                    String::from("A=M-1"),
-                    String::from("M=D // *SP = D"),
+                    Instruction::value(temp_address),
+                    Instruction::assign(Dest::D, Comp::M),
+                    sp(),
+                    Instruction::assign(Dest::M, Comp::MPlusOne), // SP++
+                    Instruction::assign(Dest::A, Comp::MMinusOne),
+                    Instruction::assign(Dest::M, Comp::D), // *SP = D
                 ]);
             }
             MemorySegments::Pointer => {
@@ -204,35 +337,40 @@ impl CodeGen {
                     1 => "THAT",
                     _ => panic!("Pointer i should be 0 or 1, not {}", i),
                 };
-                asm.append(&mut vec![
-                    format!("@{}", pointer),
-                    String::from("D=M"),
-                    SP.to_string(),
-                    String::from("M=M+1 // SP++"),
-                    String::from("A=M-1"),
-                    String::from("M=D // *SP = D"),
-                ]);
+                asm.append(&mut Self::push_pointer_value(pointer));
             }
         };
+        asm.append(&mut self.check_stack());
         asm
     }
     pub fn pop_segment(
+        &self,
         file_name: &String,
         memory_segment: MemorySegments,
         i: usize,
-    ) -> Vec<String> {
-        let mut asm = vec![format!("//pop {} {}", memory_segment.to_token(), i)];
+    ) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment(&format!(
+            "pop {} {}",
+            memory_segment.to_token(),
+            i
+        ))];
+        if let MemorySegments::That = memory_segment {
+            asm.append(&mut self.check_that_i(i));
+        }
         match memory_segment {
             MemorySegments::Temp => {
-                let temp_address = i + 5;
+                let temp_address = i + self.layout.temp_base;
                 asm.append(&mut Self::sp_minus_1_load_d());
-                asm.append(&mut vec![format!("@{}", temp_address), String::from("M=D")]);
+                asm.append(&mut vec![
+                    Instruction::value(temp_address),
+                    Instruction::assign(Dest::M, Comp::D),
+                ]);
             }
             MemorySegments::Static => {
                 asm.append(&mut Self::sp_minus_1_load_d());
                 asm.append(&mut vec![
-                    format!("@{}.{}", file_name, i),
-                    String::from("M=D"),
+                    Instruction::symbol(&Self::static_symbol(file_name, i)),
+                    Instruction::assign(Dest::M, Comp::D),
                 ]);
             }
             MemorySegments::Pointer => {
@@ -242,7 +380,12 @@ impl CodeGen {
                     _ => panic!("Pointer i should be 0 or 1, not {}", i),
                 };
                 asm.append(&mut Self::sp_minus_1_load_d());
-                asm.append(&mut vec![format!("@{}", pointer), String::from("M=D")]);
+                asm.append(&mut vec![
+                    Instruction::symbol(pointer),
+                    Instruction::assign(Dest::M, Comp::D),
+                ]);
+                // D is still the new pointer
+                asm.append(&mut self.check_address_in_d());
             }
             MemorySegments::Constant => {
                 panic!("Pop Constant should not happen")
@@ -253,189 +396,652 @@ impl CodeGen {
             | MemorySegments::This => {
                 asm.append(&mut Self::sp_minus_1_load_d());
                 asm.append(&mut vec![
-                    format!("@{}", memory_segment.to_asm()),
-                    String::from("D=D+M // *SP + LCL"),
-                    format!("@{}", i),
-                    String::from("D=D+A // *SP + (LCL+i)"),
-                    SP.to_string(),
-                    String::from("A=M // *SP"),
-                    String::from("A=M // A = *SP"),
-                    String::from("A=D-A // A = *SP + (LCL+i) - *SP, A = (LCL+i)"),
-                    String::from("M=D-A // *(LCL+i) = *SP + (LCL+i) - (LCL+i)"),
+                    Instruction::symbol(&memory_segment.to_asm()),
+                    Instruction::assign(Dest::D, Comp::DPlusM), // *SP + LCL
+                    Instruction::value(i),
+                    Instruction::assign(Dest::D, Comp::DPlusA), // *SP + (LCL+i)
+                    sp(),
+                    Instruction::assign(Dest::A, Comp::M), // *SP
+                    Instruction::assign(Dest::A, Comp::M), // A = *SP
+                    // A = *SP + (LCL+i) - *SP, A = (LCL+i)
+                    Instruction::assign(Dest::A, Comp::DMinusA),
+                    // *(LCL+i) = *SP + (LCL+i) - (LCL+i)
+                    Instruction::assign(Dest::M, Comp::DMinusA),
                 ]);
             }
         };
         asm
     }
+    /// Asm variable of static i of a file
+    pub fn static_symbol(file_name: &String, i: usize) -> String {
+        format!("{}.{}", file_name, i)
+    }
     fn generate_asm_label(function_label: &String, label: &String) -> String {
         format!("{}${}", function_label, label)
     }
-    pub fn label(function_label: &String, label: &String) -> Vec<String> {
+    pub fn label(function_label: &String, label: &String) -> Vec<Instruction> {
         vec![
-            format!("//label {}", label),
-            format!("({})", Self::generate_asm_label(function_label, label)),
+            Instruction::comment(&format!("label {}", label)),
+            Instruction::label(&Self::generate_asm_label(function_label, label)),
         ]
     }
-    pub fn goto_label(function_label: &String, label: &String) -> Vec<String> {
+    pub fn goto_label(function_label: &String, label: &String) -> Vec<Instruction> {
         vec![
-            format!("//goto {}", label),
-            format!("@{}", Self::generate_asm_label(function_label, label)),
-            String::from("0;JMP"),
+            Instruction::comment(&format!("goto {}", label)),
+            Instruction::symbol(&Self::generate_asm_label(function_label, label)),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
         ]
     }
-    pub fn if_goto_label(function_label: &String, label: &String) -> Vec<String> {
-        let mut asm = vec![format!("//if-goto {}", label)];
+    pub fn if_goto_label(function_label: &String, label: &String) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment(&format!("if-goto {}", label))];
         asm.append(&mut Self::sp_minus_1_load_d());
         asm.append(&mut vec![
-            format!("@{}", Self::generate_asm_label(function_label, label)),
-            String::from("D;JNE"),
+            Instruction::symbol(&Self::generate_asm_label(function_label, label)),
+            Instruction::jump(Comp::D, Jump::Jne),
         ]);
         asm
     }
+    /// Inline asm, copied through as is
+    /// Labels of the current function, given in labels, are scoped the same way as VM labels,
+    /// so the asm can also jump to a VM label of the function
+    pub fn inline_asm(
+        function_label: &String,
+        instructions: &[Instruction],
+        labels: &BTreeSet<String>,
+    ) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("asm")];
+        asm.extend(instructions.iter().map(|instruction| match instruction {
+            Instruction::Label(label) if labels.contains(label) => {
+                Instruction::label(&Self::generate_asm_label(function_label, label))
+            }
+            Instruction::A(Address::Symbol(symbol)) if labels.contains(symbol) => {
+                Instruction::symbol(&Self::generate_asm_label(function_label, symbol))
+            }
+            instruction => instruction.clone(),
+        }));
+        asm
+    }
+    /// Pushes the value of pointer, part of the call frame
+    fn push_frame_pointer(pointer: &str) -> Vec<Instruction> {
+        vec![
+            Instruction::symbol(pointer),
+            Instruction::assign(Dest::D, Comp::M),
+            sp(),
+            Instruction::assign(Dest::Am, Comp::MPlusOne), // SP++
+            Instruction::assign(Dest::A, Comp::AMinusOne), // SP
+            Instruction::assign(Dest::M, Comp::D),
+        ]
+    }
     pub fn call(
         &mut self,
         file_name: &String,
         function_name: &String,
         nargs: usize,
-    ) -> Vec<String> {
+    ) -> Vec<Instruction> {
         self.call_counter += 1;
-        vec![
-            format!("//call {} {}, {}", function_name, nargs, file_name),
-            format!(
-                "@{}.{}.return.{} // push @{}.return.{}",
-                file_name,
-                function_name,
-                self.call_counter - 1,
-                function_name,
-                self.call_counter - 1
-            ),
-            String::from("D=A"),
-            SP.to_string(),
-            String::from("AM=M+1 // SP++"),
-            String::from("A=A-1 // SP"),
-            format!("M=D // {}.return.{}", function_name, self.call_counter - 1),
-            // push LCL
-            String::from("@LCL // push LCL"),
-            String::from("D=M"),
-            SP.to_string(),
-            String::from("AM=M+1 // SP++"),
-            String::from("A=A-1 // SP"),
-            String::from("M=D // LCL"),
-            // push ARG
-            String::from("@ARG // push ARG"),
-            String::from("D=M"),
-            SP.to_string(),
-            String::from("AM=M+1 // SP++"),
-            String::from("A=A-1 // SP"),
-            String::from("M=D // ARG"),
-            // push THIS
-            String::from("@THIS // push THIS"),
-            String::from("D=M"),
-            SP.to_string(),
-            String::from("AM=M+1 // SP++"),
-            String::from("A=A-1 // SP"),
-            String::from("M=D // THIS"),
-            // push THAT
-            String::from("@THAT // push THAT"),
-            String::from("D=M"),
-            SP.to_string(),
-            String::from("AM=M+1 // SP++"),
-            String::from("A=A-1 // SP"),
-            String::from("M=D // THAT"),
+        let return_label = format!(
+            "{}.{}.return.{}",
+            file_name,
+            function_name,
+            self.call_counter - 1
+        );
+        let mut asm = vec![
+            Instruction::comment(&format!("call {} {}, {}", function_name, nargs, file_name)),
+            // push the return address
+            Instruction::symbol(&return_label),
+            Instruction::assign(Dest::D, Comp::A),
+            sp(),
+            Instruction::assign(Dest::Am, Comp::MPlusOne), // SP++
+            Instruction::assign(Dest::A, Comp::AMinusOne), // SP
+            Instruction::assign(Dest::M, Comp::D),
+        ];
+        for pointer in ["LCL", "ARG", "THIS", "THAT"] {
+            asm.append(&mut Self::push_frame_pointer(pointer));
+        }
+        asm.append(&mut self.check_stack());
+        asm.append(&mut vec![
             // Setting LCL and ARG
-            SP.to_string(),
-            String::from("D=M"),
-            String::from("@LCL"),
-            String::from("M=D // set LCL"),
-            format!("@{}", 5 + nargs),
-            format!("D=D-A // D = SP - {}", 5 + nargs),
-            String::from("@ARG"),
-            String::from("M=D // set ARG"),
-            format!("@{}", function_name),
-            String::from("0;JMP"),
-            format!(
-                "({}.{}.return.{})",
-                file_name,
-                function_name,
-                self.call_counter - 1
-            ),
+            sp(),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("LCL"),
+            Instruction::assign(Dest::M, Comp::D), // set LCL
+            Instruction::value(Self::CALL_FRAME_SIZE + nargs),
+            Instruction::assign(Dest::D, Comp::DMinusA), // D = SP - (5 + nargs)
+            Instruction::symbol("ARG"),
+            Instruction::assign(Dest::M, Comp::D), // set ARG
+            Instruction::symbol(function_name),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label(&return_label),
+        ]);
+        asm
+    }
+    /// *R13++ = *R14++
+    fn copy_r14_to_r13() -> Vec<Instruction> {
+        vec![
+            Instruction::symbol("R14"),
+            Instruction::assign(Dest::Am, Comp::MPlusOne),
+            Instruction::assign(Dest::A, Comp::AMinusOne),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("R13"),
+            Instruction::assign(Dest::Am, Comp::MPlusOne),
+            Instruction::assign(Dest::A, Comp::AMinusOne),
+            Instruction::assign(Dest::M, Comp::D),
         ]
     }
-    pub fn function(function_name: &String, nargs: usize) -> Vec<String> {
+    /// register = *pointer - offset
+    fn set_copy_pointer(register: &str, pointer: &str, offset: usize) -> Vec<Instruction> {
+        vec![
+            Instruction::symbol(pointer),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::value(offset),
+            Instruction::assign(Dest::D, Comp::DMinusA),
+            Instruction::symbol(register),
+            Instruction::assign(Dest::M, Comp::D),
+        ]
+    }
+    /// call function_name nargs, followed by return
+    /// Instead of pushing a new frame, the callee takes over the frame of the current function
+    /// so it returns straight to the caller of the current function
+    ///
+    /// The saved frame is copied above SP first, as the args can be written over it
+    pub fn tail_call(&self, function_name: &String, nargs: usize) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment(&format!(
+            "tail call {} {}",
+            function_name, nargs
+        ))];
+        asm.append(&mut self.check_stack_above(Self::CALL_FRAME_SIZE));
+        // Saved frame, LCL - 5 up to LCL - 1, to SP up to SP + 4
+        asm.append(&mut Self::set_copy_pointer(
+            "R14",
+            "LCL",
+            Self::CALL_FRAME_SIZE,
+        ));
+        asm.append(&mut Self::set_copy_pointer("R13", "SP", 0));
+        for _ in 0..Self::CALL_FRAME_SIZE {
+            asm.append(&mut Self::copy_r14_to_r13());
+        }
+        // Args, SP - nargs up to SP - 1, to ARG, R14 then ends up at the saved frame
+        asm.append(&mut Self::set_copy_pointer("R14", "SP", nargs));
+        asm.append(&mut Self::set_copy_pointer("R13", "ARG", 0));
+        for _ in 0..nargs {
+            asm.append(&mut Self::copy_r14_to_r13());
+        }
+        // Saved frame right after the args, like call would have pushed it
+        for _ in 0..Self::CALL_FRAME_SIZE {
+            asm.append(&mut Self::copy_r14_to_r13());
+        }
+        asm.append(&mut vec![
+            // ARG stays, LCL = SP = ARG + nargs + 5
+            Instruction::symbol("R13"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("LCL"),
+            Instruction::assign(Dest::M, Comp::D),
+            sp(),
+            Instruction::assign(Dest::M, Comp::D),
+            Instruction::symbol(function_name),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+        ]);
+        asm
+    }
+    pub fn function(&self, function_name: &String, nargs: usize) -> Vec<Instruction> {
         let mut asm = vec![
-            format!("//function {} {}", function_name, nargs),
-            format!("({})", function_name),
+            Instruction::comment(&format!("function {} {}", function_name, nargs)),
+            Instruction::label(function_name),
         ];
         for _ in 0..nargs {
             asm.append(&mut vec![
-                SP.to_string(),
-                String::from("AM=M+1"),
-                String::from("A=A-1"),
-                String::from("M=0"),
+                sp(),
+                Instruction::assign(Dest::Am, Comp::MPlusOne),
+                Instruction::assign(Dest::A, Comp::AMinusOne),
+                Instruction::assign(Dest::M, Comp::Zero),
             ]);
         }
+        if nargs > 0 {
+            asm.append(&mut self.check_stack());
+        }
         asm
     }
-    pub fn f_return() -> Vec<String> {
+    // Runtime checks, empty if they are not turned on
+    fn check_stack(&self) -> Vec<Instruction> {
+        self.check_stack_above(0)
+    }
+    /// Checks that SP + above is not past the stack limit, for words written above SP
+    fn check_stack_above(&self, above: usize) -> Vec<Instruction> {
+        let runtime_checks = match &self.runtime_checks {
+            Some(runtime_checks) => runtime_checks,
+            None => return Vec::default(),
+        };
         vec![
-            String::from("//return"),
-            String::from("@5"),
-            String::from("D=A"),
-            String::from("@LCL"),
-            String::from("A=M-D // LCL - 5, call_address"),
-            String::from("D=M // D = *call_address"),
-            SP.to_string(),
-            String::from("A=M"),
-            String::from("M=D // *SP = *call_address"),
-            String::from("A=A-1 // rtr_value addr"),
-            String::from("D=M // D = rtr_value"),
-            String::from("@ARG"),
-            String::from("A=M // ARG"),
-            String::from("M=D // *ARG = rtr_value"),
-            String::from("D=A // D = ARG"),
-            SP.to_string(),
-            String::from("A=M+1 // SP++"),
-            String::from("M=D // *SP++ = ARG"),
-            String::from("@LCL"),
-            String::from("AM=M-1 // LCL - 1"),
-            String::from("D=M // D = THAT"),
-            String::from("@THAT"),
-            String::from("M=D // set THAT"),
-            String::from("@LCL"),
-            String::from("AM=M-1 // LCL - 2"),
-            String::from("D=M // D = THIS"),
-            String::from("@THIS"),
-            String::from("M=D // set THIS"),
-            String::from("@LCL"),
-            String::from("AM=M-1 // LCL - 3"),
-            String::from("D=M // D = ARG"),
-            String::from("@ARG"),
-            String::from("M=D // set ARG"),
-            String::from("@LCL // LCL - 3"),
-            String::from("AM=M-1 // LCL - 4"),
-            String::from("D=M // D = LCL"),
-            String::from("@LCL"),
-            String::from("M=D // set LCL"),
-            SP.to_string(),
-            String::from("A=M+1 // SP++ = ARG"),
-            String::from("D=M // D = ARG"),
-            String::from("D=D+A // D = ARG + 1 + SP"),
-            SP.to_string(),
-            // Cursed ASM
-            String::from("M=D-M // M = ARG + 1 + SP - SP"),
-            String::from("A=D-M // A = SP + ARG + 1 - ARG - 1"),
-            String::from("A=M // A = *call_address"),
-            String::from("0;JMP"),
+            sp(),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::value(runtime_checks.stack_limit.saturating_sub(above)),
+            Instruction::assign(Dest::D, Comp::DMinusA), // SP + above - stack_limit
+            Instruction::symbol("__STACK_OVERFLOW"),
+            Instruction::jump(Comp::D, Jump::Jgt),
+        ]
+    }
+    /// Checks that THAT + i is in RAM
+    fn check_that_i(&self, i: usize) -> Vec<Instruction> {
+        if self.runtime_checks.is_none() {
+            return Vec::default();
+        }
+        let mut asm = vec![
+            Instruction::symbol("THAT"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::value(i),
+            Instruction::assign(Dest::D, Comp::DPlusA), // THAT + i
+        ];
+        asm.append(&mut self.check_address_in_d());
+        asm
+    }
+    /// Checks that the address in D is in RAM, D is not kept
+    fn check_address_in_d(&self) -> Vec<Instruction> {
+        if self.runtime_checks.is_none() {
+            return Vec::default();
+        }
+        vec![
+            Instruction::symbol("__SEGMENT_FAULT"),
+            Instruction::jump(Comp::D, Jump::Jlt),
+            Instruction::value(RuntimeChecks::RAM_END),
+            Instruction::assign(Dest::D, Comp::DMinusA), // D - RAM_END
+            Instruction::symbol("__SEGMENT_FAULT"),
+            Instruction::jump(Comp::D, Jump::Jgt),
+        ]
+    }
+    /// Emitted once per program when runtime checks are on, the jump targets of the checks
+    pub fn runtime_check_handlers(&self) -> Vec<Instruction> {
+        let runtime_checks = match &self.runtime_checks {
+            Some(runtime_checks) => runtime_checks,
+            None => return Vec::default(),
+        };
+        vec![
+            Instruction::comment("runtime check handlers"),
+            Instruction::label("__STACK_OVERFLOW"),
+            Instruction::value(RuntimeChecks::STACK_OVERFLOW),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol("__RUNTIME_ERROR"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__SEGMENT_FAULT"),
+            Instruction::value(RuntimeChecks::SEGMENT_FAULT),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol("__RUNTIME_ERROR"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__DIVIDE_BY_ZERO"),
+            Instruction::value(RuntimeChecks::DIVIDE_BY_ZERO),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::label("__RUNTIME_ERROR"),
+            Instruction::value(runtime_checks.error_address),
+            Instruction::assign(Dest::M, Comp::D), // error code
+            Instruction::label("__RUNTIME_ERROR.halt"),
+            Instruction::symbol("__RUNTIME_ERROR.halt"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+        ]
+    }
+    /// Extended math command, pops y and x, pushes x op y
+    /// Jumps to the shared routine from CodeGen::math_routines, which returns the result in D
+    pub fn math(&mut self, file_name: &String, command: &str) -> Vec<Instruction> {
+        let routine = match command {
+            "mul" => "__MUL",
+            "div" | "mod" => "__DIVMOD",
+            "shl" => "__SHL",
+            "shr" => "__SHR",
+            _ => panic!("Unexpected math command, {}", command),
+        };
+        self.math_counter += 1;
+        let return_label = format!("{}.{}.return.{}", file_name, command, self.math_counter - 1);
+        let mut asm = vec![Instruction::comment(command)];
+        asm.append(&mut Self::sp_minus_1_load_d()); // D = y
+        asm.append(&mut vec![
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::M, Comp::D),
+            sp(),
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::D, Comp::M), // D = x
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::M, Comp::D),
+            Instruction::symbol(&return_label),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol("__MATH.return"),
+            Instruction::assign(Dest::M, Comp::D),
+            Instruction::symbol(routine),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label(&return_label),
+        ]);
+        if command == "mod" {
+            asm.append(&mut vec![
+                Instruction::symbol("__MATH.r"),
+                Instruction::assign(Dest::D, Comp::M),
+            ]);
+        }
+        asm.append(&mut vec![
+            sp(),
+            Instruction::assign(Dest::A, Comp::MMinusOne),
+            Instruction::assign(Dest::M, Comp::D), // *SP-- = x op y
+        ]);
+        asm
+    }
+    /// Routines behind CodeGen::math, emitted once per program
+    /// They use their own asm variables, MATH_VARIABLES
+    ///
+    /// - mul wraps around like add
+    /// - div truncates towards 0, mod has the sign of x, so x = (x div y) * y + x mod y
+    /// - shl and shr shift x by y bits, shr keeps the sign, y > 15 shifts every bit out
+    pub fn math_routines(&self) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::comment("math routines")];
+        asm.append(&mut Self::mul_routine());
+        asm.append(&mut self.divmod_routine());
+        asm.append(&mut Self::shl_routine());
+        asm.append(&mut Self::shr_routine());
+        asm
+    }
+    fn math_return() -> Vec<Instruction> {
+        vec![
+            Instruction::symbol("__MATH.return"),
+            Instruction::assign(Dest::A, Comp::M),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+        ]
+    }
+    /// M = M + M of the variable, D is the old value
+    fn double(variable: &str) -> Vec<Instruction> {
+        vec![
+            Instruction::symbol(variable),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::assign(Dest::M, Comp::DPlusM),
         ]
     }
+    fn mul_routine() -> Vec<Instruction> {
+        // Shift and add, result += x for every bit of y, doubling x along the way
+        let mut asm = vec![
+            Instruction::label("__MUL"),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::Zero),
+            Instruction::symbol("__MATH.mask"),
+            Instruction::assign(Dest::M, Comp::One),
+            Instruction::label("__MUL.loop"),
+            Instruction::symbol("__MATH.mask"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__MUL.end"),
+            Instruction::jump(Comp::D, Jump::Jeq), // mask shifted out of the word
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::DAndM),
+            Instruction::symbol("__MUL.skip"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::DPlusM),
+            Instruction::label("__MUL.skip"),
+        ];
+        asm.append(&mut Self::double("__MATH.x"));
+        asm.append(&mut Self::double("__MATH.mask"));
+        asm.append(&mut vec![
+            Instruction::symbol("__MUL.loop"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__MUL.end"),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::D, Comp::M),
+        ]);
+        asm.append(&mut Self::math_return());
+        asm
+    }
+    fn divmod_routine(&self) -> Vec<Instruction> {
+        let mut asm = vec![Instruction::label("__DIVMOD")];
+        if self.runtime_checks.is_some() {
+            asm.append(&mut vec![
+                Instruction::symbol("__MATH.y"),
+                Instruction::assign(Dest::D, Comp::M),
+                Instruction::symbol("__DIVIDE_BY_ZERO"),
+                Instruction::jump(Comp::D, Jump::Jeq),
+            ]);
+        }
+        asm.append(&mut vec![
+            // Divide the magnitudes, sign is -1 when x < 0 and qsign when the signs differ
+            Instruction::symbol("__MATH.sign"),
+            Instruction::assign(Dest::M, Comp::Zero),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.x_positive"),
+            Instruction::jump(Comp::D, Jump::Jge),
+            Instruction::symbol("__MATH.x"),
+            // -32768 stays the same word, which is 32768 when read unsigned
+            Instruction::assign(Dest::M, Comp::NegM),
+            Instruction::symbol("__MATH.sign"),
+            Instruction::assign(Dest::M, Comp::MinusOne),
+            Instruction::label("__DIVMOD.x_positive"),
+            Instruction::symbol("__MATH.sign"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__MATH.qsign"),
+            Instruction::assign(Dest::M, Comp::D),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.y_positive"),
+            Instruction::jump(Comp::D, Jump::Jge),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::M, Comp::NegM),
+            Instruction::symbol("__MATH.qsign"),
+            Instruction::assign(Dest::M, Comp::NotM),
+            Instruction::label("__DIVMOD.y_positive"),
+            // Long division, one bit of x at a time from the top
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::Zero),
+            Instruction::symbol("__MATH.r"),
+            Instruction::assign(Dest::M, Comp::Zero),
+            Instruction::value(16),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol("__MATH.count"),
+            Instruction::assign(Dest::M, Comp::D),
+            Instruction::label("__DIVMOD.loop"),
+        ]);
+        asm.append(&mut Self::double("__MATH.r"));
+        asm.append(&mut vec![
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.bit_zero"),
+            Instruction::jump(Comp::D, Jump::Jge),
+            Instruction::symbol("__MATH.r"),
+            Instruction::assign(Dest::M, Comp::MPlusOne), // top bit of x into r
+            Instruction::label("__DIVMOD.bit_zero"),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::M, Comp::DPlusM), // D is still x
+        ]);
+        asm.append(&mut Self::double("__MATH.result"));
+        asm.append(&mut vec![
+            // Unsigned r >= y, r < y <= 32768 before doubling
+            // so r past 32767 is always >= y, otherwise r - y does not overflow, even for y = 32768
+            Instruction::symbol("__MATH.r"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.subtract"),
+            Instruction::jump(Comp::D, Jump::Jlt),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::DMinusM),
+            Instruction::symbol("__DIVMOD.next"),
+            Instruction::jump(Comp::D, Jump::Jlt),
+            Instruction::label("__DIVMOD.subtract"),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__MATH.r"),
+            Instruction::assign(Dest::M, Comp::MMinusD),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::MPlusOne),
+            Instruction::label("__DIVMOD.next"),
+            Instruction::symbol("__MATH.count"),
+            Instruction::assign(Dest::Md, Comp::MMinusOne),
+            Instruction::symbol("__DIVMOD.loop"),
+            Instruction::jump(Comp::D, Jump::Jgt),
+            // Signs
+            Instruction::symbol("__MATH.qsign"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.q_positive"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::NegM),
+            Instruction::label("__DIVMOD.q_positive"),
+            Instruction::symbol("__MATH.sign"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__DIVMOD.r_positive"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.r"),
+            Instruction::assign(Dest::M, Comp::NegM),
+            Instruction::label("__DIVMOD.r_positive"),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::D, Comp::M),
+        ]);
+        asm.append(&mut Self::math_return());
+        asm
+    }
+    fn shl_routine() -> Vec<Instruction> {
+        let mut asm = vec![
+            Instruction::label("__SHL"),
+            Instruction::label("__SHL.loop"),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHL.end"),
+            Instruction::jump(Comp::D, Jump::Jle),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::M, Comp::DMinusOne),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHL.end"),
+            Instruction::jump(Comp::D, Jump::Jeq), // every bit is already shifted out
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::M, Comp::DPlusM),
+            Instruction::symbol("__SHL.loop"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__SHL.end"),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+        ];
+        asm.append(&mut Self::math_return());
+        asm
+    }
+    fn shr_routine() -> Vec<Instruction> {
+        // Hack can not shift right, so bit y of x is copied to bit 0 of the result and so on
+        let mut asm = vec![
+            Instruction::label("__SHR"),
+            Instruction::symbol("__MATH.mask"),
+            Instruction::assign(Dest::M, Comp::One),
+            Instruction::label("__SHR.mask_loop"),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHR.masked"),
+            Instruction::jump(Comp::D, Jump::Jle),
+            Instruction::symbol("__MATH.y"),
+            Instruction::assign(Dest::M, Comp::DMinusOne),
+            Instruction::symbol("__MATH.mask"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::assign(Dest::Md, Comp::DPlusM), // mask = 1 << y
+            Instruction::symbol("__SHR.masked"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__SHR.mask_loop"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__SHR.masked"),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::Zero),
+            Instruction::symbol("__MATH.bit"),
+            Instruction::assign(Dest::M, Comp::One),
+            Instruction::label("__SHR.loop"),
+            Instruction::symbol("__MATH.mask"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHR.fill"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::DAndM),
+            Instruction::symbol("__SHR.skip"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.bit"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::DOrM),
+            Instruction::label("__SHR.skip"),
+        ];
+        asm.append(&mut Self::double("__MATH.mask"));
+        asm.append(&mut Self::double("__MATH.bit"));
+        asm.append(&mut vec![
+            Instruction::symbol("__SHR.loop"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            // Sign extend, bit is the first bit of the result past the copied ones
+            Instruction::label("__SHR.fill"),
+            Instruction::symbol("__MATH.x"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHR.end"),
+            Instruction::jump(Comp::D, Jump::Jge),
+            Instruction::label("__SHR.fill_loop"),
+            Instruction::symbol("__MATH.bit"),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol("__SHR.end"),
+            Instruction::jump(Comp::D, Jump::Jeq),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::M, Comp::DOrM),
+        ]);
+        asm.append(&mut Self::double("__MATH.bit"));
+        asm.append(&mut vec![
+            Instruction::symbol("__SHR.fill_loop"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+            Instruction::label("__SHR.end"),
+            Instruction::symbol("__MATH.result"),
+            Instruction::assign(Dest::D, Comp::M),
+        ]);
+        asm.append(&mut Self::math_return());
+        asm
+    }
+    /// Restores pointer from the frame, LCL is decremented to walk down the frame
+    fn restore_frame_pointer(pointer: &str) -> Vec<Instruction> {
+        vec![
+            Instruction::symbol("LCL"),
+            Instruction::assign(Dest::Am, Comp::MMinusOne),
+            Instruction::assign(Dest::D, Comp::M),
+            Instruction::symbol(pointer),
+            Instruction::assign(Dest::M, Comp::D), // set pointer
+        ]
+    }
+    pub fn f_return() -> Vec<Instruction> {
+        let mut asm = vec![
+            Instruction::comment("return"),
+            Instruction::value(Self::CALL_FRAME_SIZE),
+            Instruction::assign(Dest::D, Comp::A),
+            Instruction::symbol("LCL"),
+            Instruction::assign(Dest::A, Comp::MMinusD), // LCL - 5, call_address
+            Instruction::assign(Dest::D, Comp::M),       // D = *call_address
+            sp(),
+            Instruction::assign(Dest::A, Comp::M),
+            Instruction::assign(Dest::M, Comp::D), // *SP = *call_address
+            Instruction::assign(Dest::A, Comp::AMinusOne), // rtr_value addr
+            Instruction::assign(Dest::D, Comp::M), // D = rtr_value
+            Instruction::symbol("ARG"),
+            Instruction::assign(Dest::A, Comp::M), // ARG
+            Instruction::assign(Dest::M, Comp::D), // *ARG = rtr_value
+            Instruction::assign(Dest::D, Comp::A), // D = ARG
+            sp(),
+            Instruction::assign(Dest::A, Comp::MPlusOne), // SP++
+            Instruction::assign(Dest::M, Comp::D),        // *SP++ = ARG
+        ];
+        // LCL - 1 to LCL - 4
+        for pointer in ["THAT", "THIS", "ARG", "LCL"] {
+            asm.append(&mut Self::restore_frame_pointer(pointer));
+        }
+        asm.append(&mut vec![
+            sp(),
+            Instruction::assign(Dest::A, Comp::MPlusOne), // SP++ = ARG
+            Instruction::assign(Dest::D, Comp::M),        // D = ARG
+            Instruction::assign(Dest::D, Comp::DPlusA),   // D = ARG + 1 + SP
+            sp(),
+            // Cursed ASM
+            Instruction::assign(Dest::M, Comp::DMinusM), // M = ARG + 1 + SP - SP
+            Instruction::assign(Dest::A, Comp::DMinusM), // A = SP + ARG + 1 - ARG - 1
+            Instruction::assign(Dest::A, Comp::M),       // A = *call_address
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+        ]);
+        asm
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use asm2hack::{emulator::Emulator, simple::Simple};
     use std::{fs, path::PathBuf};
 
-    fn load_asm_file_to_vec(file: &str) -> Vec<String> {
+    fn load_asm_file_to_vec(file: &str) -> Vec<Instruction> {
         let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         file_path.push(format!("tests/code_gen/{}", file));
         let source = fs::read_to_string(file_path).unwrap();
@@ -443,8 +1049,8 @@ mod tests {
             .split("\n")
             .map(|s| s.trim())
             .filter(|s| !s.is_empty())
-            .map(str::to_string)
-            .collect::<Vec<String>>()
+            .map(|s| Instruction::parse(s).unwrap())
+            .collect::<Vec<Instruction>>()
     }
 
     #[test]
@@ -462,46 +1068,96 @@ mod tests {
     #[test]
     fn push_local_3() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::Local, 3),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Local, 3),
             load_asm_file_to_vec("push_local_3.asm")
         );
     }
     #[test]
     fn push_that_4() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::That, 4),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::That, 4),
             load_asm_file_to_vec("push_that_4.asm")
         );
     }
     #[test]
     fn push_constant_4() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::Constant, 4),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Constant, 4),
             load_asm_file_to_vec("push_constant_4.asm")
         );
     }
     #[test]
+    fn push_constant_negative() {
+        assert_eq!(
+            CodeGen::default().push_constant(-1),
+            load_asm_file_to_vec("push_constant_-1.asm")
+        );
+        assert_eq!(
+            CodeGen::default().push_constant(4),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Constant, 4)
+        );
+    }
+    #[test]
+    fn push_constant_cpu() {
+        let values = [0, 1, 32767, 32768, 40000, 65535, -1, -2, -32767, -32768];
+        for value in values {
+            let code_gen = CodeGen::default();
+            let mut asm = code_gen.bootstrap();
+            asm.append(&mut code_gen.push_constant(value));
+            let (rom, _) = Simple::assemble_with_source_lines(&asm, 16).unwrap();
+            let mut emulator = Emulator::new(&rom);
+            emulator.run(100);
+            assert_eq!(emulator.ram[0], 257);
+            assert_eq!(emulator.ram[256], value as u16, "push constant {}", value);
+        }
+    }
+    #[test]
+    #[should_panic]
+    fn push_constant_too_large() {
+        CodeGen::default().push_constant(65536);
+    }
+    #[test]
     fn push_f_static_4() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::Static, 4),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Static, 4),
             load_asm_file_to_vec("push_f_static_4.asm")
         );
     }
     #[test]
     fn push_pointer_1() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::Pointer, 1),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Pointer, 1),
             load_asm_file_to_vec("push_pointer_1.asm")
         );
     }
     #[test]
     fn push_temp_3() {
         assert_eq!(
-            CodeGen::push_segment(&String::from("f"), MemorySegments::Temp, 3),
+            CodeGen::default().push_segment(&String::from("f"), MemorySegments::Temp, 3),
             load_asm_file_to_vec("push_temp_3.asm")
         );
     }
     #[test]
+    fn push_temp_3_layout() {
+        let code_gen = CodeGen::new(MemoryLayout {
+            temp_base: 100,
+            ..MemoryLayout::default()
+        });
+        let asm = code_gen.push_segment(&String::from("f"), MemorySegments::Temp, 3);
+        assert_eq!(asm[1], Instruction::value(103));
+        let asm = code_gen.pop_segment(&String::from("f"), MemorySegments::Temp, 3);
+        assert_eq!(asm[4], Instruction::value(103));
+    }
+    #[test]
+    fn bootstrap_layout() {
+        assert_eq!(CodeGen::default().bootstrap()[0], Instruction::value(256));
+        let code_gen = CodeGen::new(MemoryLayout {
+            stack_base: 512,
+            ..MemoryLayout::default()
+        });
+        assert_eq!(code_gen.bootstrap()[0], Instruction::value(512));
+    }
+    #[test]
     fn eq_f_0() {
         let mut code_gen = CodeGen::default();
         assert_eq!(
@@ -534,33 +1190,33 @@ mod tests {
     #[test]
     fn pop_temp_3() {
         assert_eq!(
-            CodeGen::pop_segment(&String::from("f"), MemorySegments::Temp, 3),
+            CodeGen::default().pop_segment(&String::from("f"), MemorySegments::Temp, 3),
             load_asm_file_to_vec("pop_temp_3.asm")
         );
     }
     #[test]
     fn pop_f_static_4() {
         assert_eq!(
-            CodeGen::pop_segment(&String::from("f"), MemorySegments::Static, 4),
+            CodeGen::default().pop_segment(&String::from("f"), MemorySegments::Static, 4),
             load_asm_file_to_vec("pop_f_static_4.asm")
         );
     }
     #[test]
     fn pop_pointer_1() {
         assert_eq!(
-            CodeGen::pop_segment(&String::from("f"), MemorySegments::Pointer, 1),
+            CodeGen::default().pop_segment(&String::from("f"), MemorySegments::Pointer, 1),
             load_asm_file_to_vec("pop_pointer_1.asm")
         );
     }
     #[test]
     #[should_panic]
     fn pop_constant_3() {
-        CodeGen::pop_segment(&String::from("f"), MemorySegments::Constant, 3);
+        CodeGen::default().pop_segment(&String::from("f"), MemorySegments::Constant, 3);
     }
     #[test]
     fn pop_local_3() {
         assert_eq!(
-            CodeGen::pop_segment(&String::from("f"), MemorySegments::Local, 3),
+            CodeGen::default().pop_segment(&String::from("f"), MemorySegments::Local, 3),
             load_asm_file_to_vec("pop_local_3.asm")
         );
     }
@@ -577,6 +1233,16 @@ mod tests {
         );
     }
     #[test]
+    fn inline_asm() {
+        let instructions = ["(LOOP)", "@LOOP", "M=M+1", "@SP", "@END", "0;JMP"]
+            .map(|instruction| Instruction::parse(instruction).unwrap());
+        let labels = BTreeSet::from([String::from("LOOP"), String::from("END")]);
+        assert_eq!(
+            CodeGen::inline_asm(&String::from("Main.main"), &instructions, &labels),
+            load_asm_file_to_vec("inline_asm_Main_main.asm")
+        );
+    }
+    #[test]
     fn label_wow() {
         assert_eq!(
             CodeGen::label(&String::new(), &String::from("wow")),
@@ -620,11 +1286,149 @@ mod tests {
     #[allow(non_snake_case)]
     fn function_Main_main_3() {
         assert_eq!(
-            CodeGen::function(&String::from("Main.main"), 3),
+            CodeGen::default().function(&String::from("Main.main"), 3),
             load_asm_file_to_vec("function_Main_main_3.asm")
         );
     }
     #[test]
+    fn runtime_checks_off() {
+        let code_gen = CodeGen::default();
+        assert!(code_gen.runtime_check_handlers().is_empty());
+        assert!(!code_gen
+            .push_segment(&String::from("f"), MemorySegments::That, 4)
+            .iter()
+            .any(|instruction| instruction.to_string().contains("__")));
+    }
+    #[test]
+    fn runtime_check_handlers() {
+        let code_gen = CodeGen::default().with_runtime_checks(RuntimeChecks::default());
+        assert_eq!(
+            code_gen.runtime_check_handlers(),
+            load_asm_file_to_vec("runtime_check_handlers.asm")
+        );
+    }
+    #[test]
+    fn push_that_4_runtime_checks() {
+        let code_gen = CodeGen::default().with_runtime_checks(RuntimeChecks {
+            stack_limit: 1000,
+            error_address: 3000,
+        });
+        assert_eq!(
+            code_gen.push_segment(&String::from("f"), MemorySegments::That, 4),
+            load_asm_file_to_vec("push_that_4_runtime_checks.asm")
+        );
+    }
+    #[test]
+    fn pop_pointer_1_runtime_checks() {
+        let code_gen = CodeGen::default().with_runtime_checks(RuntimeChecks::default());
+        let asm = code_gen.pop_segment(&String::from("f"), MemorySegments::Pointer, 1);
+        assert_eq!(asm[asm.len() - 5], Instruction::jump(Comp::D, Jump::Jlt));
+        assert_eq!(asm.last().unwrap(), &Instruction::jump(Comp::D, Jump::Jgt));
+        assert_eq!(asm[asm.len() - 2], Instruction::symbol("__SEGMENT_FAULT"));
+    }
+    #[test]
+    #[allow(non_snake_case)]
+    fn call_and_function_runtime_checks() {
+        let mut code_gen = CodeGen::default().with_runtime_checks(RuntimeChecks::default());
+        let call = code_gen.call(&String::from("f"), &String::from("Main.main"), 3);
+        assert!(call.contains(&Instruction::symbol("__STACK_OVERFLOW")));
+        let function = code_gen.function(&String::from("Main.main"), 3);
+        assert_eq!(
+            function[function.len() - 2],
+            Instruction::symbol("__STACK_OVERFLOW")
+        );
+        // No locals pushed, so nothing to check
+        let function = code_gen.function(&String::from("Main.main"), 0);
+        assert_eq!(function.len(), 2);
+        // The saved frame is copied above SP before anything is pushed
+        let tail_call = code_gen.tail_call(&String::from("Main.main"), 1);
+        assert_eq!(
+            tail_call[3],
+            Instruction::value(RuntimeChecks::default().stack_limit - 5)
+        );
+        assert_eq!(tail_call[5], Instruction::symbol("__STACK_OVERFLOW"));
+        assert!(!CodeGen::default()
+            .tail_call(&String::from("Main.main"), 1)
+            .contains(&Instruction::symbol("__STACK_OVERFLOW")));
+    }
+    /// Runs x command y through the math routines on the emulator
+    fn run_math(command: &str, x: i16, y: i16, runtime_checks: bool) -> (i16, u16) {
+        let mut code_gen = CodeGen::default();
+        if runtime_checks {
+            code_gen = code_gen.with_runtime_checks(RuntimeChecks::default());
+        }
+        let mut asm = code_gen.bootstrap();
+        asm.append(&mut code_gen.push_constant(x as i32));
+        asm.append(&mut code_gen.push_constant(y as i32));
+        asm.append(&mut code_gen.math(&String::from("f"), command));
+        asm.append(&mut vec![
+            Instruction::label("END"),
+            Instruction::symbol("END"),
+            Instruction::jump(Comp::Zero, Jump::Jmp),
+        ]);
+        asm.append(&mut code_gen.math_routines());
+        asm.append(&mut code_gen.runtime_check_handlers());
+        let (rom, _) = Simple::assemble_with_source_lines(&asm, 16).unwrap();
+        let mut emulator = Emulator::new(&rom);
+        assert!(
+            emulator.run(10000) < 10000,
+            "{} {} {} did not halt",
+            x,
+            command,
+            y
+        );
+        (emulator.ram_i16(256), emulator.ram[0])
+    }
+    #[test]
+    fn mul_cpu() {
+        let values: [i16; 9] = [0, 1, -1, 7, -13, 181, 255, 32767, -32768];
+        for x in values {
+            for y in values {
+                assert_eq!(run_math("mul", x, y, false), (x.wrapping_mul(y), 257));
+            }
+        }
+    }
+    #[test]
+    fn div_mod_cpu() {
+        let values: [i16; 10] = [0, 1, -1, 2, 7, -13, 181, 32767, -32767, -32768];
+        for x in values {
+            for y in values.iter().copied().filter(|y| *y != 0) {
+                assert_eq!(run_math("div", x, y, false).0, x.wrapping_div(y));
+                assert_eq!(run_math("mod", x, y, false).0, x.wrapping_rem(y));
+            }
+        }
+    }
+    #[test]
+    fn divide_by_zero_runtime_check() {
+        let mut code_gen = CodeGen::default().with_runtime_checks(RuntimeChecks::default());
+        let mut asm = code_gen.bootstrap();
+        asm.append(&mut code_gen.push_constant(1));
+        asm.append(&mut code_gen.push_constant(0));
+        asm.append(&mut code_gen.math(&String::from("f"), "div"));
+        asm.append(&mut code_gen.math_routines());
+        asm.append(&mut code_gen.runtime_check_handlers());
+        let (rom, _) = Simple::assemble_with_source_lines(&asm, 16).unwrap();
+        let mut emulator = Emulator::new(&rom);
+        emulator.run(10000);
+        assert_eq!(
+            emulator.ram[RuntimeChecks::default().error_address],
+            RuntimeChecks::DIVIDE_BY_ZERO as u16
+        );
+    }
+    #[test]
+    fn shift_cpu() {
+        let values: [i16; 6] = [0, 1, -1, 181, 32767, -32768];
+        for x in values {
+            for y in [0, 1, 3, 15, 16, 100] {
+                let shl = if y > 15 { 0 } else { x << y };
+                let shr = if y > 15 { x >> 15 } else { x >> y };
+                assert_eq!(run_math("shl", x, y, false).0, shl, "{} shl {}", x, y);
+                assert_eq!(run_math("shr", x, y, false).0, shr, "{} shr {}", x, y);
+            }
+        }
+        assert_eq!(run_math("shl", 5, -1, true).0, 5);
+    }
+    #[test]
     fn f_return() {
         assert_eq!(CodeGen::f_return(), load_asm_file_to_vec("return.asm"));
     }
@@ -1,41 +1,112 @@
-use crate::{CodeGen, LineSource, MemorySegments, Parser};
+use std::collections::BTreeSet;
+
+use asm2hack::{Address, Instruction, SimpleSymbolTable};
+
+use crate::{CodeGen, LineSource, MemoryLayout, MemorySegments, Parser, RuntimeChecks};
+
+/// Where an asm instruction came from in the .vm files
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub file_name: String,
+    pub line: usize,
+    pub function_name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompilerOptions {
+    pub layout: MemoryLayout,
+    // Emits stack overflow and segment checks, the handlers are emitted once per program by the caller
+    pub runtime_checks: Option<RuntimeChecks>,
+    // Accepts mul, div, mod, shl and shr, the routines are emitted once per program by the caller
+    pub math_commands: bool,
+    // call f n followed by return reuses the frame of the current function, see CodeGen::tail_call
+    pub tail_calls: bool,
+}
+
+pub struct CompilerOutput {
+    pub asm: Vec<Instruction>,
+    pub locations: Vec<SourceLocation>,  // Source of each asm line
+    pub statics: BTreeSet<usize>,        // static i used by the file
+    pub uses_math_routines: bool,        // CodeGen::math_routines have to be emitted
+    pub asm_variables: BTreeSet<String>, // Inline asm symbols that are neither labels nor predefined
+}
 
 pub struct Compiler {
     line_sources: Vec<LineSource>,
-    asm: Vec<String>, // Output of compiled asm
+    asm: Vec<Instruction>,          // Output of compiled asm
+    locations: Vec<SourceLocation>, // Source of each asm line
     file_name: String,
     had_error: bool,
     code_gen: CodeGen,
     current_function_name: String,
+    statics: BTreeSet<usize>,
+    uses_math_routines: bool,
+    asm_variables: BTreeSet<String>,
+    current: usize,                    // Index of the line source being compiled
+    function_labels: BTreeSet<String>, // VM and inline asm labels of the current function
+    options: CompilerOptions,
 }
 
 impl Compiler {
+    #[cfg(test)]
     fn new(source: String, file_name: String) -> Self {
+        Self::new_with_options(source, file_name, CompilerOptions::default())
+    }
+    fn new_with_options(source: String, file_name: String, options: CompilerOptions) -> Self {
         let line_sources = Parser::parse(source);
+        let mut code_gen = CodeGen::new(options.layout.clone());
+        if let Some(runtime_checks) = &options.runtime_checks {
+            code_gen = code_gen.with_runtime_checks(runtime_checks.clone());
+        }
+        let function_labels = Self::function_labels(&line_sources);
         Self {
             asm: Vec::default(),
+            locations: Vec::default(),
             line_sources,
             file_name,
             had_error: false,
-            code_gen: CodeGen::default(),
+            code_gen,
             current_function_name: String::default(),
+            statics: BTreeSet::default(),
+            uses_math_routines: false,
+            asm_variables: BTreeSet::default(),
+            current: 0,
+            function_labels,
+            options,
         }
     }
 
-    pub fn compile(source: String, file_name: String) -> Option<Vec<String>> {
-        let mut compiler = Self::new(source, file_name);
+    pub fn compile(source: String, file_name: String) -> Option<Vec<Instruction>> {
+        Self::compile_with_options(source, file_name, &CompilerOptions::default())
+            .map(|output| output.asm)
+    }
+    /// Same as compile, but also returns the SourceLocation of every asm line and the statics used
+    pub fn compile_with_options(
+        source: String,
+        file_name: String,
+        options: &CompilerOptions,
+    ) -> Option<CompilerOutput> {
+        let mut compiler = Self::new_with_options(source, file_name, options.clone());
         compiler.run();
         if compiler.had_error {
             None
         } else {
-            Some(compiler.asm)
+            Some(CompilerOutput {
+                asm: compiler.asm,
+                locations: compiler.locations,
+                statics: compiler.statics,
+                uses_math_routines: compiler.uses_math_routines,
+                asm_variables: compiler.asm_variables,
+            })
         }
     }
 
     fn run(&mut self) {
-        for line_source in self.line_sources.clone() {
+        for (i, line_source) in self.line_sources.clone().into_iter().enumerate() {
+            self.current = i;
             let tokens = &line_source.tokens;
             match tokens.len() {
+                _ if tokens[0] == "asm" => self.inline_asm(&line_source),
                 1 => self.single_command(&line_source),
                 2 => self.double_command(&line_source),
                 3 => self.triple_command(&line_source),
@@ -44,6 +115,13 @@ impl Compiler {
                     String::from("Unknown token length for command"),
                 ),
             }
+            // Everything emitted for this command came from this line
+            let location = SourceLocation {
+                file_name: self.file_name.clone(),
+                line: line_source.line,
+                function_name: self.current_function_name.clone(),
+            };
+            self.locations.resize(self.asm.len(), location);
         }
     }
 
@@ -61,6 +139,11 @@ impl Compiler {
             "or" => self.asm.append(&mut CodeGen::or()),
             "not" => self.asm.append(&mut CodeGen::not()),
             "return" => self.asm.append(&mut CodeGen::f_return()),
+            command if self.options.math_commands && CodeGen::MATH_COMMANDS.contains(&command) => {
+                self.uses_math_routines = true;
+                self.asm
+                    .append(&mut self.code_gen.math(&self.file_name, command));
+            }
             _ => self.error(
                 line_source.line,
                 format!("Unknown single command, {}", command),
@@ -112,30 +195,34 @@ impl Compiler {
         memory_segment: &MemorySegments,
         i: usize,
     ) -> bool {
+        let layout = &self.options.layout;
         match memory_segment {
-            MemorySegments::Temp => {
-                // i should only be 0 - 7
-                if i > 7 {
-                    self.error(
-                        line_source.line,
-                        format!("push temp i, i should be between 0-7 not {}", i),
-                    );
-                    true
-                } else {
-                    false
-                }
+            // i should only be 0 - 7, for the default layout
+            MemorySegments::Temp if i >= layout.temp_size => {
+                let msg = format!(
+                    "push temp i, i should be between 0-{} not {}",
+                    layout.temp_size - 1,
+                    i
+                );
+                self.error(line_source.line, msg);
+                true
             }
-            MemorySegments::Pointer => {
-                // Should only be 0 or 1
-                if i > 1 {
-                    self.error(
-                        line_source.line,
-                        format!("push pointer i, i should be 0 or 1, not {}", i),
-                    );
-                    true
-                } else {
-                    false
-                }
+            MemorySegments::Static if i >= layout.static_size() => {
+                let msg = format!(
+                    "push static i, i should be between 0-{} not {}",
+                    layout.static_size() - 1,
+                    i
+                );
+                self.error(line_source.line, msg);
+                true
+            }
+            // Should only be 0 or 1
+            MemorySegments::Pointer if i > 1 => {
+                self.error(
+                    line_source.line,
+                    format!("push pointer i, i should be 0 or 1, not {}", i),
+                );
+                true
             }
             _ => false, // The other memory segment types, no need to check
         }
@@ -158,14 +245,20 @@ impl Compiler {
         if self.push_pop_check_memory_segment_i(line_source, &memory_segment, i) {
             return;
         }
-        if let MemorySegments::Constant = memory_segment {
-            return self.error(line_source.line, String::from("Should not pop constant"));
+        match memory_segment {
+            MemorySegments::Constant => {
+                return self.error(line_source.line, String::from("Should not pop constant"));
+            }
+            MemorySegments::Static => {
+                self.statics.insert(i);
+            }
+            _ => {}
         }
-        self.asm.append(&mut CodeGen::pop_segment(
-            &self.file_name,
-            memory_segment,
-            i,
-        ));
+        self.asm.append(
+            &mut self
+                .code_gen
+                .pop_segment(&self.file_name, memory_segment, i),
+        );
     }
     fn push_segment(&mut self, line_source: &LineSource) {
         assert!(line_source.tokens.len() == 3);
@@ -173,6 +266,9 @@ impl Compiler {
             Ok(memory_segment) => memory_segment,
             Err(msg) => return self.error(line_source.line, msg),
         };
+        if let MemorySegments::Constant = memory_segment {
+            return self.push_constant(line_source);
+        }
         let i = match line_source.tokens[2].parse::<usize>() {
             Ok(i) => i,
             Err(_) => {
@@ -185,13 +281,39 @@ impl Compiler {
         if self.push_pop_check_memory_segment_i(line_source, &memory_segment, i) {
             return;
         }
-        self.asm.append(&mut CodeGen::push_segment(
-            &self.file_name,
-            memory_segment,
-            i,
-        ));
+        if let MemorySegments::Static = memory_segment {
+            self.statics.insert(i);
+        }
+        self.asm.append(
+            &mut self
+                .code_gen
+                .push_segment(&self.file_name, memory_segment, i),
+        );
+    }
+    fn push_constant(&mut self, line_source: &LineSource) {
+        let value = match line_source.tokens[2].parse::<i32>() {
+            Ok(value) if (CodeGen::CONSTANT_MIN..=CodeGen::CONSTANT_MAX).contains(&value) => value,
+            Ok(value) => {
+                return self.error(
+                    line_source.line,
+                    format!(
+                        "push constant i, i should be between {}-{} not {}",
+                        CodeGen::CONSTANT_MIN,
+                        CodeGen::CONSTANT_MAX,
+                        value
+                    ),
+                )
+            }
+            Err(_) => {
+                return self.error(
+                    line_source.line,
+                    format!("Unknown i at {}", line_source.tokens[2]),
+                )
+            }
+        };
+        self.asm.append(&mut self.code_gen.push_constant(value));
     }
-    fn is_valid_label(label: &String) -> bool {
+    fn is_valid_label(label: &str) -> bool {
         let label = label.chars().collect::<Vec<char>>();
         if label.is_empty() {
             return false;
@@ -224,8 +346,76 @@ impl Compiler {
             }
         };
         self.asm
-            .append(&mut CodeGen::function(&function_name, nargs));
+            .append(&mut self.code_gen.function(&function_name, nargs));
         self.current_function_name = function_name;
+        self.function_labels = Self::function_labels(&self.line_sources[self.current + 1..]);
+    }
+    /// Labels declared from the start of line_sources up to the next function
+    fn function_labels(line_sources: &[LineSource]) -> BTreeSet<String> {
+        let mut labels = BTreeSet::new();
+        for line_source in line_sources {
+            let tokens = &line_source.tokens;
+            match tokens[0].as_str() {
+                "function" => break,
+                "label" if tokens.len() == 2 => {
+                    labels.insert(tokens[1].clone());
+                }
+                "asm" => {
+                    for instruction in &tokens[1..] {
+                        if let Ok(Instruction::Label(label)) = Instruction::parse(instruction) {
+                            labels.insert(label);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        labels
+    }
+    fn inline_asm(&mut self, line_source: &LineSource) {
+        if line_source.tokens.len() == 1 {
+            return self.error(line_source.line, String::from("Empty inline asm"));
+        }
+        if line_source.tokens[1] == "{" {
+            return self.error(
+                line_source.line,
+                String::from("asm block is never closed with }"),
+            );
+        }
+        let predefined = SimpleSymbolTable::new().table;
+        let mut instructions = Vec::default();
+        for source in &line_source.tokens[1..] {
+            match Instruction::parse(source) {
+                // Same limit as the assembler, the A-Instruction has 15 bits for the value
+                Ok(Instruction::A(Address::Value(value))) if value > 32767 => {
+                    return self.error(
+                        line_source.line,
+                        format!("Invalid inline asm {}, value is above 32767", source),
+                    )
+                }
+                Ok(instruction) => {
+                    if let Instruction::A(Address::Symbol(symbol)) = &instruction {
+                        if !self.function_labels.contains(symbol)
+                            && !predefined.contains_key(symbol)
+                        {
+                            self.asm_variables.insert(symbol.clone());
+                        }
+                    }
+                    instructions.push(instruction)
+                }
+                Err(msg) => {
+                    return self.error(
+                        line_source.line,
+                        format!("Invalid inline asm {}, {}", source, msg),
+                    )
+                }
+            }
+        }
+        self.asm.append(&mut CodeGen::inline_asm(
+            &self.current_function_name,
+            &instructions,
+            &self.function_labels,
+        ));
     }
     fn call(&mut self, line_source: &LineSource) {
         assert!(line_source.tokens.len() == 3);
@@ -239,8 +429,18 @@ impl Compiler {
                 )
             }
         };
-        self.asm
-            .append(&mut self.code_gen.call(&self.file_name, &function_name, nargs));
+        let next_is_return = self
+            .line_sources
+            .get(self.current + 1)
+            .is_some_and(|next| next.tokens == ["return"]);
+        if self.options.tail_calls && next_is_return {
+            // The return is still compiled, but never reached
+            self.asm
+                .append(&mut self.code_gen.tail_call(&function_name, nargs));
+        } else {
+            self.asm
+                .append(&mut self.code_gen.call(&self.file_name, &function_name, nargs));
+        }
     }
 
     fn error(&mut self, line: usize, msg: String) {
@@ -258,6 +458,7 @@ impl Compiler {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use asm2hack::{emulator::Emulator, simple::Simple};
     #[test]
     fn error_quad_token() {
         let source = "wow very funny lol";
@@ -266,11 +467,23 @@ mod tests {
         assert!(compiler.had_error)
     }
     #[test]
-    fn error_neg_push() {
-        let source = "push constant -10";
+    fn neg_push() {
+        let source = "push constant -10\npush constant -32768\npush constant 40000";
         let mut compiler = Compiler::new(source.to_string(), "somefile".to_string());
         compiler.run();
-        assert!(compiler.had_error)
+        assert!(!compiler.had_error)
+    }
+    #[test]
+    fn error_push_constant_out_of_range() {
+        for source in [
+            "push constant -32769",
+            "push constant 65536",
+            "push constant 1.5",
+        ] {
+            let mut compiler = Compiler::new(source.to_string(), "somefile".to_string());
+            compiler.run();
+            assert!(compiler.had_error)
+        }
     }
     #[test]
     fn error_temp_large() {
@@ -306,7 +519,7 @@ mod tests {
     }
     #[test]
     fn valid_labels() {
-        let labels = vec!["_", ".", ":", "a2", ".2", "DRAW_REACT", "wow", "_2:no"];
+        let labels = ["_", ".", ":", "a2", ".2", "DRAW_REACT", "wow", "_2:no"];
         let labels = labels
             .iter()
             .map(|s| s.to_string())
@@ -317,7 +530,7 @@ mod tests {
     }
     #[test]
     fn invalid_labels() {
-        let labels = vec!["21", "2abc", "$wow"];
+        let labels = ["21", "2abc", "$wow"];
         let labels = labels
             .iter()
             .map(|s| s.to_string())
@@ -326,4 +539,203 @@ mod tests {
             assert!(!Compiler::is_valid_label(&label));
         }
     }
+    #[test]
+    fn locations() {
+        let source = "function Main.main 0\n\npush constant 1 // one\nreturn";
+        let output = Compiler::compile_with_options(
+            source.to_string(),
+            "Main".to_string(),
+            &CompilerOptions::default(),
+        )
+        .unwrap();
+        let (asm, locations) = (output.asm, output.locations);
+        assert_eq!(asm.len(), locations.len());
+        assert_eq!(locations[0].line, 1);
+        assert_eq!(locations[0].function_name, "Main.main");
+        let push_constant =
+            CodeGen::default().push_segment(&String::from("Main"), MemorySegments::Constant, 1);
+        let push_location = &locations[2];
+        assert_eq!(asm[2], push_constant[0]);
+        assert_eq!(push_location.line, 3);
+        assert_eq!(push_location.file_name, "Main");
+        assert_eq!(locations.last().unwrap().line, 4);
+    }
+    #[test]
+    fn statics() {
+        let source = "push static 3\npop static 0\npush static 3";
+        let output = Compiler::compile_with_options(
+            source.to_string(),
+            "Main".to_string(),
+            &CompilerOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.statics.into_iter().collect::<Vec<usize>>(),
+            vec![0, 3]
+        );
+    }
+    #[test]
+    fn layout() {
+        let options = CompilerOptions {
+            layout: MemoryLayout {
+                temp_base: 2000,
+                temp_size: 16,
+                static_end: 18,
+                heap_start: 1900,
+                ..MemoryLayout::default()
+            },
+            ..CompilerOptions::default()
+        };
+        let mut compiler = Compiler::new_with_options(
+            String::from("push temp 12"),
+            "somefile".to_string(),
+            options.clone(),
+        );
+        compiler.run();
+        assert!(!compiler.had_error);
+        assert_eq!(compiler.asm[1], Instruction::value(2012));
+
+        let mut compiler = Compiler::new_with_options(
+            String::from("push static 2"),
+            "somefile".to_string(),
+            options,
+        );
+        compiler.run();
+        assert!(compiler.had_error);
+    }
+    #[test]
+    fn math_commands() {
+        let source = "push constant 6\npush constant 7\nmul";
+        assert!(Compiler::compile(source.to_string(), "Main".to_string()).is_none());
+        let options = CompilerOptions {
+            math_commands: true,
+            ..CompilerOptions::default()
+        };
+        let output =
+            Compiler::compile_with_options(source.to_string(), "Main".to_string(), &options)
+                .unwrap();
+        assert!(output.uses_math_routines);
+        assert!(output.asm.contains(&Instruction::symbol("__MUL")));
+    }
+    /// Runs a whole program on the emulator, temp 0 is the result
+    fn run_program(source: &str, options: &CompilerOptions) -> Emulator {
+        let output =
+            Compiler::compile_with_options(source.to_string(), "Main".to_string(), options)
+                .unwrap();
+        let mut code_gen = CodeGen::new(options.layout.clone())
+            .with_runtime_checks(options.runtime_checks.clone().unwrap_or_default());
+        let mut asm = code_gen.bootstrap();
+        asm.append(&mut code_gen.call(&String::from("bootstrap"), &String::from("Sys.init"), 0));
+        asm.append(&mut output.asm.clone());
+        asm.append(&mut code_gen.runtime_check_handlers());
+        let (rom, _) = Simple::assemble_with_source_lines(&asm, 16).unwrap();
+        let mut emulator = Emulator::new(&rom);
+        assert!(emulator.run(10_000_000) < 10_000_000);
+        emulator
+    }
+    #[test]
+    fn tail_call_deep_recursion() {
+        // Main.count(n) returns 42 after n tail calls
+        let source = "function Sys.init 0\npush constant 5000\ncall Main.count 1\npop temp 0\nlabel END\ngoto END\nfunction Main.count 0\npush argument 0\nif-goto RECURSE\npush constant 42\nreturn\nlabel RECURSE\npush argument 0\npush constant 1\nsub\ncall Main.count 1\nreturn";
+        let options = CompilerOptions {
+            runtime_checks: Some(RuntimeChecks::default()),
+            ..CompilerOptions::default()
+        };
+        let emulator = run_program(source, &options);
+        let error_address = RuntimeChecks::default().error_address;
+        assert_eq!(
+            emulator.ram[error_address],
+            RuntimeChecks::STACK_OVERFLOW as u16
+        );
+
+        let options = CompilerOptions {
+            tail_calls: true,
+            ..options
+        };
+        let emulator = run_program(source, &options);
+        assert_eq!(emulator.ram[error_address], 0);
+        assert_eq!(emulator.ram[5], 42);
+        // Back to the stack of Sys.init, after popping the result
+        assert_eq!(emulator.ram[0], 261);
+    }
+    #[test]
+    fn tail_call_more_args() {
+        // Main.sum(n) tail calls Main.acc(n, 0, 1) which tail calls itself with 3 args and a local
+        let source = "function Sys.init 0\npush constant 7\npush constant 100\ncall Main.sum 2\npop temp 0\nlabel END\ngoto END\nfunction Main.sum 0\npush argument 0\npush constant 0\npush constant 1\ncall Main.acc 3\nreturn\nfunction Main.acc 1\npush argument 0\npop local 0\npush local 0\nif-goto RECURSE\npush argument 1\nreturn\nlabel RECURSE\npush local 0\npush constant 1\nsub\npush argument 1\npush local 0\nadd\npush argument 2\ncall Main.acc 3\nreturn";
+        for tail_calls in [false, true] {
+            let options = CompilerOptions {
+                tail_calls,
+                ..CompilerOptions::default()
+            };
+            let emulator = run_program(source, &options);
+            assert_eq!(emulator.ram[5], 28);
+            assert_eq!(emulator.ram[0], 261);
+            // THIS and THAT of the caller are restored
+            assert_eq!(emulator.ram[3], 0);
+        }
+    }
+    #[test]
+    fn inline_asm() {
+        // Main.fill(n) sets RAM[100] up to RAM[100 + n - 1] to -1 in asm
+        let source = "function Sys.init 0\npush constant 3\ncall Main.fill 1\npop temp 0\nlabel END\ngoto END\nfunction Main.fill 0\nasm {\n  @ARG\n  A=M\n  D=M\n  @DONE\n  D;JEQ\n  @R13\n  M=D\n  @100\n  D=A\n  @R14\n  M=D\n(LOOP)\n  @R14\n  AM=M+1\n  A=A-1\n  M=-1\n  @R13\n  MD=M-1\n  @LOOP\n  D;JGT\n}\nlabel DONE\nasm \"@LOOP\"\nasm \"D=A\"\npush constant 0\nreturn\nfunction Main.other 0\nlabel LOOP\ngoto LOOP";
+        let emulator = run_program(source, &CompilerOptions::default());
+        assert_eq!(&emulator.ram[99..104], &[0, 0xffff, 0xffff, 0xffff, 0]);
+
+        let output = Compiler::compile_with_options(
+            source.to_string(),
+            "Main".to_string(),
+            &CompilerOptions::default(),
+        )
+        .unwrap();
+        assert!(output.asm.contains(&Instruction::label("Main.fill$LOOP")));
+        assert!(output.asm.contains(&Instruction::symbol("Main.fill$DONE")));
+        assert!(output.asm.contains(&Instruction::label("Main.other$LOOP")));
+        // R13 is not a label of the function, so it stays as is
+        assert!(output.asm.contains(&Instruction::symbol("R13")));
+        assert!(output.asm_variables.is_empty());
+
+        let source = "function Main.main 0\nasm \"@counter\"\nasm \"M=M+1\"\nasm \"@SP\"\npush constant 0\nreturn";
+        let output = Compiler::compile_with_options(
+            source.to_string(),
+            "Main".to_string(),
+            &CompilerOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output.asm_variables.into_iter().collect::<Vec<String>>(),
+            vec![String::from("counter")]
+        );
+    }
+    #[test]
+    fn inline_asm_errors() {
+        let sources = [
+            "function Main.main 0\nasm \"D=X\"",
+            "function Main.main 0\nasm \"@40000\"",
+            "function Main.main 0\nasm \"\"",
+            "function Main.main 0\nasm {\n@SP\nM=M+1",
+            "function Main.main 0\nasm {\n(1LOOP)\n}",
+        ];
+        for source in sources {
+            let mut compiler = Compiler::new(source.to_string(), "Main".to_string());
+            compiler.run();
+            assert!(compiler.had_error, "{}", source);
+        }
+    }
+    #[test]
+    fn runtime_checks() {
+        let options = CompilerOptions {
+            runtime_checks: Some(RuntimeChecks::default()),
+            ..CompilerOptions::default()
+        };
+        let output = Compiler::compile_with_options(
+            String::from("function Main.main 0\npush constant 1\nreturn"),
+            "Main".to_string(),
+            &options,
+        )
+        .unwrap();
+        assert!(output
+            .asm
+            .contains(&Instruction::symbol("__STACK_OVERFLOW")));
+        assert_eq!(output.asm.len(), output.locations.len());
+    }
 }
@@ -1,7 +1,13 @@
 pub mod code_gen;
 pub mod compiler;
+pub mod memory_layout;
 pub mod parser;
+pub mod stack_analysis;
+pub mod validator;
 
-pub use code_gen::{CodeGen, MemorySegments};
-pub use compiler::Compiler;
+pub use code_gen::{CodeGen, MemorySegments, RuntimeChecks};
+pub use compiler::{Compiler, CompilerOptions, CompilerOutput, SourceLocation};
+pub use memory_layout::MemoryLayout;
 pub use parser::{LineSource, Parser};
+pub use stack_analysis::{FunctionStackUsage, StackAnalysis};
+pub use validator::{ValidationError, Validator, ValidatorOptions};
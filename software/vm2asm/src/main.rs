@@ -1,6 +1,7 @@
 use std::{env, fs, path::Path};
 
-use vm2asm::{CodeGen, Compiler};
+use asm2hack::instruction;
+use vm2asm::Compiler;
 
 type ProgResult = Result<(), String>;
 
@@ -45,9 +46,7 @@ fn compile_file(file_path: &str) -> ProgResult {
             let mut output_file =
                 output_dir.join(file_path.file_stem().expect("Should have a file stem"));
             output_file.set_extension("asm");
-            let mut asm = Vec::new();
-            asm.append(&mut assembly.clone());
-            fs::write(output_file, asm.join("\n")).map_err(|e| e.to_string())?
+            fs::write(output_file, instruction::render(&assembly)).map_err(|e| e.to_string())?
         }
         None => return Err(String::from("Failed to compile")),
     };
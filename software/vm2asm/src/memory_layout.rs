@@ -0,0 +1,153 @@
+/// Where the VM segments live in RAM
+/// Defaults to the standard Hack VM mapping
+///
+/// RAM[0-4] are always SP, LCL, ARG, THIS, THAT, as the asm symbols are fixed
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryLayout {
+    pub stack_base: usize,
+    pub temp_base: usize,
+    pub temp_size: usize,
+    // Static variables are allocated from static_start up to, but not including, static_end
+    pub static_start: usize,
+    pub static_end: usize,
+    // The stack grows up to, but not including, heap_start
+    pub heap_start: usize,
+}
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self {
+            stack_base: 256,
+            temp_base: 5,
+            temp_size: 8,
+            static_start: 16,
+            static_end: 256,
+            heap_start: 2048,
+        }
+    }
+}
+impl MemoryLayout {
+    /// Screen memory map starts here, nothing should be laid out past it
+    const SCREEN: usize = 16384;
+
+    pub fn static_size(&self) -> usize {
+        self.static_end.saturating_sub(self.static_start)
+    }
+    pub fn stack_size(&self) -> usize {
+        self.heap_start.saturating_sub(self.stack_base)
+    }
+
+    /// Checks that the regions are not empty and do not overlap each other
+    pub fn validate(&self) -> Result<(), String> {
+        let mut regions = [
+            ("temp", self.temp_base, self.temp_base + self.temp_size),
+            ("static", self.static_start, self.static_end),
+            ("stack", self.stack_base, self.heap_start),
+        ];
+        if self.temp_base < 5 {
+            return Err(format!(
+                "temp base {} overlaps SP, LCL, ARG, THIS, THAT at 0-4",
+                self.temp_base
+            ));
+        }
+        for (name, start, end) in &regions {
+            if start >= end {
+                return Err(format!("{} region {}-{} is empty", name, start, end));
+            }
+            if *end > Self::SCREEN {
+                return Err(format!(
+                    "{} region {}-{} goes into the screen at {}",
+                    name,
+                    start,
+                    end - 1,
+                    Self::SCREEN
+                ));
+            }
+        }
+        regions.sort_by_key(|(_, start, _)| *start);
+        for pair in regions.windows(2) {
+            let (name, start, end) = pair[0];
+            let (next_name, next_start, next_end) = pair[1];
+            if end > next_start {
+                return Err(format!(
+                    "{} region {}-{} overlaps {} region {}-{}",
+                    name,
+                    start,
+                    end - 1,
+                    next_name,
+                    next_start,
+                    next_end - 1
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that n_statics static variables, across all files, fit in the static region
+    pub fn check_statics(&self, n_statics: usize) -> Result<(), String> {
+        if n_statics > self.static_size() {
+            Err(format!(
+                "{} static variables do not fit in the static region {}-{}, which only has {}",
+                n_statics,
+                self.static_start,
+                self.static_end - 1,
+                self.static_size()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        let layout = MemoryLayout::default();
+        assert_eq!(layout.validate(), Ok(()));
+        assert_eq!(layout.static_size(), 240);
+        assert_eq!(layout.stack_size(), 1792);
+    }
+    #[test]
+    fn overlapping_regions() {
+        let layout = MemoryLayout {
+            stack_base: 200,
+            ..MemoryLayout::default()
+        };
+        assert_eq!(
+            layout.validate(),
+            Err(String::from(
+                "static region 16-255 overlaps stack region 200-2047"
+            ))
+        );
+        let layout = MemoryLayout {
+            temp_size: 20,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.validate().is_err());
+        let layout = MemoryLayout {
+            temp_base: 2,
+            ..MemoryLayout::default()
+        };
+        assert!(layout.validate().is_err());
+    }
+    #[test]
+    fn bigger_stack() {
+        let layout = MemoryLayout {
+            heap_start: 8192,
+            ..MemoryLayout::default()
+        };
+        assert_eq!(layout.validate(), Ok(()));
+        assert_eq!(layout.stack_size(), 7936);
+    }
+    #[test]
+    fn check_statics() {
+        let layout = MemoryLayout {
+            static_end: 20,
+            ..MemoryLayout::default()
+        };
+        assert_eq!(layout.check_statics(4), Ok(()));
+        assert!(layout.check_statics(5).is_err());
+    }
+}
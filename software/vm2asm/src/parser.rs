@@ -13,17 +13,60 @@ impl Parser {
             .map(str::to_string)
             .collect::<Vec<String>>();
         let mut line_sources: Vec<LineSource> = Vec::default();
+        // Inline asm block being collected, asm { up to }
+        let mut asm_block: Option<LineSource> = None;
         for (i, line) in source.into_iter().enumerate() {
-            if let Some(line) = Self::remove_whtiespace(line) {
-                line_sources.push(LineSource {
-                    tokens: Self::generate_tokens(line),
+            let line = match Self::remove_whtiespace(line) {
+                Some(line) => line,
+                None => continue,
+            };
+            if let Some(block) = asm_block.as_mut() {
+                if line == "}" {
+                    line_sources.extend(asm_block.take());
+                } else {
+                    block.tokens.push(line);
+                }
+                continue;
+            }
+            let tokens = Self::generate_tokens(line.clone());
+            if tokens == ["asm", "{"] {
+                asm_block = Some(LineSource {
+                    tokens: vec![String::from("asm")],
                     line: i + 1,
                 });
+                continue;
             }
+            line_sources.push(LineSource {
+                tokens: match line.strip_prefix("asm ") {
+                    Some(instruction) => Self::asm_directive(instruction),
+                    None => tokens,
+                },
+                line: i + 1,
+            });
+        }
+        // A block that is never closed keeps its {, which is not a valid instruction
+        if let Some(mut block) = asm_block {
+            block.tokens.insert(1, String::from("{"));
+            line_sources.push(block);
         }
         line_sources
     }
 
+    /// asm "instruction", becomes the tokens asm, instruction
+    /// Inline asm keeps an instruction per token, so the whitespace inside it is kept
+    fn asm_directive(instruction: &str) -> Vec<String> {
+        let instruction = instruction.trim();
+        let instruction = instruction
+            .strip_prefix('"')
+            .and_then(|instruction| instruction.strip_suffix('"'))
+            .unwrap_or(instruction);
+        let mut tokens = vec![String::from("asm")];
+        if !instruction.trim().is_empty() {
+            tokens.push(instruction.trim().to_string());
+        }
+        tokens
+    }
+
     fn generate_tokens(source: String) -> Vec<String> {
         source
             .split(' ')
@@ -120,4 +163,17 @@ mod tests {
         );
         assert_eq!(line_souces[2].tokens, vec!["add".to_string()]);
     }
+    #[test]
+    fn inline_asm() {
+        let source = "asm \"D = M\" // wow\nasm {\n  @SP //stack\n\n  (LOOP)\n}\nadd";
+        let line_sources = Parser::parse(source.to_string());
+        assert_eq!(line_sources.len(), 3);
+        assert_eq!(line_sources[0].tokens, vec!["asm", "D = M"]);
+        assert_eq!(line_sources[1].tokens, vec!["asm", "@SP", "(LOOP)"]);
+        assert_eq!(line_sources[1].line, 2);
+        assert_eq!(line_sources[2].tokens, vec!["add"]);
+
+        let line_sources = Parser::parse(String::from("asm {\n@SP"));
+        assert_eq!(line_sources[0].tokens, vec!["asm", "{", "@SP"]);
+    }
 }
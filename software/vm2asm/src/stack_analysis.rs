@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use crate::{CodeGen, LineSource, Parser, ValidationError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallDepth {
+    pub function_name: String,
+    // Stack depth right before the call, including the pushed args
+    pub depth: usize,
+}
+
+/// Stack usage of a single VM function
+/// depth is counted from the stack pointer after the locals are pushed by the function command
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionStackUsage {
+    pub function_name: String,
+    pub file_name: String,
+    pub n_locals: usize,
+    pub max_depth: usize,
+    pub calls: Vec<CallDepth>,
+}
+impl FunctionStackUsage {
+    /// Words used on the stack by the function itself, not counting its callees
+    pub fn frame_size(&self) -> usize {
+        self.n_locals + self.max_depth
+    }
+}
+
+/// Stack depth analysis over the control flow graph built from label/goto/if-goto of each function
+///
+/// Reports
+/// - stack underflows
+/// - labels reached with different stack depths
+/// - return with an empty stack
+pub struct StackAnalysis {
+    pub functions: Vec<FunctionStackUsage>,
+    pub errors: Vec<ValidationError>,
+}
+
+impl StackAnalysis {
+    /// files are (file_name, source) pairs, same as Validator::validate
+    pub fn analyse(files: &[(String, String)]) -> Self {
+        let mut analysis = Self {
+            functions: Vec::default(),
+            errors: Vec::default(),
+        };
+        for (file_name, source) in files {
+            let mut function: Option<(LineSource, Vec<LineSource>)> = None;
+            for line_source in Parser::parse(source.clone()) {
+                if line_source.tokens[0] == "function" {
+                    if let Some((function_source, body)) = function.take() {
+                        analysis.analyse_function(file_name, &function_source, &body);
+                    }
+                    function = Some((line_source, Vec::default()));
+                } else if let Some((_, body)) = function.as_mut() {
+                    // Statements outside of functions are reported by the Validator
+                    body.push(line_source);
+                }
+            }
+            if let Some((function_source, body)) = function {
+                analysis.analyse_function(file_name, &function_source, &body);
+            }
+        }
+        analysis
+    }
+
+    pub fn get(&self, function_name: &str) -> Option<&FunctionStackUsage> {
+        self.functions
+            .iter()
+            .find(|function| function.function_name == function_name)
+    }
+
+    /// Worst case words used on the stack by calling function_name, including everything it calls
+    /// Calls to functions outside of the analysed files are counted as only their call frame
+    /// None if function_name can recurse, as the stack usage is unbounded
+    pub fn worst_case(&self, function_name: &str) -> Option<usize> {
+        self.worst_case_with_path(function_name, &mut Vec::default())
+    }
+    fn worst_case_with_path(&self, function_name: &str, path: &mut Vec<String>) -> Option<usize> {
+        if path.iter().any(|f| f == function_name) {
+            return None;
+        }
+        let function = match self.get(function_name) {
+            Some(function) => function,
+            None => return Some(0),
+        };
+        path.push(function_name.to_string());
+        let mut worst = function.max_depth;
+        for call in &function.calls {
+            let callee = self.worst_case_with_path(&call.function_name, path)?;
+            worst = worst.max(call.depth + CodeGen::CALL_FRAME_SIZE + callee);
+        }
+        path.pop();
+        Some(function.n_locals + worst)
+    }
+
+    fn analyse_function(
+        &mut self,
+        file_name: &str,
+        function_source: &LineSource,
+        body: &[LineSource],
+    ) {
+        let tokens = &function_source.tokens;
+        if tokens.len() != 3 {
+            return;
+        }
+        let n_locals = tokens[2].parse::<usize>().unwrap_or(0);
+        let labels = body
+            .iter()
+            .enumerate()
+            .filter(|(_, line_source)| {
+                line_source.tokens.len() == 2 && line_source.tokens[0] == "label"
+            })
+            .map(|(i, line_source)| (line_source.tokens[1].clone(), i))
+            .collect::<HashMap<String, usize>>();
+
+        // Stack depth when entering each command, None if not reached yet
+        let mut depths: Vec<Option<usize>> = vec![None; body.len()];
+        let mut reported_merge = vec![false; body.len()];
+        let mut usage = FunctionStackUsage {
+            function_name: tokens[1].clone(),
+            file_name: file_name.to_string(),
+            n_locals,
+            max_depth: 0,
+            calls: Vec::default(),
+        };
+        let mut worklist: Vec<(usize, usize)> = vec![(0, 0)];
+        while let Some((i, depth)) = worklist.pop() {
+            if i >= body.len() {
+                // Fell off the end of the function
+                continue;
+            }
+            match depths[i] {
+                Some(existing) if existing != depth => {
+                    if !reported_merge[i] {
+                        reported_merge[i] = true;
+                        self.error(
+                            file_name,
+                            body[i].line,
+                            format!(
+                                "Inconsistent stack depth in {}, reached with {} and {}",
+                                usage.function_name, existing, depth
+                            ),
+                        );
+                    }
+                    continue;
+                }
+                Some(_) => continue,
+                None => depths[i] = Some(depth),
+            }
+
+            let line_source = &body[i];
+            let command = line_source.tokens[0].as_str();
+            let (pops, pushes) = Self::stack_effect(&line_source.tokens);
+            if command == "return" {
+                if depth == 0 {
+                    self.error(
+                        file_name,
+                        line_source.line,
+                        format!("return with an empty stack in {}", usage.function_name),
+                    );
+                }
+                continue;
+            }
+            if depth < pops {
+                self.error(
+                    file_name,
+                    line_source.line,
+                    format!(
+                        "Stack underflow in {}, {} needs {} values but the stack has {}",
+                        usage.function_name,
+                        line_source.tokens.join(" "),
+                        pops,
+                        depth
+                    ),
+                );
+            }
+            if command == "call" {
+                usage.calls.push(CallDepth {
+                    function_name: line_source.tokens[1].clone(),
+                    depth,
+                });
+            }
+            let next_depth = depth.max(pops) - pops + pushes;
+            usage.max_depth = usage.max_depth.max(next_depth);
+
+            match (command, line_source.tokens.len()) {
+                ("goto", 2) => {
+                    if let Some(target) = labels.get(&line_source.tokens[1]) {
+                        worklist.push((*target, next_depth));
+                    }
+                }
+                ("if-goto", 2) => {
+                    if let Some(target) = labels.get(&line_source.tokens[1]) {
+                        worklist.push((*target, next_depth));
+                    }
+                    worklist.push((i + 1, next_depth));
+                }
+                _ => worklist.push((i + 1, next_depth)),
+            }
+        }
+        self.functions.push(usage);
+    }
+
+    /// (values popped, values pushed) by a command
+    fn stack_effect(tokens: &[String]) -> (usize, usize) {
+        match tokens[0].as_str() {
+            "push" => (0, 1),
+            "pop" | "if-goto" => (1, 0),
+            "add" | "sub" | "and" | "or" | "eq" | "gt" | "lt" => (2, 1),
+            "mul" | "div" | "mod" | "shl" | "shr" => (2, 1),
+            "neg" | "not" => (1, 1),
+            "return" => (1, 0),
+            "call" => {
+                let nargs = tokens
+                    .get(2)
+                    .and_then(|nargs| nargs.parse::<usize>().ok())
+                    .unwrap_or(0);
+                (nargs, 1)
+            }
+            // label, goto, function, and inline asm, which can not be followed
+            // so it is assumed to leave SP where it found it
+            _ => (0, 0),
+        }
+    }
+
+    fn error(&mut self, file_name: &str, line: usize, msg: String) {
+        self.errors.push(ValidationError {
+            file_name: file_name.to_string(),
+            line,
+            msg,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyse(source: &str) -> StackAnalysis {
+        StackAnalysis::analyse(&[(String::from("Main"), source.to_string())])
+    }
+
+    #[test]
+    fn straight_line() {
+        let analysis =
+            analyse("function Main.main 2\npush constant 1\npush constant 2\nadd\nneg\nreturn");
+        assert!(analysis.errors.is_empty());
+        let usage = analysis.get("Main.main").unwrap();
+        assert_eq!(usage.max_depth, 2);
+        assert_eq!(usage.frame_size(), 4);
+    }
+    #[test]
+    fn math_commands() {
+        let analysis = analyse("function Main.main 0\npush constant 6\nmul\nreturn");
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(analysis.errors[0].line, 3);
+    }
+    #[test]
+    fn underflow() {
+        let analysis = analyse("function Main.main 0\npush constant 1\nadd\nreturn");
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(analysis.errors[0].line, 3);
+    }
+    #[test]
+    fn empty_return() {
+        let analysis = analyse("function Main.main 0\nreturn");
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(
+            analysis.errors[0].msg,
+            "return with an empty stack in Main.main"
+        );
+    }
+    #[test]
+    fn loop_consistent() {
+        let source = "function Main.main 1\nlabel LOOP\npush local 0\nnot\nif-goto END\npush local 0\npop local 0\ngoto LOOP\nlabel END\npush constant 0\nreturn";
+        let analysis = analyse(source);
+        assert!(analysis.errors.is_empty());
+        assert_eq!(analysis.get("Main.main").unwrap().max_depth, 1);
+    }
+    #[test]
+    fn loop_inconsistent() {
+        // Every loop iteration leaves a value on the stack
+        let source = "function Main.main 0\nlabel LOOP\npush constant 1\npush constant 1\nif-goto LOOP\nreturn";
+        let analysis = analyse(source);
+        assert_eq!(analysis.errors.len(), 1);
+        assert_eq!(analysis.errors[0].line, 2);
+    }
+    #[test]
+    fn unreachable_code_is_skipped() {
+        let analysis = analyse("function Main.main 0\npush constant 0\nreturn\nadd\nadd");
+        assert!(analysis.errors.is_empty());
+    }
+    #[test]
+    fn worst_case() {
+        let source = "function Main.main 1\npush constant 1\npush constant 2\ncall Main.add 2\nreturn\nfunction Main.add 3\npush argument 0\npush argument 1\nadd\nreturn";
+        let analysis = analyse(source);
+        assert!(analysis.errors.is_empty());
+        assert_eq!(analysis.worst_case("Main.add"), Some(5));
+        // 1 local + 2 args + frame + Main.add
+        assert_eq!(analysis.worst_case("Main.main"), Some(1 + 2 + 5 + 5));
+    }
+    #[test]
+    fn worst_case_recursion() {
+        let source = "function Main.f 0\npush argument 0\ncall Main.f 1\nreturn";
+        let analysis = analyse(source);
+        assert_eq!(analysis.worst_case("Main.f"), None);
+    }
+}
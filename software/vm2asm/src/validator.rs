@@ -0,0 +1,432 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use asm2hack::Instruction;
+
+use crate::{LineSource, MemorySegments, Parser};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub file_name: String,
+    pub line: usize,
+    pub msg: String,
+}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.vm, line {}: {}", self.file_name, self.line, self.msg)
+    }
+}
+
+struct FunctionInfo {
+    file_name: String,
+    line: usize,
+    // Highest argument i used + 1, the least amount of args a caller has to push
+    min_nargs: usize,
+}
+
+struct CallSite {
+    file_name: String,
+    line: usize,
+    function_name: String,
+    nargs: usize,
+}
+
+/// Labels and gotos of the function currently being validated
+#[derive(Default)]
+struct FunctionScope {
+    name: String,
+    labels: HashMap<String, usize>,
+    gotos: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ValidatorOptions {
+    // Functions declared outside of the files, like in a precompiled library
+    // Calls to them are only checked to exist
+    pub externals: HashSet<String>,
+    // Statements before the first function of a file are allowed, for programs without a bootstrap
+    pub top_level_code: bool,
+}
+
+/// Checks a whole VM program (every file that gets linked together) before any asm is generated
+/// Only looks at commands that are well formed, anything else is left for the Compiler to report
+///
+/// Reports
+/// - goto/if-goto to a label not declared in the same function
+/// - labels (VM or inline asm) or functions declared twice
+/// - statements outside of any function
+/// - pop constant
+/// - calls to functions that are not declared in any file
+/// - calls that push less args than the function uses, or disagree with other calls on the nargs
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<ValidationError>,
+    functions: HashMap<String, FunctionInfo>,
+    calls: Vec<CallSite>,
+    options: ValidatorOptions,
+}
+
+impl Validator {
+    /// files are (file_name, source) pairs, file_name being the file stem like the Compiler takes
+    pub fn validate(files: &[(String, String)]) -> Result<(), Vec<ValidationError>> {
+        Self::validate_with_options(files, &ValidatorOptions::default())
+    }
+    pub fn validate_with_options(
+        files: &[(String, String)],
+        options: &ValidatorOptions,
+    ) -> Result<(), Vec<ValidationError>> {
+        let mut validator = Self {
+            options: options.clone(),
+            ..Self::default()
+        };
+        for (file_name, source) in files {
+            validator.validate_file(file_name, Parser::parse(source.clone()));
+        }
+        validator.check_calls();
+        if validator.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(validator.errors)
+        }
+    }
+
+    fn validate_file(&mut self, file_name: &str, line_sources: Vec<LineSource>) {
+        // Top level code gets a scope without a name, like the Compiler gives its labels
+        let mut scope = if self.options.top_level_code {
+            Some(FunctionScope::default())
+        } else {
+            None
+        };
+        for line_source in line_sources {
+            let tokens = &line_source.tokens;
+            if tokens[0] == "function" {
+                if let Some(scope) = scope.take() {
+                    self.check_gotos(file_name, &scope);
+                }
+                scope = self.function(file_name, &line_source);
+                continue;
+            }
+            let current = match scope.as_mut() {
+                Some(current) => current,
+                None => {
+                    self.error(
+                        file_name,
+                        line_source.line,
+                        format!("{} is outside of any function", tokens.join(" ")),
+                    );
+                    continue;
+                }
+            };
+            match (tokens[0].as_str(), tokens.len()) {
+                ("label", 2) => {
+                    if let Some(first_line) = current.labels.get(&tokens[1]) {
+                        let msg = format!(
+                            "Label {} is declared twice in {}, first on line {}",
+                            tokens[1], current.name, first_line
+                        );
+                        self.error(file_name, line_source.line, msg);
+                    } else {
+                        current.labels.insert(tokens[1].clone(), line_source.line);
+                    }
+                }
+                // Labels of inline asm share the scope of the VM labels
+                ("asm", _) => {
+                    for instruction in &tokens[1..] {
+                        if let Ok(Instruction::Label(label)) = Instruction::parse(instruction) {
+                            if let Some(first_line) = current.labels.get(&label) {
+                                let msg = format!(
+                                    "Label {} is declared twice in {}, first on line {}",
+                                    label, current.name, first_line
+                                );
+                                self.error(file_name, line_source.line, msg);
+                            } else {
+                                current.labels.insert(label, line_source.line);
+                            }
+                        }
+                    }
+                }
+                ("goto" | "if-goto", 2) => {
+                    current.gotos.push((tokens[1].clone(), line_source.line));
+                }
+                ("call", 3) => {
+                    if let Ok(nargs) = tokens[2].parse::<usize>() {
+                        self.calls.push(CallSite {
+                            file_name: file_name.to_string(),
+                            line: line_source.line,
+                            function_name: tokens[1].clone(),
+                            nargs,
+                        });
+                    }
+                }
+                ("push" | "pop", 3) => {
+                    let i = tokens[2].parse::<usize>();
+                    match (MemorySegments::from_token(&tokens[1]), i) {
+                        (Ok(MemorySegments::Constant), _) if tokens[0] == "pop" => {
+                            self.error(
+                                file_name,
+                                line_source.line,
+                                String::from("Should not pop constant"),
+                            );
+                        }
+                        (Ok(MemorySegments::Argument), Ok(i)) => {
+                            if let Some(function) = self.functions.get_mut(&current.name) {
+                                function.min_nargs = function.min_nargs.max(i + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(scope) = scope {
+            self.check_gotos(file_name, &scope);
+        }
+    }
+
+    fn function(&mut self, file_name: &str, line_source: &LineSource) -> Option<FunctionScope> {
+        if line_source.tokens.len() != 3 {
+            return None;
+        }
+        let name = line_source.tokens[1].clone();
+        if let Some(existing) = self.functions.get(&name) {
+            let msg = format!(
+                "Function {} is declared twice, first in {}.vm on line {}",
+                name, existing.file_name, existing.line
+            );
+            self.error(file_name, line_source.line, msg);
+        } else {
+            self.functions.insert(
+                name.clone(),
+                FunctionInfo {
+                    file_name: file_name.to_string(),
+                    line: line_source.line,
+                    min_nargs: 0,
+                },
+            );
+        }
+        Some(FunctionScope {
+            name,
+            ..FunctionScope::default()
+        })
+    }
+
+    fn check_gotos(&mut self, file_name: &str, scope: &FunctionScope) {
+        for (label, line) in &scope.gotos {
+            if !scope.labels.contains_key(label) {
+                self.error(
+                    file_name,
+                    *line,
+                    format!("Label {} is not declared in {}", label, scope.name),
+                );
+            }
+        }
+    }
+
+    fn check_calls(&mut self) {
+        // First call site of each function, the others should agree with it
+        let mut first_calls: HashMap<String, (String, usize, usize)> = HashMap::new();
+        let mut errors = Vec::new();
+        let mut reported_undefined = HashSet::new();
+        for call in &self.calls {
+            let function = match self.functions.get(&call.function_name) {
+                Some(function) => function,
+                None if self.options.externals.contains(&call.function_name) => continue,
+                None => {
+                    // Only report each undefined function once per file
+                    if reported_undefined.insert((call.file_name.clone(), &call.function_name)) {
+                        errors.push(ValidationError {
+                            file_name: call.file_name.clone(),
+                            line: call.line,
+                            msg: format!(
+                                "Function {} is not declared in any file",
+                                call.function_name
+                            ),
+                        });
+                    }
+                    continue;
+                }
+            };
+            if call.nargs < function.min_nargs {
+                errors.push(ValidationError {
+                    file_name: call.file_name.clone(),
+                    line: call.line,
+                    msg: format!(
+                        "call {} {}, but {} uses argument {}",
+                        call.function_name,
+                        call.nargs,
+                        call.function_name,
+                        function.min_nargs - 1
+                    ),
+                });
+                continue;
+            }
+            match first_calls.get(&call.function_name) {
+                Some((first_file, first_line, first_nargs)) if *first_nargs != call.nargs => {
+                    errors.push(ValidationError {
+                        file_name: call.file_name.clone(),
+                        line: call.line,
+                        msg: format!(
+                            "call {} {}, but it is called with {} args in {}.vm on line {}",
+                            call.function_name, call.nargs, first_nargs, first_file, first_line
+                        ),
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    first_calls.insert(
+                        call.function_name.clone(),
+                        (call.file_name.clone(), call.line, call.nargs),
+                    );
+                }
+            }
+        }
+        self.errors.append(&mut errors);
+    }
+
+    fn error(&mut self, file_name: &str, line: usize, msg: String) {
+        self.errors.push(ValidationError {
+            file_name: file_name.to_string(),
+            line,
+            msg,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(files: &[(&str, &str)]) -> Result<(), Vec<ValidationError>> {
+        let files = files
+            .iter()
+            .map(|(file_name, source)| (file_name.to_string(), source.to_string()))
+            .collect::<Vec<(String, String)>>();
+        Validator::validate(&files)
+    }
+
+    #[test]
+    fn valid_program() {
+        let main = "function Main.main 1\npush constant 2\ncall Main.double 1\npop local 0\nlabel LOOP\npush local 0\nif-goto LOOP\npush constant 0\nreturn\nfunction Main.double 0\npush argument 0\npush argument 0\nadd\nreturn";
+        let sys = "function Sys.init 0\ncall Main.main 0\nlabel END\ngoto END";
+        assert_eq!(validate(&[("Main", main), ("Sys", sys)]), Ok(()));
+    }
+    #[test]
+    fn undefined_label() {
+        let source = "function Main.main 0\ngoto NOWHERE\nfunction Main.other 0\nlabel NOWHERE";
+        let errors = validate(&[("Main", source)]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+        assert_eq!(errors[0].msg, "Label NOWHERE is not declared in Main.main");
+    }
+    #[test]
+    fn duplicate_label() {
+        let source = "function Main.main 0\nlabel A\nlabel A";
+        let errors = validate(&[("Main", source)]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+    }
+    #[test]
+    fn inline_asm_labels() {
+        let source =
+            "function Main.main 0\nasm {\n(LOOP)\n@LOOP\n0;JMP\n}\ngoto LOOP\nasm \"(LOOP)\"";
+        let errors = validate(&[("Main", source)]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 8);
+    }
+    #[test]
+    fn duplicate_function() {
+        let errors = validate(&[
+            ("Main", "function Main.main 0"),
+            ("Other", "function Main.main 0"),
+        ])
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].file_name, "Other");
+    }
+    #[test]
+    fn statement_outside_function() {
+        let errors = validate(&[("Main", "push constant 1\nfunction Main.main 0")]).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 1);
+        assert_eq!(errors[0].msg, "push constant 1 is outside of any function");
+    }
+    #[test]
+    fn top_level_code() {
+        let files = [(
+            String::from("Main"),
+            String::from("push constant 1\nlabel LOOP\ngoto LOOP\nfunction Main.main 0\ngoto LOOP"),
+        )];
+        let options = ValidatorOptions {
+            top_level_code: true,
+            ..ValidatorOptions::default()
+        };
+        let errors = Validator::validate_with_options(&files, &options).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 5);
+    }
+    #[test]
+    fn pop_constant() {
+        let errors = validate(&[("Main", "function Main.main 0\npop constant 1")]).unwrap_err();
+        assert_eq!(errors[0].msg, "Should not pop constant");
+    }
+    #[test]
+    fn undefined_function() {
+        let errors = validate(&[(
+            "Main",
+            "function Main.main 0\ncall Output.printInt 1\ncall Output.printInt 1",
+        )])
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+    }
+    #[test]
+    fn external_function() {
+        let files = [(
+            String::from("Main"),
+            String::from("function Main.main 0\ncall Math.multiply 2"),
+        )];
+        assert!(Validator::validate(&files).is_err());
+        let options = ValidatorOptions {
+            externals: HashSet::from([String::from("Math.multiply")]),
+            ..ValidatorOptions::default()
+        };
+        assert_eq!(Validator::validate_with_options(&files, &options), Ok(()));
+    }
+    #[test]
+    fn call_arity_mismatch() {
+        let math = "function Math.max 0\npush argument 1\nreturn";
+        let errors = validate(&[
+            ("Main", "function Main.main 0\ncall Math.max 1"),
+            ("Math", math),
+        ])
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].msg,
+            "call Math.max 1, but Math.max uses argument 1"
+        );
+
+        let errors = validate(&[
+            (
+                "Main",
+                "function Main.main 0\ncall Math.max 2\ncall Math.max 3",
+            ),
+            ("Math", math),
+        ])
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 3);
+    }
+    #[test]
+    fn reports_all_errors() {
+        let errors = validate(&[(
+            "Main",
+            "add\nfunction Main.main 0\npop constant 0\ngoto A\ncall Nope.nope 0",
+        )])
+        .unwrap_err();
+        assert_eq!(errors.len(), 4);
+    }
+}
@@ -0,0 +1,7 @@
+//asm
+(Main.main$LOOP)
+@Main.main$LOOP
+M=M+1
+@SP
+@Main.main$END
+0;JMP
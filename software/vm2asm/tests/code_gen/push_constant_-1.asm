@@ -0,0 +1,7 @@
+//push constant -1
+@1
+D=-A // D = -1
+@SP
+AM=M+1 // *SP+1, SP++
+A=A-1
+M=D // *SP = -1
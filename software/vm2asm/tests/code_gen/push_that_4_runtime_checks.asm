@@ -0,0 +1,27 @@
+//push that 4
+@THAT
+D=M
+@4
+D=D+A // THAT + i
+@__SEGMENT_FAULT
+D;JLT
+@24576
+D=D-A // D - 24576
+@__SEGMENT_FAULT
+D;JGT
+@THAT
+D=M
+@4
+A=D+A
+D=M // D = *(THAT+4)
+@SP
+A=M
+M=D // *SP = D
+@SP
+M=M+1 // SP++
+@SP
+D=M
+@1000
+D=D-A // SP - 1000
+@__STACK_OVERFLOW
+D;JGT
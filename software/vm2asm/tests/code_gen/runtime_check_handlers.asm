@@ -0,0 +1,20 @@
+//runtime check handlers
+(__STACK_OVERFLOW)
+@1
+D=A
+@__RUNTIME_ERROR
+0;JMP
+(__SEGMENT_FAULT)
+@2
+D=A
+@__RUNTIME_ERROR
+0;JMP
+(__DIVIDE_BY_ZERO)
+@3
+D=A
+(__RUNTIME_ERROR)
+@16383
+M=D // error code
+(__RUNTIME_ERROR.halt)
+@__RUNTIME_ERROR.halt
+0;JMP
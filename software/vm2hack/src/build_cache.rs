@@ -0,0 +1,149 @@
+//! Cache of translated .vm files, kept in the build folder of a program
+//!
+//! ```text
+//! build/
+//!   cache          <file_name> <hash>, one line per .vm file
+//!   Main.asm       Translated asm, only written for reading
+//!   Main.hobj      ObjectFile of Main.vm
+//!   Main.lines     <line> <function_name> of every asm instruction, for the source map
+//! ```
+//!
+//! The hash covers the .vm source and the compiler options, so changing either retranslates the file
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use asm2hack::ObjectFile;
+use vm2asm::SourceLocation;
+
+const CACHE_FILE: &str = "cache";
+
+/// 64 bit FNV-1a
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub struct BuildCache {
+    dir: PathBuf,
+    hashes: HashMap<String, u64>,
+}
+
+impl BuildCache {
+    /// Reads the cache in dir, a missing or unreadable cache is empty
+    pub fn load(dir: PathBuf) -> Self {
+        let hashes = fs::read_to_string(dir.join(CACHE_FILE))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (file_name, hash) = line.split_once(' ')?;
+                Some((file_name.to_string(), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect();
+        Self { dir, hashes }
+    }
+
+    /// Object and locations of file_name, if it was translated from a source with the same hash
+    pub fn get(&self, file_name: &str, hash: u64) -> Option<(ObjectFile, Vec<SourceLocation>)> {
+        if self.hashes.get(file_name) != Some(&hash) {
+            return None;
+        }
+        let object = fs::read_to_string(self.path(file_name, ObjectFile::EXTENSION)).ok()?;
+        let object = ObjectFile::from_text(&object).ok()?;
+        let lines = fs::read_to_string(self.path(file_name, "lines")).ok()?;
+        let locations = lines
+            .lines()
+            .map(|line| {
+                let (line, function_name) = line.split_once(' ')?;
+                Some(SourceLocation {
+                    file_name: file_name.to_string(),
+                    line: line.parse().ok()?,
+                    function_name: function_name.to_string(),
+                })
+            })
+            .collect::<Option<Vec<SourceLocation>>>()?;
+        Some((object, locations))
+    }
+
+    pub fn insert(
+        &mut self,
+        file_name: &str,
+        hash: u64,
+        asm: &str,
+        object: &ObjectFile,
+        locations: &[SourceLocation],
+    ) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        let lines = locations
+            .iter()
+            .map(|location| format!("{} {}", location.line, location.function_name))
+            .collect::<Vec<String>>()
+            .join("\n");
+        fs::write(self.path(file_name, "asm"), asm).map_err(|e| e.to_string())?;
+        fs::write(
+            self.path(file_name, ObjectFile::EXTENSION),
+            object.to_text(),
+        )
+        .map_err(|e| e.to_string())?;
+        fs::write(self.path(file_name, "lines"), lines).map_err(|e| e.to_string())?;
+        self.hashes.insert(file_name.to_string(), hash);
+        Ok(())
+    }
+
+    /// Writes the cache, only keeping the entries of file_names
+    pub fn save(&mut self, file_names: &[String]) -> Result<(), String> {
+        self.hashes
+            .retain(|file_name, _| file_names.contains(file_name));
+        let mut entries = self
+            .hashes
+            .iter()
+            .map(|(file_name, hash)| format!("{} {:016x}", file_name, hash))
+            .collect::<Vec<String>>();
+        entries.sort();
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(CACHE_FILE), entries.join("\n")).map_err(|e| e.to_string())
+    }
+
+    fn path(&self, file_name: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", file_name, extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm2hack::Instruction;
+
+    #[test]
+    fn fnv_hash() {
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(hash(b"push constant 1"), hash(b"push constant 2"));
+    }
+    #[test]
+    fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("vm2hack-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let asm = [
+            Instruction::label("Main.main"),
+            Instruction::symbol("Main.0"),
+        ];
+        let object = ObjectFile::assemble("Main", &asm, &[String::from("Main.0")]).unwrap();
+        let locations = vec![SourceLocation {
+            file_name: String::from("Main"),
+            line: 2,
+            function_name: String::from("Main.main"),
+        }];
+
+        let mut cache = BuildCache::load(dir.clone());
+        assert!(cache.get("Main", 1).is_none());
+        cache.insert("Main", 1, "", &object, &locations).unwrap();
+        cache.insert("Old", 2, "", &object, &locations).unwrap();
+        cache.save(&[String::from("Main")]).unwrap();
+
+        let cache = BuildCache::load(dir.clone());
+        assert_eq!(cache.get("Main", 1), Some((object, locations)));
+        assert!(cache.get("Main", 2).is_none());
+        assert!(cache.get("Old", 2).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+mod build_cache;
+pub mod options;
+pub mod program;
+mod source_map;
+
+pub use options::{BootstrapMode, Options, DEFAULT_ENTRY, USAGE};
+pub use program::{compile_folder, compile_to_hack, compile_to_objects, Program};
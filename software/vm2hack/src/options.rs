@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use vm2asm::{CompilerOptions, RuntimeChecks};
+
+/// Flags of Options::from_args
+pub const USAGE: &str = "rust-vm2hack [--stack-report] [--clean] [--output folder] [--manifest file] [--entry function] [--bootstrap auto|always|never] [--sp n] [--lcl n] [--arg n] [--this n] [--that n] [--runtime-checks] [--math-commands] [--tail-calls] [--stack-limit n] [--error-address n] [--stack-base n] [--heap-start n] [--temp-base n] [--temp-size n] [--static-start n] [--static-end n] [path]";
+
+/// Entry function called by the bootstrap, unless --entry is given
+pub const DEFAULT_ENTRY: &str = "Sys.init";
+
+#[derive(Default)]
+pub enum BootstrapMode {
+    // Call the entry if it is defined, programs without Sys.init just start at the first file
+    #[default]
+    Auto,
+    Always,
+    // Only the pointers given are set, no entry is called
+    Never,
+}
+
+#[derive(Default)]
+pub struct Options {
+    pub folder: String,
+    pub output: Option<String>, // Folder for the .hack, source map and build cache, folder by default
+    pub name: Option<String>,   // Name of the .hack, the name of folder by default
+    pub stack_report: bool,
+    pub clean: bool, // Throw away the build cache, so every file is translated again
+    pub manifest: Option<String>, // Inputs in link order, see read_manifest
+    pub entry: Option<String>,
+    pub bootstrap: BootstrapMode,
+    pub pointers: Vec<(String, usize)>, // Initial values of SP, LCL, ARG, THIS and THAT
+    pub compiler: CompilerOptions,
+}
+impl Options {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut folder = None;
+        let mut runtime_checks = false;
+        // Defaults depend on the layout, so they are only filled in after every arg is read
+        let mut stack_limit = None;
+        let mut error_address = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let layout = &mut options.compiler.layout;
+            let value = match arg.as_str() {
+                "--stack-report" => {
+                    options.stack_report = true;
+                    continue;
+                }
+                "--output" => {
+                    match args.next() {
+                        Some(output) => options.output = Some(output.clone()),
+                        None => return Err(String::from("Expected a folder after --output")),
+                    }
+                    continue;
+                }
+                "--manifest" => {
+                    match args.next() {
+                        Some(manifest) => options.manifest = Some(manifest.clone()),
+                        None => return Err(String::from("Expected a file after --manifest")),
+                    }
+                    continue;
+                }
+                "--entry" => {
+                    match args.next() {
+                        Some(entry) => options.entry = Some(entry.clone()),
+                        None => return Err(String::from("Expected a function after --entry")),
+                    }
+                    continue;
+                }
+                "--bootstrap" => {
+                    options.bootstrap = match args.next().map(|mode| mode.as_str()) {
+                        Some("auto") => BootstrapMode::Auto,
+                        Some("always") => BootstrapMode::Always,
+                        Some("never") => BootstrapMode::Never,
+                        _ => {
+                            return Err(String::from(
+                                "Expected auto, always or never after --bootstrap",
+                            ))
+                        }
+                    };
+                    continue;
+                }
+                "--sp" | "--lcl" | "--arg" | "--this" | "--that" => {
+                    let pointer = arg[2..].to_uppercase();
+                    match args.next().map(|v| v.parse::<usize>()) {
+                        // The bootstrap sets it with an A-Instruction
+                        Some(Ok(value)) if value <= 32767 => {
+                            options.pointers.push((pointer, value))
+                        }
+                        _ => return Err(format!("Expected a number up to 32767 after {}", arg)),
+                    }
+                    continue;
+                }
+                "--clean" => {
+                    options.clean = true;
+                    continue;
+                }
+                "--runtime-checks" => {
+                    runtime_checks = true;
+                    continue;
+                }
+                "--math-commands" => {
+                    options.compiler.math_commands = true;
+                    continue;
+                }
+                "--tail-calls" => {
+                    options.compiler.tail_calls = true;
+                    continue;
+                }
+                "--stack-limit" => stack_limit.insert(0),
+                "--error-address" => error_address.insert(0),
+                "--stack-base" => &mut layout.stack_base,
+                "--heap-start" => &mut layout.heap_start,
+                "--temp-base" => &mut layout.temp_base,
+                "--temp-size" => &mut layout.temp_size,
+                "--static-start" => &mut layout.static_start,
+                "--static-end" => &mut layout.static_end,
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ if folder.is_none() => {
+                    folder = Some(arg.clone());
+                    continue;
+                }
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            };
+            *value = match args.next().map(|v| v.parse::<usize>()) {
+                Some(Ok(v)) => v,
+                _ => return Err(format!("Expected a number after {}", arg)),
+            };
+        }
+        options.compiler.layout.validate()?;
+        if runtime_checks {
+            let mut checks = RuntimeChecks::new(&options.compiler.layout);
+            checks.stack_limit = stack_limit.unwrap_or(checks.stack_limit);
+            checks.error_address = error_address.unwrap_or(checks.error_address);
+            options.compiler.runtime_checks = Some(checks);
+        } else if stack_limit.is_some() || error_address.is_some() {
+            return Err(String::from(
+                "--stack-limit and --error-address need --runtime-checks",
+            ));
+        }
+        match folder {
+            Some(folder) => {
+                options.folder = folder;
+                Ok(options)
+            }
+            None => Err(String::from("Expected a folder")),
+        }
+    }
+
+    pub fn output_folder(&self) -> PathBuf {
+        PathBuf::from(self.output.as_ref().unwrap_or(&self.folder))
+    }
+    pub fn program_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        // canonicalize gives . and .. a name
+        let folder = Path::new(&self.folder);
+        folder
+            .canonicalize()
+            .unwrap_or(folder.to_path_buf())
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("program")
+            .to_string()
+    }
+}
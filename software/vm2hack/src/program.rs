@@ -0,0 +1,693 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use asm2hack::{instruction, Comp, Instruction, Jump, Linker, ObjectFile};
+use vm2asm::{
+    CodeGen, Compiler, MemoryLayout, Parser, SourceLocation, StackAnalysis, ValidationError,
+    Validator, ValidatorOptions,
+};
+
+use crate::{
+    build_cache, build_cache::BuildCache, source_map::SourceMap, BootstrapMode, Options,
+    DEFAULT_ENTRY,
+};
+
+/// Folder inside the output folder for the intermediate files, see BuildCache
+const BUILD_FOLDER: &str = "build";
+
+fn join_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Stack usage of every function and of the whole program, one line each
+fn stack_report(analysis: &StackAnalysis, layout: &MemoryLayout) -> Vec<String> {
+    let mut report = vec![format!(
+        "Stack usage (words), stack region is {}-{}",
+        layout.stack_base,
+        layout.heap_start - 1
+    )];
+    for function in &analysis.functions {
+        let worst_case = match analysis.worst_case(&function.function_name) {
+            Some(worst_case) => worst_case.to_string(),
+            None => String::from("unbounded (recursive)"),
+        };
+        report.push(format!(
+            "{}: {} locals, max depth {}, frame {}, worst case {}",
+            function.function_name,
+            function.n_locals,
+            function.max_depth,
+            function.frame_size(),
+            worst_case
+        ));
+    }
+    if analysis.get("Sys.init").is_some() {
+        report.push(match analysis.worst_case("Sys.init") {
+            // The bootstrap call frame of Sys.init
+            Some(worst_case) => format!(
+                "Program worst case {} of {} words",
+                worst_case + CodeGen::CALL_FRAME_SIZE,
+                layout.stack_size()
+            ),
+            None => String::from("Program worst case is unbounded (recursive)"),
+        });
+    }
+    report
+}
+
+/// A single object that goes into the program, with the .vm source of each of its instructions
+struct LinkUnit {
+    object: ObjectFile,
+    // Indexed by instruction, like ObjectFile::source_lines, empty for precompiled objects
+    locations: Vec<SourceLocation>,
+}
+impl LinkUnit {
+    fn new(object: ObjectFile) -> Self {
+        Self {
+            object,
+            locations: Vec::default(),
+        }
+    }
+}
+
+/// An input file of the program
+enum Input {
+    Vm { file_name: String, source: String },
+    Object(ObjectFile),
+}
+
+/// Inputs of the program, in the order they are linked
+/// read_dir order depends on the filesystem, so it is never used as is
+fn read_inputs(file_path: &Path, manifest: Option<&String>) -> Result<Vec<Input>, String> {
+    let paths = match manifest {
+        Some(manifest) => read_manifest(file_path, manifest)?,
+        None => default_inputs(file_path)?,
+    };
+    paths.iter().map(|path| read_input(path)).collect()
+}
+
+/// Every .vm file sorted by name, then every precompiled object sorted by name
+/// Objects with a .vm file next to them are not precompiled, they are left overs of an older build
+fn default_inputs(file_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut vm_paths = Vec::default();
+    let mut object_paths = Vec::default();
+    for entry in fs::read_dir(file_path).map_err(|e| e.to_string())? {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        if !entry_path.is_file() {
+            // Skip folders
+            continue;
+        }
+        match entry_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("vm") => vm_paths.push(entry_path),
+            Some(ObjectFile::EXTENSION) if !entry_path.with_extension("vm").is_file() => {
+                object_paths.push(entry_path)
+            }
+            _ => {}
+        }
+    }
+    vm_paths.sort();
+    object_paths.sort();
+    vm_paths.append(&mut object_paths);
+    Ok(vm_paths)
+}
+
+/// A manifest lists the inputs in link order, one .vm or .hobj file per line
+/// Paths are relative to the program folder, // comments and empty lines are skipped
+fn read_manifest(file_path: &Path, manifest: &String) -> Result<Vec<PathBuf>, String> {
+    let text = fs::read_to_string(manifest).map_err(|e| format!("{}: {}", manifest, e))?;
+    let mut paths: Vec<PathBuf> = Vec::default();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |msg: &str| Err(format!("{}, line {}: {} {}", manifest, i + 1, line, msg));
+        let path = file_path.join(line);
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if !matches!(extension, Some("vm" | ObjectFile::EXTENSION)) {
+            return error("is not a .vm or .hobj file");
+        }
+        if !path.is_file() {
+            return error("does not exist");
+        }
+        if paths.contains(&path) {
+            return error("is listed twice");
+        }
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn read_input(path: &Path) -> Result<Input, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if path.extension().is_some_and(|extension| extension == "vm") {
+        let file_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        return Ok(Input::Vm {
+            file_name,
+            source: text,
+        });
+    }
+    ObjectFile::from_text(&text)
+        .map(Input::Object)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The function the bootstrap calls, None if it does not call one
+/// functions are the functions declared by the program
+fn entry_function(
+    options: &Options,
+    functions: &HashSet<String>,
+) -> Result<Option<String>, String> {
+    let entry = options.entry.as_deref().unwrap_or(DEFAULT_ENTRY);
+    let call_entry = match options.bootstrap {
+        BootstrapMode::Never => false,
+        // Only the default entry is optional
+        BootstrapMode::Auto if options.entry.is_none() => functions.contains(entry),
+        BootstrapMode::Auto | BootstrapMode::Always => true,
+    };
+    if call_entry && !functions.contains(entry) {
+        return Err(format!("Entry function {} is not defined", entry));
+    }
+    Ok(call_entry.then(|| entry.to_string()))
+}
+
+/// A program translated to objects, ready to be linked
+pub struct Program {
+    units: Vec<LinkUnit>, // In link order
+    entry: Option<String>,
+    pub rebuilt: Vec<String>, // .vm files translated again, the others came from the build cache
+    pub n_vm_files: usize,    // Every .vm input, rebuilt or not
+    pub stack_report: Vec<String>, // Lines of the stack report, empty unless Options::stack_report
+}
+impl Program {
+    /// Which .vm files were translated again, for the user
+    pub fn rebuilt_summary(&self) -> String {
+        if self.rebuilt.is_empty() {
+            format!("All {} files are up to date", self.n_vm_files)
+        } else {
+            format!(
+                "Rebuilt {} of {} files: {}",
+                self.rebuilt.len(),
+                self.n_vm_files,
+                self.rebuilt.join(", ")
+            )
+        }
+    }
+}
+
+/// Translates and assembles every input of the program folder, unchanged files come from the build cache
+pub fn compile_to_objects(options: &Options) -> Result<Program, String> {
+    let file_path = Path::new(&options.folder);
+    if !file_path.is_dir() {
+        return Err(String::from("Not a directory"));
+    }
+    let inputs = read_inputs(file_path, options.manifest.as_ref())?;
+    // (file_name, source) for every .vm file, so they can be validated together
+    let mut vm_files: Vec<(String, String)> = Vec::default();
+    // Every label of a precompiled object could be a function
+    let mut externals = HashSet::new();
+    let mut functions = HashSet::new();
+    for input in &inputs {
+        match input {
+            Input::Vm { file_name, source } => {
+                vm_files.push((file_name.clone(), source.clone()));
+                functions.extend(
+                    Parser::parse(source.clone())
+                        .into_iter()
+                        .filter(|line_source| line_source.tokens[0] == "function")
+                        .filter_map(|line_source| line_source.tokens.get(1).cloned()),
+                );
+            }
+            Input::Object(object) => {
+                externals.extend(object.labels.iter().map(|(label, _)| label.clone()))
+            }
+        }
+    }
+    functions.extend(externals.iter().cloned());
+    let entry = entry_function(options, &functions)?;
+
+    let validator_options = ValidatorOptions {
+        externals,
+        // Without an entry, the program starts at the top of the first file
+        top_level_code: entry.is_none(),
+    };
+    if let Err(errors) = Validator::validate_with_options(&vm_files, &validator_options) {
+        return Err(join_errors(&errors));
+    }
+    let analysis = StackAnalysis::analyse(&vm_files);
+    if !analysis.errors.is_empty() {
+        return Err(join_errors(&analysis.errors));
+    }
+    let stack_report = if options.stack_report {
+        stack_report(&analysis, &options.compiler.layout)
+    } else {
+        Vec::default()
+    };
+
+    let build_path = options.output_folder().join(BUILD_FOLDER);
+    if options.clean && build_path.is_dir() {
+        fs::remove_dir_all(&build_path).map_err(|e| e.to_string())?;
+    }
+    let mut cache = BuildCache::load(build_path);
+    let mut units = Vec::default();
+    let mut rebuilt = Vec::default();
+    for input in inputs {
+        let (file_name, source) = match input {
+            Input::Vm { file_name, source } => (file_name, source),
+            Input::Object(object) => {
+                units.push(LinkUnit::new(object));
+                continue;
+            }
+        };
+        let file_name = &file_name;
+        // Options change the asm as much as the source does
+        let hash = build_cache::hash(format!("{:?}\n{}", options.compiler, source).as_bytes());
+        if let Some((object, locations)) = cache.get(file_name, hash) {
+            units.push(LinkUnit { object, locations });
+            continue;
+        }
+        let output =
+            match Compiler::compile_with_options(source, file_name.clone(), &options.compiler) {
+                Some(output) => output,
+                None => return Err(format!("Failed to compile {}.vm", file_name)),
+            };
+        let statics = output
+            .statics
+            .iter()
+            .map(|i| CodeGen::static_symbol(file_name, *i))
+            .collect::<Vec<String>>();
+        let variables = output.asm_variables.into_iter().collect::<Vec<String>>();
+        let object =
+            ObjectFile::assemble_with_variables(file_name, &output.asm, &statics, &variables)
+                .map_err(|e| format!("Failed to assemble {}.asm, {}", file_name, e))?;
+        cache.insert(
+            file_name,
+            hash,
+            &instruction::render(&output.asm),
+            &object,
+            &output.locations,
+        )?;
+        units.push(LinkUnit {
+            object,
+            locations: output.locations,
+        });
+        rebuilt.push(format!("{}.vm", file_name));
+    }
+    let file_names = vm_files
+        .into_iter()
+        .map(|(file_name, _)| file_name)
+        .collect::<Vec<String>>();
+    cache.save(&file_names)?;
+    Ok(Program {
+        units,
+        entry,
+        rebuilt,
+        n_vm_files: file_names.len(),
+        stack_report,
+    })
+}
+
+/// Code that runs before the first file, sets the pointers and calls the entry function
+/// Empty if there is nothing to set and no entry to call
+fn bootstrap_object(options: &Options, entry: Option<&String>) -> Result<ObjectFile, String> {
+    let mut code_gen = CodeGen::new(options.compiler.layout.clone());
+    let mut bootstrap = Vec::default();
+    let sets_sp = options.pointers.iter().any(|(pointer, _)| pointer == "SP");
+    if entry.is_some() && !sets_sp {
+        bootstrap.append(&mut code_gen.bootstrap());
+    }
+    for (pointer, value) in &options.pointers {
+        bootstrap.append(&mut CodeGen::set_pointer(pointer, *value));
+    }
+    if let Some(entry) = entry {
+        bootstrap.append(&mut code_gen.call(&String::from("bootstrap"), entry, 0));
+        // Sys.init never returns, but another entry can
+        bootstrap.append(&mut halt_loop("__BOOTSTRAP.halt"));
+    }
+    ObjectFile::assemble("bootstrap", &bootstrap, &[])
+}
+
+/// Endless loop at label, where execution stops
+fn halt_loop(label: &str) -> Vec<Instruction> {
+    vec![
+        Instruction::label(label),
+        Instruction::symbol(label),
+        Instruction::jump(Comp::Zero, Jump::Jmp),
+    ]
+}
+
+/// Objects of the code vm2asm generates once per program, only linked in when referenced
+fn runtime_objects(options: &Options) -> Result<Vec<ObjectFile>, String> {
+    let mut code_gen = CodeGen::new(options.compiler.layout.clone());
+    if let Some(runtime_checks) = &options.compiler.runtime_checks {
+        code_gen = code_gen.with_runtime_checks(runtime_checks.clone());
+    }
+    let math_variables = CodeGen::MATH_VARIABLES.map(String::from);
+    let optional = vec![
+        ObjectFile::assemble("math routines", &code_gen.math_routines(), &math_variables)?,
+        // Empty without runtime checks
+        ObjectFile::assemble("runtime checks", &code_gen.runtime_check_handlers(), &[])?,
+    ];
+    Ok(optional)
+}
+
+/// True if any of objects references a label or static of object
+fn is_referenced(object: &ObjectFile, objects: &[&ObjectFile]) -> bool {
+    objects.iter().any(|other| {
+        other.references.iter().any(|(_, symbol)| {
+            object.labels.iter().any(|(label, _)| label == symbol)
+                || object.statics.contains(symbol)
+        })
+    })
+}
+
+/// Links the program and writes the .hack and source map to the output folder
+pub fn compile_to_hack(options: &Options, program: Program) -> Result<(), String> {
+    let output_folder = options.output_folder();
+    fs::create_dir_all(&output_folder).map_err(|e| e.to_string())?;
+    let program_name = options.program_name();
+    let hack_program_path = output_folder.join(format!("{}.hack", program_name));
+    let source_map_path = output_folder.join(format!("{}.map.json", program_name));
+    let Program { units, entry, .. } = program;
+
+    let mut objects = Vec::default();
+    let mut locations = vec![Vec::default()];
+    for unit in units {
+        objects.push(unit.object);
+        locations.push(unit.locations);
+    }
+    // The bootstrap always comes first
+    objects.insert(0, bootstrap_object(options, entry.as_ref())?);
+    let runtime = runtime_objects(options)?
+        .into_iter()
+        .filter(|object| is_referenced(object, &objects.iter().collect::<Vec<&ObjectFile>>()))
+        .collect::<Vec<ObjectFile>>();
+    if entry.is_none() && !runtime.is_empty() {
+        // Without an entry the last file runs off its end, which must not fall into the runtime code
+        objects.push(ObjectFile::assemble("halt", &halt_loop("__END.halt"), &[])?);
+    }
+    objects.extend(runtime);
+
+    let output =
+        Linker::link(&objects, options.compiler.layout.static_start).map_err(|errors| {
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        })?;
+    options.compiler.layout.check_statics(output.n_statics)?;
+    fs::write(
+        hack_program_path,
+        output
+            .rom
+            .iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n"),
+    )
+    .map_err(|e| e.to_string())?;
+    // Source location of each ROM word, bootstrap and runtime code have none
+    let rom_locations = output
+        .source_lines
+        .iter()
+        .map(|(object, line)| locations.get(*object).and_then(|l| l.get(*line)).cloned())
+        .collect::<Vec<Option<SourceLocation>>>();
+    fs::write(source_map_path, SourceMap::new(&rom_locations).to_json())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn compile_folder(options: &Options) -> Result<(), String> {
+    let program = compile_to_objects(options)?;
+    compile_to_hack(options, program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm2hack::emulator::Emulator;
+    use std::env;
+    use vm2asm::RuntimeChecks;
+
+    const FILES: [(&str, &str); 3] = [
+        ("Sys.vm", "function Sys.init 0\ncall Main.main 0\nlabel END\ngoto END"),
+        ("Main.vm", "function Main.main 0\npush constant 2\ncall Lib.twice 1\npop static 0\npush constant 0\nreturn"),
+        ("Lib.vm", "function Lib.twice 0\npush argument 0\npush argument 0\nadd\npop static 0\npush static 0\nreturn"),
+    ];
+
+    /// Writes files into a fresh program folder, in the order given, and builds it
+    /// options.manifest is the text of the manifest, not its path
+    fn build(name: &str, files: &[(&str, &str)], mut options: Options) -> Result<Vec<u8>, String> {
+        let folder = env::temp_dir()
+            .join(format!("vm2hack-{}-{}", name, std::process::id()))
+            .join("Prog");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        for (file_name, source) in files {
+            fs::write(folder.join(file_name), source).unwrap();
+        }
+        options.folder = folder.to_str().unwrap().to_string();
+        if let Some(manifest) = &options.manifest {
+            let manifest_path = folder.join("link.txt");
+            fs::write(&manifest_path, manifest).unwrap();
+            options.manifest = Some(manifest_path.to_str().unwrap().to_string());
+        }
+        let result = compile_folder(&options).map(|_| fs::read(folder.join("Prog.hack")).unwrap());
+        fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+        result
+    }
+    fn with_manifest(manifest: &str) -> Options {
+        Options {
+            manifest: Some(manifest.to_string()),
+            ..Options::default()
+        }
+    }
+    fn run(hack: &[u8]) -> Emulator {
+        let rom = String::from_utf8(hack.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| line.chars().collect::<Vec<char>>().try_into().unwrap())
+            .collect::<Vec<[char; 16]>>();
+        let mut emulator = Emulator::new(&rom);
+        emulator.run(10_000);
+        emulator
+    }
+
+    #[test]
+    fn same_inputs_same_output() {
+        let mut reversed = FILES;
+        reversed.reverse();
+        let hack = build("order-a", &FILES, Options::default()).unwrap();
+        assert_eq!(
+            hack,
+            build("order-b", &reversed, Options::default()).unwrap()
+        );
+        assert_eq!(hack, build("order-c", &FILES, Options::default()).unwrap());
+    }
+    #[test]
+    fn manifest_order() {
+        let sorted = build("manifest-a", &FILES, Options::default()).unwrap();
+        let manifest = "// Sorted order\nLib.vm\nMain.vm\n\nSys.vm";
+        assert_eq!(
+            build("manifest-b", &FILES, with_manifest(manifest)).unwrap(),
+            sorted
+        );
+        let manifest = "Sys.vm\nMain.vm\nLib.vm";
+        assert_ne!(
+            build("manifest-c", &FILES, with_manifest(manifest)).unwrap(),
+            sorted
+        );
+    }
+    #[test]
+    fn manifest_errors() {
+        let manifests = [
+            "Sys.vm\nSys.vm",
+            "Sys.vm\nOther.vm",
+            "Sys.asm",
+            "Sys.vm\nMain.vm",
+        ];
+        for (i, manifest) in manifests.into_iter().enumerate() {
+            let result = build(
+                &format!("manifest-error-{}", i),
+                &FILES,
+                with_manifest(manifest),
+            );
+            assert!(result.is_err(), "{}", manifest);
+        }
+    }
+    #[test]
+    fn entry_and_bootstrap() {
+        let hack = build("entry-default", &FILES, Options::default()).unwrap();
+        let emulator = run(&hack);
+        // Frame of Sys.init and the return value of Main.main
+        assert_eq!(emulator.ram[0], 262);
+        assert_eq!(emulator.ram[16], 4);
+
+        // No Sys.init, the program starts at the top of the first file
+        let files = [(
+            "Main.vm",
+            "push constant 7\npush constant 8\nadd\nlabel END\ngoto END",
+        )];
+        let options = Options {
+            pointers: vec![(String::from("SP"), 256)],
+            ..Options::default()
+        };
+        let emulator = run(&build("entry-none", &files, options).unwrap());
+        assert_eq!(emulator.ram[0], 257);
+        assert_eq!(emulator.ram[256], 15);
+
+        let options = Options {
+            entry: Some(String::from("Main.main")),
+            ..Options::default()
+        };
+        let files = [FILES[1], FILES[2]];
+        let emulator = run(&build("entry-main", &files, options).unwrap());
+        assert_eq!(emulator.ram[16], 4);
+    }
+    #[test]
+    fn no_entry_halts_before_runtime_code() {
+        let sp = || vec![(String::from("SP"), 256)];
+        let files = [("Main.vm", "push constant 3\npush constant 4\nmul")];
+        let mut options = Options {
+            pointers: sp(),
+            ..Options::default()
+        };
+        options.compiler.math_commands = true;
+        let emulator = run(&build("halt-math", &files, options).unwrap());
+        assert_eq!(emulator.ram[0], 257);
+        assert_eq!(emulator.ram[256], 12);
+
+        let files = [("Main.vm", "push constant 3\npush constant 4\nadd")];
+        let mut options = Options {
+            pointers: sp(),
+            ..Options::default()
+        };
+        options.compiler.runtime_checks = Some(RuntimeChecks::default());
+        let emulator = run(&build("halt-checks", &files, options).unwrap());
+        assert_eq!(emulator.ram[0], 257);
+        assert_eq!(emulator.ram[256], 7);
+        // No error code was written by __STACK_OVERFLOW
+        assert_eq!(emulator.ram[16383], 0);
+    }
+    #[test]
+    fn build_cache() {
+        let folder = env::temp_dir()
+            .join(format!("vm2hack-cache-{}", std::process::id()))
+            .join("Prog");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        for (file_name, source) in FILES {
+            fs::write(folder.join(file_name), source).unwrap();
+        }
+        let mut options = Options {
+            folder: folder.to_str().unwrap().to_string(),
+            ..Options::default()
+        };
+        let rebuilt = |options: &Options| {
+            let program = compile_to_objects(options).unwrap();
+            let rebuilt = program.rebuilt.clone();
+            compile_to_hack(options, program).unwrap();
+            rebuilt
+        };
+        assert_eq!(rebuilt(&options), ["Lib.vm", "Main.vm", "Sys.vm"]);
+        let hack = fs::read(folder.join("Prog.hack")).unwrap();
+        assert!(rebuilt(&options).is_empty());
+        let program = compile_to_objects(&options).unwrap();
+        assert_eq!(program.rebuilt_summary(), "All 3 files are up to date");
+        assert!(program.stack_report.is_empty());
+        options.stack_report = true;
+        let program = compile_to_objects(&options).unwrap();
+        // Heading, a line per function and the program worst case
+        assert_eq!(program.stack_report.len(), 5);
+        assert_eq!(
+            program.stack_report[4],
+            "Program worst case 18 of 1792 words"
+        );
+        options.stack_report = false;
+        assert_eq!(fs::read(folder.join("Prog.hack")).unwrap(), hack);
+
+        let main = FILES[1].1.replace("push constant 2", "push constant 3");
+        fs::write(folder.join("Main.vm"), &main).unwrap();
+        assert_eq!(rebuilt(&options), ["Main.vm"]);
+        assert_eq!(run(&fs::read(folder.join("Prog.hack")).unwrap()).ram[16], 6);
+
+        let build_path = folder.join(BUILD_FOLDER);
+        let cache = fs::read_to_string(build_path.join("cache")).unwrap();
+        let cached = cache
+            .lines()
+            .map(|line| line.split_once(' ').unwrap())
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            cached
+                .iter()
+                .map(|(file_name, _)| *file_name)
+                .collect::<Vec<&str>>(),
+            ["Lib", "Main", "Sys"]
+        );
+        let main_hash = build_cache::hash(format!("{:?}\n{}", options.compiler, main).as_bytes());
+        assert_eq!(cached[1].1, format!("{:016x}", main_hash));
+        for extension in ["asm", ObjectFile::EXTENSION, "lines"] {
+            assert!(build_path.join(format!("Main.{}", extension)).is_file());
+        }
+        // The asm only goes into the build folder
+        let asm_files = fs::read_dir(&folder)
+            .unwrap()
+            .filter(|entry| {
+                let path = entry.as_ref().unwrap().path();
+                path.extension().is_some_and(|extension| extension == "asm")
+            })
+            .count();
+        assert_eq!(asm_files, 0);
+
+        options.compiler.tail_calls = true;
+        assert_eq!(rebuilt(&options).len(), 3);
+        assert!(rebuilt(&options).is_empty());
+        options.clean = true;
+        assert_eq!(rebuilt(&options).len(), 3);
+        fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+    }
+    #[test]
+    fn inline_asm_variables() {
+        let files = [
+            (
+                "Sys.vm",
+                "function Sys.init 0\nasm {\n@counter\nM=M+1\n}\ncall Main.main 0\nlabel END\ngoto END",
+            ),
+            (
+                "Main.vm",
+                "function Main.main 0\npush constant 5\npop static 0\nasm \"@counter\"\nasm \"M=M+1\"\npush constant 0\nreturn",
+            ),
+        ];
+        let emulator = run(&build("asm-variables", &files, Options::default()).unwrap());
+        // counter gets the slot after the statics, shared by both files
+        assert_eq!(emulator.ram[16], 5);
+        assert_eq!(emulator.ram[17], 2);
+    }
+    #[test]
+    fn entry_errors() {
+        let files = [FILES[1], FILES[2]];
+        let options = Options {
+            bootstrap: BootstrapMode::Always,
+            ..Options::default()
+        };
+        assert!(build("entry-always", &files, options).is_err());
+        let options = Options {
+            entry: Some(String::from("Main.other")),
+            ..Options::default()
+        };
+        assert!(build("entry-missing", &FILES, options).is_err());
+        // Top level code is only allowed without an entry
+        let files = [FILES[0], ("Main.vm", "push constant 1")];
+        assert!(build("entry-top-level", &files, Options::default()).is_err());
+    }
+}
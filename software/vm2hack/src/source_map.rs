@@ -0,0 +1,188 @@
+//! Source map from Hack ROM addresses back to the .vm files
+//!
+//! Written as `<program>.map.json` next to the `.hack` file
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "rom_size": 120,
+//!   "functions": [
+//!     {"name": "Main.main", "file": "Main.vm", "start": 48, "end": 90}
+//!   ],
+//!   "entries": [
+//!     {"start": 48, "end": 50, "file": "Main.vm", "line": 1, "function": "Main.main"},
+//!     {"start": 50, "end": 56, "file": "Main.vm", "line": 2, "function": "Main.main"}
+//!   ]
+//! }
+//! ```
+//!
+//! - `start` is inclusive and `end` is exclusive, both are ROM addresses
+//! - `line` starts from 1, like the line numbers in error messages
+//! - `entries` are sorted by `start`, with consecutive addresses of the same vm line merged
+//! - Addresses without a vm line (bootstrap code) have no entry
+//! - `functions` gives the ROM range of each function, from its first to its last instruction
+use vm2asm::SourceLocation;
+
+const VERSION: usize = 1;
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    start: usize,
+    end: usize,
+    location: SourceLocation,
+}
+
+#[derive(Debug, PartialEq)]
+struct FunctionRange {
+    name: String,
+    file_name: String,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SourceMap {
+    rom_size: usize,
+    entries: Vec<Entry>,
+    functions: Vec<FunctionRange>,
+}
+
+impl SourceMap {
+    /// locations has the SourceLocation of each ROM address, None for addresses without one
+    pub fn new(locations: &[Option<SourceLocation>]) -> Self {
+        let mut entries: Vec<Entry> = Vec::default();
+        let mut functions: Vec<FunctionRange> = Vec::default();
+        for (address, location) in locations.iter().enumerate() {
+            let location = match location {
+                Some(location) => location,
+                None => continue,
+            };
+            match entries.last_mut() {
+                Some(entry) if entry.end == address && &entry.location == location => {
+                    entry.end += 1;
+                }
+                _ => entries.push(Entry {
+                    start: address,
+                    end: address + 1,
+                    location: location.clone(),
+                }),
+            }
+            if location.function_name.is_empty() {
+                continue;
+            }
+            match functions.last_mut() {
+                Some(function) if function.name == location.function_name => {
+                    function.end = address + 1;
+                }
+                _ => functions.push(FunctionRange {
+                    name: location.function_name.clone(),
+                    file_name: location.file_name.clone(),
+                    start: address,
+                    end: address + 1,
+                }),
+            }
+        }
+        Self {
+            rom_size: locations.len(),
+            entries,
+            functions,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let functions = self
+            .functions
+            .iter()
+            .map(|function| {
+                format!(
+                    "    {{\"name\": {}, \"file\": {}, \"start\": {}, \"end\": {}}}",
+                    json_string(&function.name),
+                    json_string(&format!("{}.vm", function.file_name)),
+                    function.start,
+                    function.end
+                )
+            })
+            .collect::<Vec<String>>();
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "    {{\"start\": {}, \"end\": {}, \"file\": {}, \"line\": {}, \"function\": {}}}",
+                    entry.start,
+                    entry.end,
+                    json_string(&format!("{}.vm", entry.location.file_name)),
+                    entry.location.line,
+                    json_string(&entry.location.function_name)
+                )
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "{{\n  \"version\": {},\n  \"rom_size\": {},\n  \"functions\": [\n{}\n  ],\n  \"entries\": [\n{}\n  ]\n}}\n",
+            VERSION,
+            self.rom_size,
+            functions.join(",\n"),
+            entries.join(",\n")
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(line: usize, function_name: &str) -> Option<SourceLocation> {
+        Some(SourceLocation {
+            file_name: String::from("Main"),
+            line,
+            function_name: function_name.to_string(),
+        })
+    }
+
+    #[test]
+    fn merges_entries() {
+        let locations = vec![
+            None,
+            None,
+            location(1, "Main.main"),
+            location(2, "Main.main"),
+            location(2, "Main.main"),
+            location(3, "Main.f"),
+        ];
+        let source_map = SourceMap::new(&locations);
+        assert_eq!(source_map.rom_size, 6);
+        assert_eq!(source_map.entries.len(), 3);
+        assert_eq!(source_map.entries[1].start, 3);
+        assert_eq!(source_map.entries[1].end, 5);
+        assert_eq!(source_map.functions.len(), 2);
+        assert_eq!(source_map.functions[0].start, 2);
+        assert_eq!(source_map.functions[0].end, 5);
+    }
+    #[test]
+    fn to_json() {
+        let source_map = SourceMap::new(&[None, location(4, "Main.main")]);
+        assert_eq!(
+            source_map.to_json(),
+            "{\n  \"version\": 1,\n  \"rom_size\": 2,\n  \"functions\": [\n    {\"name\": \"Main.main\", \"file\": \"Main.vm\", \"start\": 1, \"end\": 2}\n  ],\n  \"entries\": [\n    {\"start\": 1, \"end\": 2, \"file\": \"Main.vm\", \"line\": 4, \"function\": \"Main.main\"}\n  ]\n}\n"
+        );
+    }
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}